@@ -2,15 +2,84 @@ use bevy::prelude::*;
 use naia_bevy_client::events::InsertComponentEvent;
 
 use rgj_shared::{
-    behavior::HEXAGON_SIZE,
+    behavior::{AxialCoordinates, HEXAGON_HEIGHT, HEXAGON_SIZE, HEXAGON_WIDTH},
     components::{genome::Hybrid, players::PlayerId},
-    protocol::{ProtocolKind, UnitSync},
+    protocol::{
+        game_sync::map_sync::{TileStructure, TileType},
+        ProtocolKind, UnitSync,
+    },
 };
 
-use crate::{game::resources::Map, UnitSprites};
+use crate::{game::resources::Map, TileSprites, UnitSprites};
 
 pub mod chat;
 
+/// Where the tile at `position` on `layer` is drawn. The sky layer is drawn beneath the ground.
+pub fn tile_transform(position: AxialCoordinates, layer: i32) -> Transform {
+    let world = position.to_world();
+
+    Transform::from_xyz(world.x, world.y, layer as f32 * -1.0)
+}
+
+/// What a tile the player has seen before but can't currently see is tinted with, so that
+/// remembered terrain is told apart from what is in view
+const REMEMBERED_TINT: Color = Color::rgb(0.5, 0.5, 0.5);
+
+/// The color a tile's sprite is drawn with, darker when it is only remembered
+pub fn tile_tint(remembered: bool) -> Color {
+    if remembered {
+        REMEMBERED_TINT
+    } else {
+        Color::WHITE
+    }
+}
+
+/// The sprite for a single hex of the map
+pub fn tile_sprite(
+    position: AxialCoordinates,
+    layer: i32,
+    tile: TileType,
+    sprites: &TileSprites,
+) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            custom_size: Some(Vec2::new(*HEXAGON_WIDTH, HEXAGON_HEIGHT)),
+            ..Default::default()
+        },
+        transform: tile_transform(position, layer),
+        texture: sprites.for_tile(tile).clone(),
+        ..Default::default()
+    }
+}
+
+/// The sprite for a structure standing on the tile drawn with `tile_transform`
+pub fn structure_sprite(structure: &TileStructure, tile_transform: Transform) -> SpriteBundle {
+    let mut transform = tile_transform;
+    transform.translation.z += 0.1;
+
+    SpriteBundle {
+        sprite: Sprite {
+            color: structure.into(),
+            custom_size: Some(Vec2::new(65.0, 65.0)),
+            ..Default::default()
+        },
+        transform,
+        ..Default::default()
+    }
+}
+
+/// Where a unit is drawn. Flying units are drawn above and slightly up from the hex they are on so
+/// that they don't hide any ground unit sharing it.
+pub fn unit_transform(position: AxialCoordinates, layer: i32) -> Transform {
+    let world = position.to_world();
+
+    if layer > 0 {
+        Transform::from_xyz(world.x, world.y + HEXAGON_SIZE / 3.0, 0.95)
+    } else {
+        Transform::from_xyz(world.x, world.y, 0.9)
+    }
+}
+
 fn insert_unit(
     commands: &mut Commands,
     transform: Transform,
@@ -128,15 +197,7 @@ pub fn insert_unit_sync_event(
     for event in event_reader.iter() {
         if let InsertComponentEvent(entity, ProtocolKind::UnitSync) = event {
             if let Ok(unit_sync) = query.get(*entity) {
-                let q = unit_sync.position.column_q;
-                let r = unit_sync.position.row_r;
-                let z = *unit_sync.layer as i32;
-
-                let transform = Transform::from_xyz(
-                    HEXAGON_SIZE * (q as f32 * f32::sqrt(3.0) + (f32::sqrt(3.0) / 2.0 * r as f32)),
-                    HEXAGON_SIZE * (r as f32 * 3.0 / 2.0),
-                    z as f32 * -1.0 + 0.9,
-                );
+                let transform = unit_transform(*unit_sync.position, *unit_sync.layer);
 
                 insert_unit(
                     &mut commands,
@@ -147,7 +208,7 @@ pub fn insert_unit_sync_event(
                     &unit_sprites,
                 );
 
-                map.coords_to_unit.insert((q, r, z), *entity);
+                map.insert_unit(*entity, *unit_sync.position, *unit_sync.layer);
             }
         }
     }
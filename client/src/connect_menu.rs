@@ -14,7 +14,10 @@ pub struct UiState {
     socket_addr_s: String,
 
     username: String,
+    room_name: String,
     password: String,
+    /// Only filled in when reconnecting to a game that has already started
+    reconnect_code: String,
 }
 
 pub fn connect_menu_init(mut commands: Commands) {
@@ -32,6 +35,7 @@ pub fn connect_menu(
     assets: Res<AssetServer>,
 ) {
     let mut clicked = false;
+    let mut editor_clicked = false;
 
     egui::Window::new("Connect").show(egui_context.ctx_mut(), |ui| {
         ui.horizontal(|ui| {
@@ -44,28 +48,63 @@ pub fn connect_menu(
             ui.text_edit_singleline(&mut ui_state.username);
         });
 
+        ui.horizontal(|ui| {
+            ui.label("Room");
+            ui.text_edit_singleline(&mut ui_state.room_name);
+        });
+
         ui.horizontal(|ui| {
             ui.label("Room Password");
             ui.text_edit_singleline(&mut ui_state.password);
         });
 
-        clicked = ui.button("Connect").clicked();
+        ui.horizontal(|ui| {
+            ui.label("Reconnect Code");
+            ui.text_edit_singleline(&mut ui_state.reconnect_code);
+        });
+
+        ui.horizontal(|ui| {
+            clicked = ui.button("Connect").clicked();
+            editor_clicked = ui.button("Map Editor").clicked();
+        });
 
         ui.label(&ui_state.error_msg);
     });
 
+    if editor_clicked {
+        commands.insert_resource(NextState(GameState::MapEditor));
+        return;
+    }
+
     if clicked {
         let socket_addr = SocketAddr::from_str(&ui_state.socket_addr_s);
         if ui_state.username.is_empty() {
             ui_state.error_msg = "You must enter a username".to_owned();
         }
+        if ui_state.room_name.is_empty() {
+            ui_state.error_msg = "You must enter the room to join".to_owned();
+            return;
+        }
+
+        let reconnect_token = match ui_state.reconnect_code.trim() {
+            "" => None,
+            code => match u64::from_str_radix(code, 16) {
+                Ok(token) => Some(token),
+                Err(_) => {
+                    ui_state.error_msg = "The reconnect code is not valid".to_owned();
+                    return;
+                }
+            },
+        };
 
         match socket_addr {
             Ok(socket_addr) => {
                 commands.insert_resource(ConnectionInformation {
                     socket_addr: Some(socket_addr),
                     username: ui_state.username.clone(),
+                    room_name: ui_state.room_name.clone(),
                     room_password: ui_state.password.clone(),
+                    reconnect_token,
                 });
                 commands.insert_resource(NextState(GameState::WaitingForMoreConnectionsMenu));
 
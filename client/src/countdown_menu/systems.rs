@@ -7,25 +7,29 @@ use naia_bevy_client::{
 };
 
 use rgj_shared::{
-    behavior::{HEXAGON_HEIGHT, HEXAGON_SIZE, HEXAGON_WIDTH},
     components::{
-        genome::{Hybrid, DEER},
+        genome::{AnimalType, Hybrid, DEER},
         players::PlayerId,
     },
     protocol::{
-        game_sync::map_sync::{MapSync, TileStructure, TileType},
-        ClientKeepAlive, Protocol, ProtocolKind, UnitSync,
+        game_sync::map_sync::{MapSync, TileStructure, MAP_HEIGHT},
+        notifications::WhoseTurn,
+        ClientKeepAlive, MapChunkSync, Protocol, ProtocolKind, UnitSync,
     },
     Channels,
 };
 
 use super::resources::SecondsLeft;
 use crate::{
+    common_systems::{structure_sprite, tile_sprite, tile_tint},
     game::{
         components::TileWithBuilding,
-        resources::{Map, TileSelectedState, TurnTracker, UnlockedGenomes},
+        resources::{
+            CombatLog, GameSeed, HoveredTile, Map, PathPreview, TileSelectedState, TurnClock,
+            TurnTracker, UnlockedGenomes,
+        },
     },
-    GameState, TileSprites,
+    GameState, ReconnectCode, TileSprites,
 };
 
 pub fn init(mut commands: Commands) {
@@ -38,78 +42,45 @@ pub fn spawn_entity_event(mut event_reader: EventReader<SpawnEntityEvent>) {
     }
 }
 
-pub fn insert_map_sync_event(
+/// Unpacks each chunk of the map the server sends into an entity for every tile
+pub fn insert_map_chunk_event(
     mut event_reader: EventReader<InsertComponentEvent<ProtocolKind>>,
     mut commands: Commands,
 
-    query: Query<&MapSync>,
+    query: Query<&MapChunkSync>,
 
     mut map: ResMut<Map>,
     assets: Res<TileSprites>,
 ) {
     for event in event_reader.iter() {
-        if let InsertComponentEvent(entity, ProtocolKind::MapSync) = event {
-            if let Ok(map_sync) = query.get(*entity) {
-                let q = map_sync.position.column_q;
-                let r = map_sync.position.row_r;
-                let z = *map_sync.layer;
-
-                let mut transform = Transform::from_xyz(
-                    HEXAGON_SIZE * (q as f32 * f32::sqrt(3.0) + (f32::sqrt(3.0) / 2.0 * r as f32)),
-                    HEXAGON_SIZE * (r as f32 * 3.0 / 2.0),
-                    z as f32 * -1.0,
-                );
-
-                let texture = match *map_sync.tile_type {
-                    TileType::Fog => &assets.fog,
-
-                    TileType::Grass => &assets.grass,
-                    TileType::Forest => &assets.forest,
-                    TileType::Desert => &assets.desert,
-
-                    TileType::Ocean => &assets.ocean,
-                    // FIXME: River should be river
-                    TileType::River => &assets.ocean,
-                    TileType::DesertOasis => &assets.oasis,
-
-                    TileType::ClearSky => &assets.clear_sky,
-                    TileType::WindySky => &assets.windy_sky,
-                    TileType::StormySky => &assets.stormy_sky,
-                };
-
-                commands.entity(*entity).insert_bundle(SpriteBundle {
-                    sprite: Sprite {
-                        custom_size: Some(Vec2::new(*HEXAGON_WIDTH, HEXAGON_HEIGHT)),
-                        ..Default::default()
-                    },
-                    transform,
-                    texture: texture.clone(),
-                    ..Default::default()
-                });
-
-                // Insert the building if there is one
-                if *map_sync.structure != TileStructure::None {
-                    let color: Color = (&*map_sync.structure).into();
-                    transform.translation.z += 0.1;
-
-                    let structure_entity = commands
-                        .spawn_bundle(SpriteBundle {
-                            sprite: Sprite {
-                                color,
-                                custom_size: Some(Vec2::new(65.0, 65.0)),
-                                ..Default::default()
-                            },
-                            transform,
-                            ..Default::default()
-                        })
-                        .id();
-
-                    commands
-                        .entity(*entity)
-                        .insert(TileWithBuilding { structure_entity });
+        if let InsertComponentEvent(entity, ProtocolKind::MapChunkSync) = event {
+            if let Ok(chunk) = query.get(*entity) {
+                for z in 0..MAP_HEIGHT {
+                    for qr in chunk.bounds().hexes() {
+                        let map_sync =
+                            MapSync::new_complete(qr, z, chunk.tile(qr, z), chunk.structure(qr, z));
+
+                        let mut tile = tile_sprite(qr, z, *map_sync.tile_type, &assets);
+                        tile.sprite.color = tile_tint(chunk.is_remembered(qr, z));
+                        let transform = tile.transform;
+
+                        let mut tile_entity = commands.spawn_bundle(tile);
+
+                        // Insert the building if there is one
+                        if *map_sync.structure != TileStructure::None {
+                            let structure_entity = tile_entity
+                                .commands()
+                                .spawn_bundle(structure_sprite(&map_sync.structure, transform))
+                                .id();
+
+                            tile_entity.insert(TileWithBuilding { structure_entity });
+                        }
+
+                        let tile_entity = tile_entity.insert(map_sync).id();
+                        map.coords_to_tile
+                            .insert((qr.column_q, qr.row_r, z), tile_entity);
+                    }
                 }
-
-                map.coords_to_tile.insert((q, r, z), *entity);
             }
         }
     }
@@ -134,18 +105,62 @@ pub fn receive_game_start_notification(
         if let MessageEvent(Channels::GameNotification, Protocol::GameStartNotification(gsn)) =
             event
         {
-            commands.insert_resource(TurnTracker::new(&gsn.whose_turn));
-            commands.insert_resource(TileSelectedState::default());
-            commands.insert_resource(NextState(GameState::Game));
-            commands.insert_resource(UnlockedGenomes(vec![DEER.clone()]));
+            info!("Game seed is {}", *gsn.seed);
+
+            start_game(
+                &mut commands,
+                &gsn.whose_turn,
+                *gsn.seed,
+                vec![DEER.clone()],
+            );
         }
     }
 }
 
-pub fn countdown_menu(seconds_left: Res<SecondsLeft>, mut egui_context: ResMut<EguiContext>) {
+/// Inserts everything the game state needs and moves into it
+pub fn start_game(
+    commands: &mut Commands,
+    whose_turn: &WhoseTurn,
+    seed: u64,
+    unlocked_genomes: Vec<AnimalType>,
+) {
+    commands.insert_resource(TurnTracker::new(whose_turn));
+    commands.insert_resource(GameSeed(seed));
+    commands.insert_resource(TileSelectedState::default());
+    commands.insert_resource(HoveredTile::default());
+    commands.insert_resource(PathPreview::default());
+    commands.insert_resource(CombatLog::default());
+    commands.insert_resource(TurnClock::default());
+    commands.insert_resource(NextState(GameState::Game));
+    commands.insert_resource(UnlockedGenomes(unlocked_genomes));
+}
+
+/// Keeps the token which lets this player take their seat back if they lose their connection
+pub fn receive_reconnect_token(
+    mut event_reader: EventReader<MessageEvent<Protocol, Channels>>,
+    mut commands: Commands,
+) {
+    for event in event_reader.iter() {
+        if let MessageEvent(Channels::GameNotification, Protocol::ReconnectToken(rt)) = event {
+            commands.insert_resource(ReconnectCode(*rt.token));
+        }
+    }
+}
+
+pub fn countdown_menu(
+    seconds_left: Res<SecondsLeft>,
+    reconnect_code: Option<Res<ReconnectCode>>,
+    mut egui_context: ResMut<EguiContext>,
+) {
     let label = format!("Starting in {} seconds", seconds_left.0);
 
-    egui::Window::new("Countdown").show(egui_context.ctx_mut(), |ui| ui.label(label));
+    egui::Window::new("Countdown").show(egui_context.ctx_mut(), |ui| {
+        ui.label(label);
+
+        if let Some(code) = reconnect_code {
+            ui.label(format!("Reconnect code: {:016x}", code.0));
+        }
+    });
 }
 
 pub fn tick(mut client: Client<Protocol, Channels>) {
@@ -4,3 +4,7 @@ use bevy::prelude::*;
 pub struct TileWithBuilding {
     pub structure_entity: Entity,
 }
+
+/// Marks the sprites drawn along a previewed movement route
+#[derive(Component)]
+pub struct PathPreviewMarker;
@@ -1,10 +1,13 @@
 use std::collections::HashMap;
 
-use bevy::prelude::Entity;
+use bevy::prelude::{Entity, Query};
 use rgj_shared::{
-    behavior::AxialCoordinates,
+    behavior::{pathfinding::Path, AxialCoordinates},
     components::genome::AnimalType,
-    protocol::{notifications::WhoseTurn, player_input::PlayerInputVariant, MapSync, UnitSync},
+    protocol::{
+        game_sync::map_sync::TileType, notifications::WhoseTurn, player_input::PlayerInputVariant,
+        MapSync,
+    },
 };
 
 pub struct TileSelectedEvent(pub AxialCoordinates);
@@ -13,6 +16,7 @@ pub struct TileSelectedEvent(pub AxialCoordinates);
 pub struct TileSelectedState {
     pub error: String,
     pub moving_unit: Option<Entity>,
+    pub attacking_unit: Option<Entity>,
 
     pub build_screen: bool,
     pub head: Option<AnimalType>,
@@ -20,6 +24,8 @@ pub struct TileSelectedState {
     pub limbs: Option<AnimalType>,
 
     pub tile: Option<AxialCoordinates>,
+    /// The layer of the selected tile that is being shown
+    pub layer: i32,
 }
 
 pub struct TurnTracker {
@@ -39,6 +45,70 @@ impl TurnTracker {
 pub struct Map {
     pub coords_to_tile: HashMap<(i32, i32, i32), Entity>,
     pub coords_to_unit: HashMap<(i32, i32, i32), Entity>,
+    /// The reverse of `coords_to_unit`, so that a unit can be found again after it moves
+    pub unit_to_coords: HashMap<Entity, (i32, i32, i32)>,
+}
+
+impl Map {
+    /// The [`TileType`] of a tile on the given layer as this client currently perceives it, or
+    /// [`None`] if the tile does not exist
+    pub fn tile_type(
+        &self,
+        query: &Query<&MapSync>,
+        qr: AxialCoordinates,
+        layer: i32,
+    ) -> Option<TileType> {
+        self.coords_to_tile
+            .get(&(qr.column_q, qr.row_r, layer))
+            .and_then(|entity| query.get(*entity).ok())
+            .map(|tile| *tile.tile_type)
+    }
+
+    pub fn unit_at(&self, qr: AxialCoordinates, layer: i32) -> Option<Entity> {
+        self.coords_to_unit
+            .get(&(qr.column_q, qr.row_r, layer))
+            .copied()
+    }
+
+    /// Records the unit as being on the given hex and layer, forgetting wherever it was before
+    pub fn insert_unit(&mut self, entity: Entity, qr: AxialCoordinates, layer: i32) {
+        self.remove_unit(entity);
+
+        let coords = (qr.column_q, qr.row_r, layer);
+        self.coords_to_unit.insert(coords, entity);
+        self.unit_to_coords.insert(entity, coords);
+    }
+
+    pub fn remove_unit(&mut self, entity: Entity) {
+        if let Some(coords) = self.unit_to_coords.remove(&entity) {
+            if self.coords_to_unit.get(&coords) == Some(&entity) {
+                self.coords_to_unit.remove(&coords);
+            }
+        }
+    }
+}
+
+/// The hex currently under the mouse cursor, if any
+#[derive(Default)]
+pub struct HoveredTile(pub Option<AxialCoordinates>);
+
+/// The route currently previewed for the unit being moved
+#[derive(Default)]
+pub struct PathPreview {
+    pub path: Option<Path>,
 }
 
 pub struct UnlockedGenomes(pub Vec<AnimalType>);
+
+/// The seed the server drew this game's randomness from. Quoting it in a bug report lets the exact
+/// same map and spawns be replayed with `--seed`.
+pub struct GameSeed(pub u64);
+
+/// Descriptions of the fights this player's units have been involved in, oldest first
+#[derive(Default)]
+pub struct CombatLog(pub Vec<String>);
+
+/// The seconds the player whose turn it is has left of their turn and in their time bank, when the
+/// server limits how long turns last
+#[derive(Default)]
+pub struct TurnClock(pub Option<(u32, u32)>);
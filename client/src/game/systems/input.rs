@@ -10,13 +10,13 @@ use bevy_egui::EguiContext;
 use bevy_prototype_lyon::prelude::*;
 
 use rgj_shared::{
-    behavior::{AxialCoordinates, HEXAGON_SIZE},
+    behavior::AxialCoordinates,
     protocol::{player_input::PlayerInputVariant, MapSync},
 };
 
 use super::Player;
 
-use crate::game::resources::{Map, TileSelectedEvent, TurnTracker};
+use crate::game::resources::{HoveredTile, Map, TileSelectedEvent, TurnTracker};
 
 // This is the list of "things in the game I want to be able to do based on input"
 #[derive(Actionlike, PartialEq, Eq, Clone, Copy, Hash, Debug)]
@@ -72,6 +72,30 @@ pub fn zoom_camera_system(
     }
 }
 
+/// Converts the position of the cursor in the window to the hex under it
+pub fn cursor_to_axial(
+    window: &Window,
+    camera_trans: &GlobalTransform,
+    camera_proj: &OrthographicProjection,
+) -> Option<AxialCoordinates> {
+    let position = window.cursor_position()?;
+
+    let camera_x = camera_trans.translation.x;
+    let camera_y = camera_trans.translation.y;
+    let camera_scale = camera_proj.scale;
+
+    let x = position.x * camera_scale + camera_x - window.width() * camera_scale / 2.0;
+    let y = position.y * camera_scale + camera_y - window.height() * camera_scale / 2.0;
+
+    let qr = AxialCoordinates::from_world(Vec2::new(x, y));
+
+    if qr.column_q >= 0 && qr.row_r >= 0 {
+        Some(qr)
+    } else {
+        None
+    }
+}
+
 pub fn select_entity(
     mut entity_selected: EventWriter<TileSelectedEvent>,
     action_query: Query<&ActionState<Action>, With<Player>>,
@@ -83,34 +107,34 @@ pub fn select_entity(
         let action_state = action_query.single();
         if action_state.pressed(Action::Select) {
             let window = windows.get_primary().unwrap();
+            let (camera_trans, camera_proj) = camera_transform_query.get_single().unwrap();
 
-            if let Some(position) = window.cursor_position() {
-                let (camera_trans, camera_proj) = camera_transform_query.get_single().unwrap();
-
-                let camera_x = camera_trans.translation.x;
-                let camera_y = camera_trans.translation.y;
-                let camera_scale = camera_proj.scale;
-
-                let x = position.x * camera_scale + camera_x - window.width() * camera_scale / 2.0;
-                let y = position.y * camera_scale + camera_y - window.height() * camera_scale / 2.0;
-
-                let mut q = (f32::sqrt(3.0) / 3.0 * x - 1.0 / 3.0 * y) / HEXAGON_SIZE;
-                let mut r = (2.0 / 3.0 * y) / HEXAGON_SIZE;
-
-                q = q.round();
-                r = r.round();
+            if let Some(qr) = cursor_to_axial(window, camera_trans, camera_proj) {
+                info!("Clicked {} {}", qr.column_q, qr.row_r);
 
-                if q >= 0.0 && r >= 0.0 && q <= i32::MAX as f32 && r <= i32::MAX as f32 {
-                    let q = q as i32;
-                    let r = r as i32;
+                entity_selected.send(TileSelectedEvent(qr));
+            }
+        }
+    }
+}
 
-                    let qr = AxialCoordinates::new(q, r);
+/// Keeps track of which hex the cursor is hovering over
+pub fn hover_tile(
+    mut hovered: ResMut<HoveredTile>,
+    camera_transform_query: Query<(&GlobalTransform, &OrthographicProjection)>,
+    windows: Res<Windows>,
+    mut egui_context: ResMut<EguiContext>,
+) {
+    let qr = if egui_context.ctx_mut().is_pointer_over_area() {
+        None
+    } else {
+        let window = windows.get_primary().unwrap();
+        let (camera_trans, camera_proj) = camera_transform_query.get_single().unwrap();
 
-                    info!("Clicked {} {}", q, r);
+        cursor_to_axial(window, camera_trans, camera_proj)
+    };
 
-                    entity_selected.send(TileSelectedEvent(qr));
-                }
-            }
-        }
+    if hovered.0 != qr {
+        hovered.0 = qr;
     }
 }
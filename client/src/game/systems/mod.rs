@@ -6,30 +6,33 @@ use bevy_egui::{
 use leafwing_input_manager::prelude::*;
 
 use naia_bevy_client::{
-    events::{InsertComponentEvent, MessageEvent, UpdateComponentEvent},
+    events::{DespawnEntityEvent, InsertComponentEvent, MessageEvent, UpdateComponentEvent},
     Client,
 };
 
 use rgj_shared::{
-    behavior::HEXAGON_SIZE,
     components::players::PlayerId,
     protocol::{
-        game_sync::map_sync::{MapSync, TileStructure, TileType},
+        game_sync::map_sync::{MapSync, TileStructure, MAP_HEIGHT},
         notifications::{genome_status_change::LockedStatus, WhoseTurn},
         player_input::PlayerInputVariant,
-        PlayerInput, Protocol, ProtocolKind, UnitSync,
+        MapChunkSync, PlayerInput, Protocol, ProtocolKind, UnitSync,
     },
     Channels,
 };
 
-use crate::TileSprites;
+use crate::{
+    common_systems::{structure_sprite, tile_tint, unit_transform},
+    ReconnectCode, TileSprites,
+};
 
 use super::{
     components::TileWithBuilding,
-    resources::{Map, TurnTracker, UnlockedGenomes},
+    resources::{CombatLog, Map, TileSelectedState, TurnClock, TurnTracker, UnlockedGenomes},
 };
 
 pub mod input;
+pub mod path_preview;
 pub mod tile_info;
 
 // TODO: Extract and don't copy paste from version in countdown
@@ -44,15 +47,7 @@ pub fn insert_unit_sync_event(
     for event in event_reader.iter() {
         if let InsertComponentEvent(entity, ProtocolKind::UnitSync) = event {
             if let Ok(unit_sync) = query.get(*entity) {
-                let q = unit_sync.position.column_q;
-                let r = unit_sync.position.row_r;
-                let z = *unit_sync.layer;
-
-                let transform = Transform::from_xyz(
-                    HEXAGON_SIZE * (q as f32 * f32::sqrt(3.0) + (f32::sqrt(3.0) / 2.0 * r as f32)),
-                    HEXAGON_SIZE * (r as f32 * 3.0 / 2.0),
-                    z as f32 * -1.0 + 0.9,
-                );
+                let transform = unit_transform(*unit_sync.position, *unit_sync.layer);
 
                 commands.entity(*entity).insert_bundle(SpriteBundle {
                     sprite: Sprite {
@@ -64,7 +59,7 @@ pub fn insert_unit_sync_event(
                     ..Default::default()
                 });
 
-                map.coords_to_unit.insert((q, r, z as i32), *entity);
+                map.insert_unit(*entity, *unit_sync.position, *unit_sync.layer);
             }
         }
     }
@@ -85,74 +80,70 @@ pub fn spawn_player(mut commands: Commands) {
         });
 }
 
-pub fn update_map_component_event(
+/// Applies changes to a chunk of the map to the tiles in it
+pub fn update_map_chunk_event(
     mut commands: Commands,
 
     mut event_reader: EventReader<UpdateComponentEvent<ProtocolKind>>,
 
-    query_auth: Query<&MapSync>,
-    query_translate: Query<&Transform>,
-    mut query_handle: Query<&mut Handle<Image>>,
-    // mut query_w_building: Query<&mut TileWithBuilding>,
+    query_chunk: Query<&MapChunkSync>,
+    mut query_tile: Query<(
+        &mut MapSync,
+        &mut Handle<Image>,
+        &mut Sprite,
+        &Transform,
+        Option<&TileWithBuilding>,
+    )>,
+    map: Res<Map>,
     assets: Res<TileSprites>,
 ) {
     for event in event_reader.iter() {
-        if let UpdateComponentEvent(_tick, entity, ProtocolKind::MapSync) = event {
-            if let Ok(map_sync) = query_auth.get(*entity) {
-                let mut handle = query_handle.get_mut(*entity).unwrap();
-                let texture = match *map_sync.tile_type {
-                    TileType::Fog => &assets.fog,
-
-                    TileType::Grass => &assets.grass,
-                    TileType::Forest => &assets.forest,
-                    TileType::Desert => &assets.desert,
-
-                    TileType::Ocean => &assets.ocean,
-                    // FIXME: River should be river
-                    TileType::River => &assets.ocean,
-                    TileType::DesertOasis => &assets.oasis,
-
-                    TileType::ClearSky => &assets.clear_sky,
-                    TileType::WindySky => &assets.windy_sky,
-                    TileType::StormySky => &assets.stormy_sky,
-                };
-
-                *handle = texture.clone();
-
-                if *map_sync.structure != TileStructure::None {
-                    if let Ok(transform) = query_translate.get(*entity) {
-                        let mut transform = transform.clone();
-
-                        let color: Color = (&*map_sync.structure).into();
-                        transform.translation.z += 0.1;
-
-                        let structure_entity = commands
-                            .spawn_bundle(SpriteBundle {
-                                sprite: Sprite {
-                                    color,
-                                    custom_size: Some(Vec2::new(65.0, 65.0)),
-                                    ..Default::default()
-                                },
-                                transform: transform,
-                                ..Default::default()
-                            })
-                            .id();
-
-                        commands.entity(*entity).insert(TileWithBuilding {
-                            structure_entity: structure_entity,
-                        });
+        if let UpdateComponentEvent(_tick, entity, ProtocolKind::MapChunkSync) = event {
+            if let Ok(chunk) = query_chunk.get(*entity) {
+                for z in 0..MAP_HEIGHT {
+                    for qr in chunk.bounds().hexes() {
+                        let tile_entity = match map.coords_to_tile.get(&(qr.column_q, qr.row_r, z))
+                        {
+                            Some(tile_entity) => *tile_entity,
+                            None => continue,
+                        };
+                        let (mut map_sync, mut handle, mut sprite, transform, building) =
+                            query_tile.get_mut(tile_entity).unwrap();
+
+                        let tile_type = chunk.tile(qr, z);
+                        if *map_sync.tile_type != tile_type {
+                            *map_sync.tile_type = tile_type;
+                            *handle = assets.for_tile(tile_type).clone();
+                        }
+
+                        // Dim the tiles which are only remembered
+                        let tint = tile_tint(chunk.is_remembered(qr, z));
+                        if sprite.color != tint {
+                            sprite.color = tint;
+                        }
+
+                        let structure = chunk.structure(qr, z);
+                        if *map_sync.structure != structure {
+                            // Replace the building's sprite, if there was one
+                            if let Some(building) = building {
+                                commands.entity(building.structure_entity).despawn();
+                                commands.entity(tile_entity).remove::<TileWithBuilding>();
+                            }
+
+                            if structure != TileStructure::None {
+                                let structure_entity = commands
+                                    .spawn_bundle(structure_sprite(&structure, *transform))
+                                    .id();
+
+                                commands
+                                    .entity(tile_entity)
+                                    .insert(TileWithBuilding { structure_entity });
+                            }
+
+                            *map_sync.structure = structure;
+                        }
                     }
                 }
-                // Clean up if the entity has a TileWithBuilding
-                else {
-                    // TODO: Not sure why this doesn't work, but it doesn't, so it's a TODO
-                    // if let Ok(twb) = query_w_building.get_mut(*entity) {
-                    //     let struct_entity = twb.structure_entity;
-
-                    //     commands.entity(struct_entity).despawn();
-                    //     commands.entity(*entity).remove::<TileWithBuilding>();
-                    // }
-                }
             }
         }
     }
@@ -169,46 +160,41 @@ pub fn update_unit_component_event(
     for event in event_reader.iter() {
         if let UpdateComponentEvent(_tick, entity, ProtocolKind::UnitSync) = event {
             if let Ok(unit_sync) = query_unit.get(*entity) {
-                let (q, r) = (unit_sync.position.column_q, unit_sync.position.row_r);
-
                 let mut transform = query_local.get_mut(*entity).unwrap();
-                let (old_q, old_r) = {
-                    let mut q = (f32::sqrt(3.0) / 3.0 * transform.translation.x
-                        - 1.0 / 3.0 * transform.translation.y)
-                        / HEXAGON_SIZE;
-                    let mut r = (2.0 / 3.0 * transform.translation.y) / HEXAGON_SIZE;
-
-                    q = q.round();
-                    r = r.round();
-
-                    if q >= 0.0 && r >= 0.0 && q <= i32::MAX as f32 && r <= i32::MAX as f32 {
-                        let q = q as i32;
-                        let r = r as i32;
-
-                        (q, r)
-                    } else {
-                        panic!("Could not fit in i32");
-                    }
-                };
-
-                let new = Transform::from_xyz(
-                    HEXAGON_SIZE * (q as f32 * f32::sqrt(3.0) + (f32::sqrt(3.0) / 2.0 * r as f32)),
-                    HEXAGON_SIZE * (r as f32 * 3.0 / 2.0),
-                    0.9,
-                );
-                *transform = new;
+                *transform = unit_transform(*unit_sync.position, *unit_sync.layer);
 
-                map.coords_to_unit.remove(&(old_q, old_r, 0));
-                map.coords_to_unit.insert((q, r, 0), *entity);
+                map.insert_unit(*entity, *unit_sync.position, *unit_sync.layer);
             }
         }
     }
 }
 
+/// Forgets about units which the server has removed, such as those which died in combat
+pub fn despawn_entity_event(
+    mut event_reader: EventReader<DespawnEntityEvent>,
+
+    mut map: ResMut<Map>,
+    mut state: ResMut<TileSelectedState>,
+) {
+    for DespawnEntityEvent(entity) in event_reader.iter() {
+        map.remove_unit(*entity);
+
+        if state.moving_unit == Some(*entity) {
+            state.moving_unit = None;
+        }
+        if state.attacking_unit == Some(*entity) {
+            state.attacking_unit = None;
+        }
+    }
+}
+
 pub fn game_menu(
     mut client: Client<Protocol, Channels>,
 
     turn_tracker: Res<TurnTracker>,
+    turn_clock: Res<TurnClock>,
+    combat_log: Res<CombatLog>,
+    reconnect_code: Option<Res<ReconnectCode>>,
     mut egui_context: ResMut<EguiContext>,
 ) {
     let label = match &turn_tracker.whose_turn {
@@ -231,13 +217,19 @@ pub fn game_menu(
         WhoseTurn::Yours { turn_number } => {
             egui::Window::new(format!("Turn {}", turn_number)).show(egui_context.ctx_mut(), |ui| {
                 ui.label(label);
+                show_turn_clock(ui, &turn_clock);
                 commit_turn = ui.button("End Turn").clicked();
+                show_combat_log(ui, &combat_log);
+                show_reconnect_code(ui, reconnect_code.as_deref());
             });
         }
 
         WhoseTurn::Player { turn_number, .. } => {
             egui::Window::new(format!("Turn {}", turn_number)).show(egui_context.ctx_mut(), |ui| {
                 ui.label(label);
+                show_turn_clock(ui, &turn_clock);
+                show_combat_log(ui, &combat_log);
+                show_reconnect_code(ui, reconnect_code.as_deref());
             });
         }
     }
@@ -250,6 +242,43 @@ pub fn game_menu(
     }
 }
 
+/// Shows the code needed to take this player's seat back if they lose their connection
+fn show_reconnect_code(ui: &mut egui::Ui, reconnect_code: Option<&ReconnectCode>) {
+    if let Some(code) = reconnect_code {
+        ui.collapsing("Reconnect code", |ui| {
+            ui.label(format!("{:016x}", code.0));
+        });
+    }
+}
+
+/// Shows how long the current turn has left, if turns are timed
+fn show_turn_clock(ui: &mut egui::Ui, turn_clock: &TurnClock) {
+    if let Some((turn_secs, bank_secs)) = turn_clock.0 {
+        let mut text = format!("Time left: {}:{:02}", turn_secs / 60, turn_secs % 60);
+        if bank_secs > 0 {
+            text.push_str(&format!(
+                " (+{}:{:02} banked)",
+                bank_secs / 60,
+                bank_secs % 60
+            ));
+        }
+
+        // Draw attention to the clock once the turn is nearly over
+        if turn_secs <= 10 {
+            ui.label(RichText::new(text).color(Color32::from_rgb(200, 40, 40)));
+        } else {
+            ui.label(text);
+        }
+    }
+}
+
+/// Lists the most recent fights underneath the turn information
+fn show_combat_log(ui: &mut egui::Ui, combat_log: &CombatLog) {
+    for entry in combat_log.0.iter().rev().take(5) {
+        ui.label(entry);
+    }
+}
+
 pub fn receive_turn_change_notification(
     mut event_reader: EventReader<MessageEvent<Protocol, Channels>>,
     mut commands: Commands,
@@ -263,6 +292,19 @@ pub fn receive_turn_change_notification(
     }
 }
 
+pub fn receive_turn_clock_notification(
+    mut event_reader: EventReader<MessageEvent<Protocol, Channels>>,
+    mut turn_clock: ResMut<TurnClock>,
+) {
+    for event in event_reader.iter() {
+        if let MessageEvent(Channels::GameNotification, Protocol::TurnClockNotification(tcn)) =
+            event
+        {
+            turn_clock.0 = Some((*tcn.turn_secs_left, *tcn.bank_secs_left));
+        }
+    }
+}
+
 pub fn receive_genome_status_change_notification(
     mut event_reader: EventReader<MessageEvent<Protocol, Channels>>,
     mut genomes: ResMut<UnlockedGenomes>,
@@ -286,3 +328,41 @@ pub fn receive_genome_status_change_notification(
         }
     }
 }
+
+pub fn receive_combat_result_notification(
+    mut event_reader: EventReader<MessageEvent<Protocol, Channels>>,
+    mut combat_log: ResMut<CombatLog>,
+) {
+    for event in event_reader.iter() {
+        if let MessageEvent(Channels::GameNotification, Protocol::CombatResultNotification(crn)) =
+            event
+        {
+            let attacker = &*crn.attacker;
+            let defender = &*crn.defender;
+
+            let mut entry = format!(
+                "{:?}'s {} attacked {:?}'s {} for {} damage",
+                attacker.owner,
+                attacker.hybrid_name,
+                defender.owner,
+                defender.hybrid_name,
+                defender.damage_taken
+            );
+
+            if defender.died() {
+                entry.push_str(", killing it");
+            } else if attacker.damage_taken > 0 {
+                entry.push_str(&format!(
+                    " and took {} damage in return",
+                    attacker.damage_taken
+                ));
+                if attacker.died() {
+                    entry.push_str(", dying in the attempt");
+                }
+            }
+
+            info!("{}", entry);
+            combat_log.0.push(entry);
+        }
+    }
+}
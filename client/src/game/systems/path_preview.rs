@@ -0,0 +1,68 @@
+use bevy::prelude::*;
+
+use rgj_shared::{
+    behavior::pathfinding::find_path,
+    protocol::{MapSync, UnitSync},
+};
+
+use crate::game::{
+    components::PathPreviewMarker,
+    resources::{HoveredTile, Map, PathPreview, TileSelectedState},
+};
+
+/// Shows the route the unit being moved would take to reach the hovered tile
+pub fn preview_path(
+    mut commands: Commands,
+
+    query_markers: Query<Entity, With<PathPreviewMarker>>,
+    map_sync_query: Query<&MapSync>,
+    unit_sync_query: Query<&UnitSync>,
+
+    map: Res<Map>,
+    hovered: Res<HoveredTile>,
+    state: Res<TileSelectedState>,
+    mut preview: ResMut<PathPreview>,
+) {
+    let path = match (state.moving_unit, hovered.0) {
+        (Some(entity), Some(goal)) => unit_sync_query.get(entity).ok().and_then(|unit_sync| {
+            find_path(
+                &unit_sync.hybrid_type,
+                *unit_sync.stamina_remaining,
+                *unit_sync.position,
+                goal,
+                |point| map.tile_type(&map_sync_query, point, *unit_sync.layer),
+            )
+            .ok()
+        }),
+        _ => None,
+    };
+
+    // Only respawn the markers when the route actually changes
+    if path == preview.path {
+        return;
+    }
+
+    for marker in query_markers.iter() {
+        commands.entity(marker).despawn();
+    }
+
+    if let Some(path) = &path {
+        for step in &path.steps {
+            let world = step.to_world();
+
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgba(1.0, 1.0, 1.0, 0.6),
+                        custom_size: Some(Vec2::new(20.0, 20.0)),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(world.x, world.y, 0.8),
+                    ..Default::default()
+                })
+                .insert(PathPreviewMarker);
+        }
+    }
+
+    preview.path = path;
+}
@@ -3,17 +3,24 @@ use bevy_egui::{egui, EguiContext};
 use naia_bevy_client::{events::UpdateComponentEvent, shared::BigMapKey, Client};
 
 use rgj_shared::{
-    behavior::AxialCoordinates,
-    components::genome::{AnimalType, Hybrid, TerrainType, DEER},
+    behavior::{
+        combat::{check_attack, CombatError},
+        movement::{layer_change_cost, step_cost, STAMINA_PER_TURN},
+        pathfinding::find_path,
+        AxialCoordinates,
+    },
+    components::genome::{AnimalType, Hybrid, DEER},
     protocol::{
-        game_sync::map_sync::{ConstructionStatus, MapSync, TileStructure, TileType},
+        game_sync::map_sync::{ConstructionStatus, MapSync, TileStructure, TileType, MAP_HEIGHT},
         player_input::PlayerInputVariant,
         PlayerInput, Protocol, ProtocolKind, UnitSync,
     },
     Channels,
 };
 
-use crate::game::resources::{Map, TileSelectedEvent, TileSelectedState, UnlockedGenomes};
+use crate::game::resources::{
+    Map, PathPreview, TileSelectedEvent, TileSelectedState, UnlockedGenomes,
+};
 
 // TODO: Only run on state for performance
 pub fn display_info(
@@ -27,6 +34,7 @@ pub fn display_info(
 
     map: Res<Map>,
     genomes: Res<UnlockedGenomes>,
+    preview: Res<PathPreview>,
 
     mut state: ResMut<TileSelectedState>,
 
@@ -41,6 +49,7 @@ pub fn display_info(
                     // Then update the tracked  tile to the new position of the unit
                     if let Ok(unit_sync) = unit_sync_query.get(*entity) {
                         state.tile = Some(*unit_sync.position);
+                        state.layer = *unit_sync.layer;
                     }
                 }
             }
@@ -49,135 +58,60 @@ pub fn display_info(
 
     // If a new tile is selected
     if let Some(tile) = tile_selected.iter().last() {
-        // Then move the set moving unit to that tile if there is one
-        if let Some(entity) = state.moving_unit {
+        // Then attack that tile with the set attacking unit if there is one
+        if let Some(entity) = state.attacking_unit {
             if let Ok(unit_sync) = unit_sync_query.get(entity) {
-                let entity_pos = *unit_sync.position;
-                let desired_pos = tile.0;
-
-                // TODO: More hex utils
-
-                // Calculate the distance between the the desired position and the current position,
-                // caching what's needed
-                let (dist, diff_q, diff_r) = {
-                    let (diff_q, diff_r) = (
-                        entity_pos.column_q as i32 - desired_pos.column_q as i32,
-                        entity_pos.row_r as i32 - desired_pos.row_r as i32,
-                    );
-
-                    let abs_q = i32::abs(diff_q);
-                    let abs_q_plus_r = i32::abs(diff_q + diff_r);
-                    let abs_r_div_2 = i32::abs(diff_r) / 2;
-
-                    ((abs_q + abs_q_plus_r + abs_r_div_2) as i32, diff_q, diff_r)
-                };
-
-                // Draw a line through the two points
-                let mut travels_through: Vec<AxialCoordinates> = Vec::new();
-                for i in 0..=dist {
-                    let step_q =
-                        desired_pos.column_q as f32 + diff_q as f32 * 1.0 / dist as f32 * i as f32;
-                    let step_r =
-                        desired_pos.row_r as f32 + diff_r as f32 * 1.0 / dist as f32 * i as f32;
-
-                    travels_through.push(AxialCoordinates::new(
-                        step_q.round() as i32,
-                        step_r.round() as i32,
-                    ));
-                }
+                let target = tile.0;
 
-                // Ensure that the player's unit has enough stamina and has the requisite terrain
-                // types to cross this terrain
-                let mut entity_stamina = *unit_sync.stamina_remaining;
+                let attack =
+                    check_attack(*unit_sync.position, *unit_sync.stamina_remaining, target)
+                        .and_then(|_| {
+                            map.unit_at(target, *unit_sync.layer)
+                                .ok_or(CombatError::NoTarget)
+                        });
 
-                let mut terrain_types = Vec::with_capacity(2);
-                terrain_types.push(unit_sync.hybrid_type.limbs().terrain_a.terrain_type);
-                if let Some(terrain_b) = unit_sync.hybrid_type.limbs().terrain_b {
-                    terrain_types.push(terrain_b.terrain_type);
-                }
+                match attack {
+                    Ok(_) => {
+                        let mut input =
+                            PlayerInput::new_complete(PlayerInputVariant::Attack(target));
+                        input.relevant_entity.set(&client, &entity);
 
-                let mut can_travel = true;
-
-                // Step through each point in the line
-                for point in travels_through {
-                    if let Some(tile) = map.coords_to_tile.get(&(point.column_q, point.row_r, 0)) {
-                        let tile_state = map_sync_query.get(*tile).unwrap();
-                        // TODO: More complex movement rules here -- traversing different terrains
-                        // differently
-                        // TODO: Queueing movement that cancels or pauses if cannont be completed
-                        // ever or immediately
-                        match *tile_state.tile_type {
-                            TileType::Fog => {
-                                can_travel = false;
-                                state.error = "You must be able to see through the fog to cross it -- try getting closer firt".to_owned();
-                                break;
-                            }
-                            TileType::Grass | TileType::Forest | TileType::Desert => {
-                                if terrain_types.contains(&TerrainType::Ground) {
-                                    if entity_stamina > 0 {
-                                        entity_stamina -= 1;
-                                    } else {
-                                        can_travel = false;
-                                        state.error =
-                                            "You do not have enough energy to walk this far"
-                                                .to_owned();
-                                        break;
-                                    }
-                                } else {
-                                    can_travel = false;
-                                    state.error =
-                                        "This creature cannot walk on the ground".to_owned();
-                                    break;
-                                }
-                            }
-                            TileType::Ocean | TileType::River | TileType::DesertOasis => {
-                                if terrain_types.contains(&TerrainType::Water) {
-                                    if entity_stamina > 0 {
-                                        entity_stamina -= 1;
-                                    } else {
-                                        can_travel = false;
-                                        state.error =
-                                            "You do not have enough energy to swim this far"
-                                                .to_owned();
-                                        break;
-                                    }
-                                } else {
-                                    can_travel = false;
-                                    state.error =
-                                        "This creature cannot swim in the water".to_owned();
-                                    break;
-                                }
-                            }
-                            TileType::ClearSky | TileType::WindySky | TileType::StormySky => {
-                                if terrain_types.contains(&TerrainType::Air) {
-                                    if entity_stamina > 0 {
-                                        entity_stamina -= 1;
-                                    } else {
-                                        can_travel = false;
-                                        state.error =
-                                            "You do not have enough energy to fly this far"
-                                                .to_owned();
-                                        break;
-                                    }
-                                } else {
-                                    can_travel = false;
-                                    state.error = "This creature cannot fly in the air".to_owned();
-                                    break;
-                                }
-                            }
-                        }
-                    } else {
-                        state.error = "Walking through tile that does not exist".to_owned();
+                        client.send_message(Channels::PlayerInput, &input);
+                        state.attacking_unit = None;
+                        state.error = String::new();
                     }
+                    Err(e) => state.error = format!("Cannot attack there: {}", e),
                 }
+            } else {
+                state.error = "Fatal internal error in UnitSync-less unit".to_owned();
+            }
+        }
+        // Or move the set moving unit to that tile if there is one
+        else if let Some(entity) = state.moving_unit {
+            if let Ok(unit_sync) = unit_sync_query.get(entity) {
+                let desired_pos = tile.0;
 
-                // Finally if the travel is valid, send the message
-                if can_travel {
-                    let mut input =
-                        PlayerInput::new_complete(PlayerInputVariant::MoveEntity(desired_pos));
-                    input.relevant_entity.set(&client, &entity);
+                // Use the same pathfinder as the server so that a move accepted here is never
+                // rejected there
+                let path = find_path(
+                    &unit_sync.hybrid_type,
+                    *unit_sync.stamina_remaining,
+                    *unit_sync.position,
+                    desired_pos,
+                    |point| map.tile_type(&map_sync_query, point, *unit_sync.layer),
+                );
 
-                    client.send_message(Channels::PlayerInput, &input);
+                // Finally if the travel is valid, send the message
+                match path {
+                    Ok(_) => {
+                        let mut input =
+                            PlayerInput::new_complete(PlayerInputVariant::MoveEntity(desired_pos));
+                        input.relevant_entity.set(&client, &entity);
+
+                        client.send_message(Channels::PlayerInput, &input);
+                        state.error = String::new();
+                    }
+                    Err(e) => state.error = format!("Cannot move there: {}", e),
                 }
             } else {
                 state.error = "Fatal internal error in UnitSync-less unit".to_owned();
@@ -186,6 +120,13 @@ pub fn display_info(
         // If a unit isn't being tracked, then update the tracked tile
         else {
             state.tile = Some(tile.0);
+
+            // Show whichever layer has a unit on it if the current one doesn't
+            if map.unit_at(tile.0, state.layer).is_none() {
+                if let Some(layer) = (0..MAP_HEIGHT).find(|z| map.unit_at(tile.0, *z).is_some()) {
+                    state.layer = layer;
+                }
+            }
         }
     }
 
@@ -193,7 +134,7 @@ pub fn display_info(
         .tile
         .map(|tile| {
             map.coords_to_tile
-                .get(&(tile.column_q, tile.row_r, 0))
+                .get(&(tile.column_q, tile.row_r, state.layer))
                 .map(|e| map_sync_query.get(*e).map(|s| s.clone()).ok())
                 .flatten()
         })
@@ -203,7 +144,7 @@ pub fn display_info(
         .tile
         .map(|tile| {
             map.coords_to_unit
-                .get(&(tile.column_q, tile.row_r, 0))
+                .get(&(tile.column_q, tile.row_r, state.layer))
                 .map(|e| unit_sync_query.get(*e).map(|s| s.clone()).ok())
                 .flatten()
         })
@@ -225,6 +166,9 @@ pub fn display_info(
             }),
         ) => {
             let mut toggle_move = false;
+            let mut toggle_attack = false;
+            let mut change_layer = None;
+            let mut view_layer = None;
             egui::Window::new("Unit View").show(egui_context.ctx_mut(), |ui| {
                 if !state.error.is_empty() {
                     ui.label(&state.error);
@@ -246,9 +190,19 @@ pub fn display_info(
 
                 ui.horizontal(|ui| {
                     ui.label("Remaining Stamina:");
-                    ui.label(format!("{}", *stamina_remaining));
+                    ui.label(format!("{} of {}", *stamina_remaining, STAMINA_PER_TURN));
                 });
 
+                // While choosing where to move, show what the hovered route would cost
+                if state.moving_unit.is_some() {
+                    if let Some(path) = &preview.path {
+                        ui.horizontal(|ui| {
+                            ui.label("Move Cost:");
+                            ui.label(format!("{}", path.cost));
+                        });
+                    }
+                }
+
                 ui.horizontal(|ui| {
                     ui.label("Tile Type:");
                     ui.label((*tile_type).to_string());
@@ -281,17 +235,66 @@ pub fn display_info(
                     }
                 }
 
-                // TODO: Only display this button if the unit belongs to this player
+                // TODO: Only display these buttons if the unit belongs to this player
+                if state.attacking_unit.is_none() {
+                    if state.moving_unit.is_none() {
+                        if ui.button("Move").clicked() {
+                            toggle_move = true;
+                        }
+                    } else {
+                        if ui.button("Cancel Move").clicked() {
+                            toggle_move = true;
+                        }
+                    }
+                }
+
                 if state.moving_unit.is_none() {
-                    if ui.button("Move").clicked() {
-                        toggle_move = true;
+                    if state.attacking_unit.is_none() {
+                        if ui.button("Attack").clicked() {
+                            toggle_attack = true;
+                        }
+                    } else {
+                        if ui.button("Cancel Attack").clicked() {
+                            toggle_attack = true;
+                        }
                     }
-                } else {
-                    if ui.button("Cancel Move").clicked() {
-                        toggle_move = true;
+                }
+
+                // Flying hybrids can take off from and land on the hex they are on
+                if state.moving_unit.is_none() && state.attacking_unit.is_none() {
+                    let (label, target) = if *layer == 0 {
+                        ("Take Off", 1)
+                    } else {
+                        ("Land", *layer - 1)
+                    };
+
+                    // Only offer it to hybrids which could ever enter the tile on the other layer
+                    if let Some(target_tile) = map.tile_type(&map_sync_query, *position, target) {
+                        if (target_tile == TileType::Fog
+                            || step_cost(&hybrid_type, target_tile).is_some())
+                            && ui.button(label).clicked()
+                        {
+                            match layer_change_cost(
+                                &hybrid_type,
+                                *stamina_remaining,
+                                *layer,
+                                target,
+                                target_tile,
+                            ) {
+                                Ok(_) => {
+                                    change_layer = Some(target);
+                                    state.error = String::new();
+                                }
+                                Err(e) => {
+                                    state.error = format!("Cannot {}: {}", label.to_lowercase(), e)
+                                }
+                            }
+                        }
                     }
                 }
 
+                view_layer = other_layer_button(ui, *layer);
+
                 if state.build_screen {
                     if on_facility && ui.button("Close build menu").clicked() {
                         state.build_screen = false;
@@ -368,18 +371,30 @@ pub fn display_info(
                 } else {
                     Change::CancelMoveUnit
                 }
+            } else if toggle_attack {
+                if state.attacking_unit.is_none() {
+                    Change::AttackWithUnit(*position, *layer)
+                } else {
+                    Change::CancelAttack
+                }
+            } else if let Some(target) = change_layer {
+                Change::ChangeLayer(*position, *layer, target)
+            } else if let Some(layer) = view_layer {
+                Change::ViewLayer(layer)
             } else {
                 Change::None
             }
         }
         (
             Some(MapSync {
+                layer,
                 tile_type,
                 structure,
                 ..
             }),
             None,
         ) => {
+            let mut view_layer = None;
             egui::Window::new("Tile View").show(egui_context.ctx_mut(), |ui| {
                 if !state.error.is_empty() {
                     ui.label(&state.error);
@@ -413,8 +428,14 @@ pub fn display_info(
                         ));
                     }
                 }
+
+                view_layer = other_layer_button(ui, *layer);
             });
-            Change::None
+
+            match view_layer {
+                Some(layer) => Change::ViewLayer(layer),
+                None => Change::None,
+            }
         }
 
         (None, None) => Change::None,
@@ -437,6 +458,29 @@ pub fn display_info(
             state.moving_unit = None;
             state.error = String::new();
         }
+        Change::AttackWithUnit(coord, layer) => {
+            if let Some(unit) = map
+                .coords_to_unit
+                .get(&(coord.column_q, coord.row_r, layer))
+            {
+                state.attacking_unit = Some(*unit);
+            }
+        }
+        Change::CancelAttack => {
+            state.attacking_unit = None;
+            state.error = String::new();
+        }
+        Change::ChangeLayer(coord, layer, target) => {
+            if let Some(unit) = map.unit_at(coord, layer) {
+                let mut input = PlayerInput::new_complete(PlayerInputVariant::ChangeLayer(target));
+                input.relevant_entity.set(&client, &unit);
+
+                client.send_message(Channels::PlayerInput, &input);
+            }
+        }
+        Change::ViewLayer(layer) => {
+            state.layer = layer;
+        }
         Change::BuildUnit(pos, hybrid) => {
             client.send_message(
                 Channels::PlayerInput,
@@ -451,5 +495,24 @@ pub enum Change {
     None,
     MoveUnit(AxialCoordinates, i32),
     CancelMoveUnit,
+    AttackWithUnit(AxialCoordinates, i32),
+    CancelAttack,
+    ChangeLayer(AxialCoordinates, i32, i32),
+    ViewLayer(i32),
     BuildUnit(AxialCoordinates, Hybrid),
 }
+
+/// Offers to show the other layer of the selected hex, returning that layer if clicked
+fn other_layer_button(ui: &mut egui::Ui, layer: i32) -> Option<i32> {
+    let (label, other) = if layer == 0 {
+        ("View Sky", 1)
+    } else {
+        ("View Ground", 0)
+    };
+
+    if ui.button(label).clicked() {
+        Some(other)
+    } else {
+        None
+    }
+}
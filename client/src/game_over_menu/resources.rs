@@ -0,0 +1,6 @@
+use rgj_shared::protocol::notifications::game_over::{Standing, Victory};
+
+pub struct FinalStandings {
+    pub victory: Victory,
+    pub standings: Vec<Standing>,
+}
@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use iyes_loopless::state::NextState;
+use naia_bevy_client::events::MessageEvent;
+
+use rgj_shared::{
+    protocol::{notifications::game_over::Victory, Protocol},
+    Channels,
+};
+
+use super::resources::FinalStandings;
+use crate::{game::resources::GameSeed, GameState};
+
+pub fn receive_game_over_notification(
+    mut event_reader: EventReader<MessageEvent<Protocol, Channels>>,
+    mut commands: Commands,
+) {
+    for event in event_reader.iter() {
+        if let MessageEvent(Channels::GameNotification, Protocol::GameOverNotification(gon)) = event
+        {
+            commands.insert_resource(FinalStandings {
+                victory: (*gon.victory).clone(),
+                standings: (*gon.standings).clone(),
+            });
+            commands.insert_resource(NextState(GameState::GameOverMenu));
+        }
+    }
+}
+
+pub fn game_over_menu(
+    standings: Res<FinalStandings>,
+    seed: Res<GameSeed>,
+    mut egui_context: ResMut<EguiContext>,
+) {
+    let how = match standings.victory {
+        Victory::LastPlayerStanding => "by being the last player standing".to_owned(),
+        Victory::HeldFacilities { facilities, turns } => format!(
+            "by holding {} genome facilities for {} turns",
+            facilities, turns
+        ),
+    };
+
+    egui::Window::new("Game Over").show(egui_context.ctx_mut(), |ui| {
+        if let Some(winner) = standings.standings.first() {
+            ui.label(format!("{} won {}", winner.username, how));
+        }
+
+        ui.separator();
+
+        for (place, standing) in standings.standings.iter().enumerate() {
+            let eliminated = match standing.eliminated_on_turn {
+                Some(turn) => format!(" -- eliminated on turn {}", turn),
+                None => "".to_owned(),
+            };
+
+            ui.label(format!(
+                "{}. {}{}",
+                place + 1,
+                standing.username,
+                eliminated
+            ));
+        }
+
+        ui.separator();
+        ui.label(format!("Game seed: {}", seed.0));
+    });
+}
@@ -2,10 +2,14 @@ use std::net::SocketAddr;
 
 use bevy::prelude::*;
 
+use rgj_shared::protocol::game_sync::map_sync::TileType;
+
 pub mod common_systems;
 pub mod connect_menu;
 pub mod countdown_menu;
 pub mod game;
+pub mod game_over_menu;
+pub mod map_editor;
 pub mod waiting_for_more_connections_menu;
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
@@ -14,15 +18,24 @@ pub enum GameState {
     WaitingForMoreConnectionsMenu,
     CountdownMenu,
     Game,
+    GameOverMenu,
+    MapEditor,
 }
 
 #[derive(Default)]
 pub struct ConnectionInformation {
     pub socket_addr: Option<SocketAddr>,
     pub username: String,
+    pub room_name: String,
     pub room_password: String,
+    /// The token from a game this player lost their connection to, to take their seat back
+    pub reconnect_token: Option<u64>,
 }
 
+/// The token the server handed out when the game started, which lets this player take their seat
+/// back if they lose their connection. Shown as hexadecimal so that it can be typed back in.
+pub struct ReconnectCode(pub u64);
+
 pub struct TileSprites {
     pub beach: Handle<Image>,
     pub clear_sky: Handle<Image>,
@@ -37,6 +50,44 @@ pub struct TileSprites {
     pub windy_sky: Handle<Image>,
 }
 
+impl TileSprites {
+    pub fn load(assets: &AssetServer) -> TileSprites {
+        TileSprites {
+            beach: assets.load("tiles/BeachHex.png"),
+            clear_sky: assets.load("tiles/ClearSkyHex.png"),
+            desert: assets.load("tiles/DesertHex.png"),
+            fog: assets.load("tiles/FogHex.png"),
+            forest: assets.load("tiles/ForestHex.png"),
+            grass: assets.load("tiles/GrassHex.png"),
+            island: assets.load("tiles/IslandHex.png"),
+            oasis: assets.load("tiles/OasisHex.png"),
+            ocean: assets.load("tiles/OceanHex.png"),
+            stormy_sky: assets.load("tiles/StormySkyHex.png"),
+            windy_sky: assets.load("tiles/WindySkyHex.png"),
+        }
+    }
+
+    /// The sprite drawn for the given type of tile
+    pub fn for_tile(&self, tile: TileType) -> &Handle<Image> {
+        match tile {
+            TileType::Fog => &self.fog,
+
+            TileType::Grass => &self.grass,
+            TileType::Forest => &self.forest,
+            TileType::Desert => &self.desert,
+
+            TileType::Ocean => &self.ocean,
+            // FIXME: River should be river
+            TileType::River => &self.ocean,
+            TileType::DesertOasis => &self.oasis,
+
+            TileType::ClearSky => &self.clear_sky,
+            TileType::WindySky => &self.windy_sky,
+            TileType::StormySky => &self.stormy_sky,
+        }
+    }
+}
+
 pub struct UnitSprites {
     pub bg_red: Handle<Image>,
     pub bg_orange: Handle<Image>,
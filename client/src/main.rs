@@ -17,6 +17,8 @@ use rgj_client::{
     common_systems, connect_menu,
     countdown_menu::systems as countdown_systems,
     game::{resources::TileSelectedEvent, systems as game_systems},
+    game_over_menu::systems as game_over_systems,
+    map_editor::systems as editor_systems,
     waiting_for_more_connections_menu::systems as waiting_systems,
     GameState,
 };
@@ -90,10 +92,12 @@ fn main() {
                 // Countdown state before sending the first countdown. This is a workaround to make
                 // sure that entities still spawn while this happens
                 .with_system(countdown_systems::spawn_entity_event)
-                .with_system(countdown_systems::insert_map_sync_event)
+                .with_system(countdown_systems::insert_map_chunk_event)
                 .with_system(common_systems::insert_unit_sync_event)
                 .with_system(waiting_systems::receive_waiting_on_players_message)
                 .with_system(waiting_systems::receive_countdown_message)
+                .with_system(waiting_systems::receive_rejoin_notification)
+                .with_system(countdown_systems::receive_reconnect_token)
                 .into(),
         )
         .add_system_set_to_stage(
@@ -117,10 +121,11 @@ fn main() {
             ConditionSet::new()
                 .run_in_state(GameState::CountdownMenu)
                 .with_system(countdown_systems::spawn_entity_event)
-                .with_system(countdown_systems::insert_map_sync_event)
+                .with_system(countdown_systems::insert_map_chunk_event)
                 .with_system(common_systems::insert_unit_sync_event)
                 .with_system(countdown_systems::receive_countdown_message)
                 .with_system(countdown_systems::receive_game_start_notification)
+                .with_system(countdown_systems::receive_reconnect_token)
                 .into(),
         )
         .add_system_set_to_stage(
@@ -141,11 +146,15 @@ fn main() {
             Stage::ReceiveEvents,
             ConditionSet::new()
                 .run_in_state(GameState::Game)
-                .with_system(game_systems::update_map_component_event)
+                .with_system(game_systems::update_map_chunk_event)
                 .with_system(game_systems::update_unit_component_event)
                 .with_system(common_systems::insert_unit_sync_event)
                 .with_system(game_systems::receive_turn_change_notification)
+                .with_system(game_systems::receive_turn_clock_notification)
                 .with_system(game_systems::receive_genome_status_change_notification)
+                .with_system(game_systems::receive_combat_result_notification)
+                .with_system(game_systems::despawn_entity_event)
+                .with_system(game_over_systems::receive_game_over_notification)
                 .into(),
         )
         .add_system_set_to_stage(
@@ -156,9 +165,36 @@ fn main() {
                 .with_system(game_systems::input::pan_camera_system)
                 .with_system(game_systems::input::zoom_camera_system)
                 .with_system(game_systems::input::select_entity)
+                .with_system(game_systems::input::hover_tile)
                 .with_system(game_systems::tile_info::display_info)
+                .with_system(game_systems::path_preview::preview_path)
                 .into(),
         )
         .add_enter_system(GameState::Game, game_systems::spawn_player)
+        // Game over
+        .add_system_set_to_stage(
+            Stage::Frame,
+            ConditionSet::new()
+                .run_in_state(GameState::GameOverMenu)
+                .with_system(game_over_systems::game_over_menu)
+                .with_system(game_systems::input::pan_camera_system)
+                .with_system(game_systems::input::zoom_camera_system)
+                .into(),
+        )
+        // Map editor
+        .add_enter_system(GameState::MapEditor, editor_systems::init)
+        .add_exit_system(GameState::MapEditor, editor_systems::exit)
+        .add_system_set_to_stage(
+            Stage::Frame,
+            ConditionSet::new()
+                .run_in_state(GameState::MapEditor)
+                .with_system(editor_systems::editor_menu)
+                .with_system(editor_systems::paint)
+                .with_system(editor_systems::redraw_map)
+                .with_system(editor_systems::show_layer)
+                .with_system(game_systems::input::pan_camera_system)
+                .with_system(game_systems::input::zoom_camera_system)
+                .into(),
+        )
         .run();
 }
@@ -0,0 +1,17 @@
+use bevy::prelude::*;
+
+/// Marks every entity the map editor spawns, so they can all be cleaned up when it is left
+#[derive(Component)]
+pub struct EditorEntity;
+
+/// A tile of the map being edited
+#[derive(Component)]
+pub struct EditorTile {
+    pub layer: i32,
+}
+
+/// A genome facility or spawn point drawn over the map being edited
+#[derive(Component)]
+pub struct EditorMarker {
+    pub layer: i32,
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use rgj_shared::{
+    behavior::AxialCoordinates,
+    components::genome::AnimalType,
+    map::{MapLayout, MapMetadata},
+    protocol::game_sync::map_sync::TileType,
+    resources::MapConfig,
+};
+
+/// The size of the map the editor starts with
+pub const DEFAULT_EDITOR_SIZE: u16 = 20;
+
+/// What clicking on the map does
+#[derive(Clone, PartialEq)]
+pub enum Brush {
+    Tile(TileType),
+    Facility(AnimalType),
+    EraseFacility,
+    /// Adds a spawn point, or removes the one already there
+    Spawn,
+}
+
+pub struct EditorState {
+    pub layout: MapLayout,
+    /// The layer being shown and painted on
+    pub layer: i32,
+    pub brush: Brush,
+
+    pub width: u16,
+    pub height: u16,
+    pub path: String,
+    /// The outcome of the last save, load or validation
+    pub message: String,
+
+    /// Set when the map changed in a way that needs every tile spawned again
+    pub redraw_tiles: bool,
+    /// Set when genome facilities or spawn points changed
+    pub redraw_markers: bool,
+}
+
+impl Default for EditorState {
+    fn default() -> Self {
+        let config = MapConfig {
+            size_width: DEFAULT_EDITOR_SIZE,
+            size_height: DEFAULT_EDITOR_SIZE,
+        };
+
+        let mut layout = MapLayout::filled(config, TileType::Ocean);
+        layout.metadata = Some(MapMetadata::default());
+        for hex in layout.hexes().collect::<Vec<_>>() {
+            layout.set_tile(hex, 1, TileType::ClearSky);
+        }
+
+        EditorState {
+            layout,
+            layer: 0,
+            brush: Brush::Tile(TileType::Grass),
+
+            width: DEFAULT_EDITOR_SIZE,
+            height: DEFAULT_EDITOR_SIZE,
+            path: "map.ron".to_owned(),
+            message: String::new(),
+
+            redraw_tiles: true,
+            redraw_markers: true,
+        }
+    }
+}
+
+impl EditorState {
+    /// Replaces the map being edited, such as with one loaded from a file
+    pub fn replace_layout(&mut self, mut layout: MapLayout) {
+        if layout.metadata.is_none() {
+            layout.metadata = Some(MapMetadata::default());
+        }
+
+        self.width = layout.config.size_width;
+        self.height = layout.config.size_height;
+        self.layout = layout;
+
+        self.redraw_tiles = true;
+        self.redraw_markers = true;
+    }
+}
+
+/// The entity drawing each tile of the map being edited
+pub struct EditorTiles(pub HashMap<(AxialCoordinates, i32), Entity>);
@@ -0,0 +1,394 @@
+use std::{collections::HashMap, path::Path};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use iyes_loopless::state::NextState;
+use leafwing_input_manager::prelude::*;
+
+use rgj_shared::{
+    components::genome::{unique_genomes, DEER},
+    map::{
+        load::{self, validate},
+        save, Facility, MapMetadata,
+    },
+    protocol::game_sync::map_sync::{TileStructure, TileType, MAP_HEIGHT},
+    resources::MapConfig,
+};
+
+use super::{
+    components::{EditorEntity, EditorMarker, EditorTile},
+    resources::{Brush, EditorState, EditorTiles},
+};
+use crate::{
+    common_systems::{structure_sprite, tile_sprite, tile_transform},
+    game::systems::{
+        input::{cursor_to_axial, default_input_map, Action},
+        Player,
+    },
+    GameState, TileSprites,
+};
+
+const GROUND_TILES: [TileType; 6] = [
+    TileType::Grass,
+    TileType::Forest,
+    TileType::Desert,
+    TileType::Ocean,
+    TileType::River,
+    TileType::DesertOasis,
+];
+
+const SKY_TILES: [TileType; 3] = [TileType::ClearSky, TileType::WindySky, TileType::StormySky];
+
+pub fn init(mut commands: Commands, assets: Res<AssetServer>) {
+    commands
+        .spawn_bundle(OrthographicCameraBundle::new_2d())
+        .insert(EditorEntity);
+
+    commands
+        .spawn()
+        .insert(Player)
+        .insert(EditorEntity)
+        .insert_bundle(InputManagerBundle::<Action> {
+            action_state: ActionState::default(),
+            input_map: default_input_map(),
+        });
+
+    commands.insert_resource(TileSprites::load(&assets));
+    commands.insert_resource(EditorState::default());
+    commands.insert_resource(EditorTiles(HashMap::new()));
+}
+
+pub fn exit(mut commands: Commands, query: Query<Entity, With<EditorEntity>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    commands.remove_resource::<EditorState>();
+    commands.remove_resource::<EditorTiles>();
+}
+
+/// Spawns the map again after it was resized or loaded, and redraws genome facilities and spawn
+/// points when they change
+pub fn redraw_map(
+    mut commands: Commands,
+    mut state: ResMut<EditorState>,
+    mut tiles: ResMut<EditorTiles>,
+    sprites: Res<TileSprites>,
+
+    tile_query: Query<Entity, With<EditorTile>>,
+    marker_query: Query<Entity, With<EditorMarker>>,
+) {
+    if state.redraw_tiles {
+        state.redraw_tiles = false;
+
+        for entity in tile_query.iter() {
+            commands.entity(entity).despawn();
+        }
+        tiles.0.clear();
+
+        for hex in state.layout.hexes() {
+            for layer in 0..MAP_HEIGHT {
+                let entity = commands
+                    .spawn_bundle(tile_sprite(
+                        hex,
+                        layer,
+                        state.layout.tile(hex, layer),
+                        &sprites,
+                    ))
+                    .insert(EditorTile { layer })
+                    .insert(EditorEntity)
+                    .id();
+
+                tiles.0.insert((hex, layer), entity);
+            }
+        }
+    }
+
+    if state.redraw_markers {
+        state.redraw_markers = false;
+
+        for entity in marker_query.iter() {
+            commands.entity(entity).despawn();
+        }
+
+        for facility in &state.layout.facilities {
+            let transform = tile_transform(facility.position, facility.layer);
+            // Facilities from legacy maps don't have a genome until the game starts, but all
+            // facilities are drawn the same
+            let structure = TileStructure::GenomeFacility {
+                unique_genome: facility.genome.clone().unwrap_or_else(|| DEER.clone()),
+                building: None,
+            };
+
+            commands
+                .spawn_bundle(structure_sprite(&structure, transform))
+                .insert(EditorMarker {
+                    layer: facility.layer,
+                })
+                .insert(EditorEntity);
+        }
+
+        for spawn in &state.layout.spawns {
+            let mut transform = tile_transform(*spawn, 0);
+            transform.translation.z += 0.2;
+
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgba(1.0, 0.2, 0.2, 0.8),
+                        custom_size: Some(Vec2::new(30.0, 30.0)),
+                        ..Default::default()
+                    },
+                    transform,
+                    ..Default::default()
+                })
+                .insert(EditorMarker { layer: 0 })
+                .insert(EditorEntity);
+        }
+    }
+}
+
+/// Only shows the layer being edited
+pub fn show_layer(
+    state: Res<EditorState>,
+    mut tile_query: Query<(&EditorTile, &mut Visibility)>,
+    mut marker_query: Query<(&EditorMarker, &mut Visibility), Without<EditorTile>>,
+) {
+    for (tile, mut visibility) in tile_query.iter_mut() {
+        visibility.is_visible = tile.layer == state.layer;
+    }
+
+    for (marker, mut visibility) in marker_query.iter_mut() {
+        visibility.is_visible = marker.layer == state.layer;
+    }
+}
+
+/// Applies the brush to the hex under the cursor
+pub fn paint(
+    mut state: ResMut<EditorState>,
+    tiles: Res<EditorTiles>,
+    sprites: Res<TileSprites>,
+
+    action_query: Query<&ActionState<Action>, With<Player>>,
+    camera_query: Query<(&GlobalTransform, &OrthographicProjection)>,
+    mut handle_query: Query<&mut Handle<Image>>,
+
+    windows: Res<Windows>,
+    mut egui_context: ResMut<EguiContext>,
+) {
+    if egui_context.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+
+    let action_state = action_query.single();
+    if !action_state.pressed(Action::Select) {
+        return;
+    }
+
+    let window = windows.get_primary().unwrap();
+    let (camera_trans, camera_proj) = camera_query.get_single().unwrap();
+
+    let qr = match cursor_to_axial(window, camera_trans, camera_proj) {
+        Some(qr) if qr.is_in_bounds(&state.layout.config) => qr,
+        _ => return,
+    };
+    let layer = state.layer;
+
+    match state.brush.clone() {
+        Brush::Tile(tile) => {
+            if state.layout.tile(qr, layer) != tile {
+                state.layout.set_tile(qr, layer, tile);
+
+                if let Some(mut handle) = tiles
+                    .0
+                    .get(&(qr, layer))
+                    .and_then(|entity| handle_query.get_mut(*entity).ok())
+                {
+                    *handle = sprites.for_tile(tile).clone();
+                }
+            }
+        }
+        Brush::Facility(genome) => {
+            let facilities = &mut state.layout.facilities;
+            let unchanged = facilities.iter().any(|facility| {
+                facility.position == qr
+                    && facility.layer == layer
+                    && facility.genome.as_ref() == Some(&genome)
+            });
+
+            if !unchanged {
+                // Each genome is only held by one facility
+                facilities.retain(|facility| {
+                    !(facility.position == qr && facility.layer == layer)
+                        && facility.genome.as_ref() != Some(&genome)
+                });
+                facilities.push(Facility {
+                    position: qr,
+                    layer,
+                    genome: Some(genome),
+                });
+
+                state.redraw_markers = true;
+            }
+        }
+        Brush::EraseFacility => {
+            if state.layout.facility(qr, layer).is_some() {
+                state
+                    .layout
+                    .facilities
+                    .retain(|facility| !(facility.position == qr && facility.layer == layer));
+
+                state.redraw_markers = true;
+            }
+        }
+        Brush::Spawn => {
+            if layer == 0 && action_state.just_pressed(Action::Select) {
+                if state.layout.spawns.contains(&qr) {
+                    state.layout.spawns.retain(|spawn| *spawn != qr);
+                } else {
+                    state.layout.spawns.push(qr);
+                }
+
+                state.redraw_markers = true;
+            }
+        }
+    }
+}
+
+pub fn editor_menu(
+    mut commands: Commands,
+    mut state: ResMut<EditorState>,
+    mut egui_context: ResMut<EguiContext>,
+) {
+    let state = &mut *state;
+
+    egui::Window::new("Map Editor").show(egui_context.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Layer");
+            ui.selectable_value(&mut state.layer, 0, "Ground");
+            ui.selectable_value(&mut state.layer, 1, "Sky");
+        });
+
+        let layer_tiles: &[TileType] = if state.layer == 0 {
+            &GROUND_TILES
+        } else {
+            &SKY_TILES
+        };
+
+        // Ground tiles can't be painted into the sky, nor sky into the ground
+        if let Brush::Tile(tile) = state.brush {
+            if !layer_tiles.contains(&tile) {
+                state.brush = Brush::Tile(layer_tiles[0]);
+            }
+        }
+
+        ui.separator();
+
+        ui.label("Terrain");
+        ui.horizontal_wrapped(|ui| {
+            for tile in layer_tiles {
+                ui.selectable_value(&mut state.brush, Brush::Tile(*tile), format!("{:?}", tile));
+            }
+        });
+
+        ui.label("Genome facilities");
+        ui.horizontal_wrapped(|ui| {
+            for genome in unique_genomes() {
+                let placed = state
+                    .layout
+                    .facilities
+                    .iter()
+                    .any(|facility| facility.genome.as_ref() == Some(&genome));
+                let label = if placed {
+                    format!("{} (placed)", genome.name)
+                } else {
+                    genome.name.clone()
+                };
+
+                ui.selectable_value(&mut state.brush, Brush::Facility(genome), label);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut state.brush, Brush::EraseFacility, "Erase facility");
+            ui.selectable_value(&mut state.brush, Brush::Spawn, "Spawn point");
+        });
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("Width");
+            ui.add(egui::DragValue::new(&mut state.width).clamp_range(1..=200));
+            ui.label("Height");
+            ui.add(egui::DragValue::new(&mut state.height).clamp_range(1..=200));
+
+            if ui.button("Resize").clicked() {
+                let config = MapConfig {
+                    size_width: state.width,
+                    size_height: state.height,
+                };
+                let layout = state
+                    .layout
+                    .resized(config, TileType::Ocean, TileType::ClearSky);
+                state.replace_layout(layout);
+            }
+        });
+
+        let metadata = state
+            .layout
+            .metadata
+            .get_or_insert_with(MapMetadata::default);
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut metadata.name);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Author");
+            ui.text_edit_singleline(&mut metadata.author);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Recommended players");
+            ui.add(egui::DragValue::new(&mut metadata.recommended_players).clamp_range(1..=16));
+        });
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("File");
+            ui.text_edit_singleline(&mut state.path);
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Validate").clicked() {
+                state.message = match validate(&state.layout) {
+                    Ok(()) => "The map is valid".to_owned(),
+                    Err(e) => e.to_string(),
+                };
+            }
+
+            if ui.button("Save").clicked() {
+                state.message = match save::save(&state.layout, Path::new(&state.path)) {
+                    Ok(()) => format!("Saved to {}", state.path),
+                    Err(e) => e.to_string(),
+                };
+            }
+
+            if ui.button("Load").clicked() {
+                match load::load(Path::new(&state.path)) {
+                    Ok(layout) => {
+                        state.replace_layout(layout);
+                        state.message = format!("Loaded {}", state.path);
+                    }
+                    Err(e) => state.message = e.to_string(),
+                }
+            }
+        });
+
+        ui.label(&state.message);
+
+        ui.separator();
+
+        if ui.button("Back").clicked() {
+            commands.insert_resource(NextState(GameState::ConnectMenu));
+        }
+    });
+}
@@ -9,8 +9,9 @@ use naia_bevy_client::{
 };
 
 use crate::{
-    game::resources::Map, waiting_for_more_connections_menu::resources::WaitingFor,
-    ConnectionInformation, GameState, TileSprites, UnitSprites,
+    countdown_menu::systems::start_game, game::resources::Map,
+    waiting_for_more_connections_menu::resources::WaitingFor, ConnectionInformation, GameState,
+    TileSprites, UnitSprites,
 };
 use rgj_shared::{
     protocol::{ClientKeepAlive, Identification, Protocol, ProtocolKind},
@@ -26,7 +27,9 @@ pub fn init(
 ) {
     client.auth(Identification::new_complete(
         std::mem::take(&mut conn_info.username),
+        std::mem::take(&mut conn_info.room_name),
         std::mem::take(&mut conn_info.room_password),
+        conn_info.reconnect_token,
     ));
     client.connect(&format!("http://{}", conn_info.socket_addr.unwrap()));
 
@@ -34,33 +37,10 @@ pub fn init(
     commands.insert_resource(Map {
         coords_to_tile: HashMap::new(),
         coords_to_unit: HashMap::new(),
+        unit_to_coords: HashMap::new(),
     });
 
-    let beach = assets.load("tiles/BeachHex.png");
-    let clear_sky = assets.load("tiles/ClearSkyHex.png");
-    let desert = assets.load("tiles/DesertHex.png");
-    let fog = assets.load("tiles/FogHex.png");
-    let forest = assets.load("tiles/ForestHex.png");
-    let grass = assets.load("tiles/GrassHex.png");
-    let island = assets.load("tiles/IslandHex.png");
-    let oasis = assets.load("tiles/OasisHex.png");
-    let ocean = assets.load("tiles/OceanHex.png");
-    let stormy_sky = assets.load("tiles/StormySkyHex.png");
-    let windy_sky = assets.load("tiles/WindySkyHex.png");
-
-    commands.insert_resource(TileSprites {
-        beach,
-        clear_sky,
-        desert,
-        fog,
-        forest,
-        grass,
-        island,
-        oasis,
-        ocean,
-        stormy_sky,
-        windy_sky,
-    });
+    commands.insert_resource(TileSprites::load(&assets));
 
     let bg_red = assets.load("unit_backgrounds/RedTeam.png");
     let bg_orange = assets.load("unit_backgrounds/OrangeTeam.png");
@@ -144,6 +124,25 @@ pub fn receive_countdown_message(
     }
 }
 
+/// Skips straight into the game when reconnecting to one that is already being played
+pub fn receive_rejoin_notification(
+    mut event_reader: EventReader<MessageEvent<Protocol, Channels>>,
+    mut commands: Commands,
+) {
+    for event in event_reader.iter() {
+        if let MessageEvent(Channels::GameNotification, Protocol::RejoinNotification(rn)) = event {
+            info!("Rejoined the game, whose seed is {}", *rn.seed);
+
+            start_game(
+                &mut commands,
+                &rn.whose_turn,
+                *rn.seed,
+                (*rn.unlocked_genomes).clone(),
+            );
+        }
+    }
+}
+
 pub fn waiting_for_more_connections_menu(
     waiting_for: Res<WaitingFor>,
     mut egui_context: ResMut<EguiContext>,
@@ -0,0 +1,199 @@
+//! A client without a window that is driven a frame at a time from ordinary Rust code
+
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use bevy::{app::ScheduleRunnerSettings, prelude::*};
+use naia_bevy_client::{Client, ClientConfig, Plugin as ClientPlugin, Stage};
+use thiserror::Error;
+
+use rgj_shared::{
+    protocol::{
+        player_input::PlayerInputVariant, ClientKeepAlive, Identification, PlayerInput, Protocol,
+        SendChat,
+    },
+    shared_config, Channels,
+};
+
+use crate::view::{self, GameView};
+
+/// How long to sleep between frames while waiting on the server, so that waiting doesn't spin
+const FRAME_TIME: Duration = Duration::from_millis(5);
+
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    pub username: String,
+    /// The match to join, which is started if nobody is playing in a room of that name yet
+    pub room_name: String,
+    pub room_password: String,
+    /// The token handed out when the game started, to take back a seat after losing the connection
+    pub reconnect_token: Option<u64>,
+}
+
+#[derive(Debug, Error)]
+#[error("gave up after waiting {0:?} for the server")]
+pub struct Timeout(pub Duration);
+
+/// Something to send to the server on the next tick
+enum Outgoing {
+    Input(Option<Entity>, PlayerInputVariant),
+    Chat(String),
+}
+
+#[derive(Default)]
+struct Outbox(Vec<Outgoing>);
+
+/// The server a [`HeadlessPlugin`] connects to
+pub struct ServerAddress(pub SocketAddr);
+
+/// Connects to a server and keeps a [`GameView`] of everything it sends, with no window, sprites or
+/// audio. Nothing happens between calls, so call [`HeadlessClient::update`] or one of the methods
+/// which wait often enough for the server not to time the connection out.
+pub struct HeadlessClient {
+    app: App,
+}
+
+impl HeadlessClient {
+    /// Starts connecting to the server. The connection is made over the next few updates.
+    pub fn connect(server: SocketAddr, credentials: Credentials) -> HeadlessClient {
+        let mut app = App::new();
+
+        app.insert_resource(ScheduleRunnerSettings::run_once())
+            .add_plugins(MinimalPlugins)
+            .add_plugin(HeadlessPlugin)
+            .insert_resource(ServerAddress(server))
+            .insert_resource(credentials);
+
+        HeadlessClient { app }
+    }
+
+    /// Runs a single frame, taking in whatever the server sent and sending anything queued up
+    pub fn update(&mut self) {
+        self.app.update();
+    }
+
+    /// The app behind the client, for adding plugins and systems of one's own such as logging
+    pub fn app_mut(&mut self) -> &mut App {
+        &mut self.app
+    }
+
+    pub fn view(&self) -> &GameView {
+        self.app.world.resource::<GameView>()
+    }
+
+    /// Sends a turn's worth of input, about the given unit if it needs one
+    pub fn send_input(&mut self, unit: Option<Entity>, input: PlayerInputVariant) {
+        self.outbox().push(Outgoing::Input(unit, input));
+    }
+
+    pub fn send_chat(&mut self, message: impl Into<String>) {
+        self.outbox().push(Outgoing::Chat(message.into()));
+    }
+
+    /// Updates until `condition` holds for the view, or until `timeout` has passed
+    pub fn update_until(
+        &mut self,
+        timeout: Duration,
+        mut condition: impl FnMut(&GameView) -> bool,
+    ) -> Result<(), Timeout> {
+        let started = Instant::now();
+
+        loop {
+            self.update();
+
+            if condition(self.view()) {
+                return Ok(());
+            }
+            if started.elapsed() > timeout {
+                return Err(Timeout(timeout));
+            }
+
+            std::thread::sleep(FRAME_TIME);
+        }
+    }
+
+    /// Keeps the connection alive for the given time
+    pub fn update_for(&mut self, duration: Duration) {
+        let _ = self.update_until(duration, |_| false);
+    }
+
+    fn outbox(&mut self) -> &mut Vec<Outgoing> {
+        &mut self.app.world.resource_mut::<Outbox>().into_inner().0
+    }
+}
+
+/// The systems behind [`HeadlessClient`], for adding a headless client to an existing [`App`]. The
+/// [`Credentials`] and [`ServerAddress`] resources must be inserted along with the plugin.
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(ClientPlugin::<Protocol, Channels>::new(
+            ClientConfig::default(),
+            shared_config(),
+        ))
+        .init_resource::<GameView>()
+        .init_resource::<Outbox>()
+        .add_startup_system(connect)
+        .add_system_to_stage(Stage::Connection, view::connection_event)
+        .add_system_to_stage(Stage::Rejection, view::rejection_event)
+        .add_system_to_stage(Stage::Disconnection, view::disconnection_event)
+        .add_system_set_to_stage(
+            Stage::ReceiveEvents,
+            SystemSet::new()
+                .with_system(view::receive_message_event)
+                .with_system(view::insert_component_event)
+                .with_system(view::update_component_event)
+                .with_system(view::despawn_entity_event),
+        )
+        .add_system_to_stage(Stage::Tick, send_outbox);
+    }
+}
+
+fn connect(
+    mut client: Client<Protocol, Channels>,
+
+    credentials: Res<Credentials>,
+    server: Res<ServerAddress>,
+    mut view: ResMut<GameView>,
+) {
+    view.username = credentials.username.clone();
+
+    client.auth(Identification::new_complete(
+        credentials.username.clone(),
+        credentials.room_name.clone(),
+        credentials.room_password.clone(),
+        credentials.reconnect_token,
+    ));
+
+    client.connect(&format!("http://{}", server.0));
+}
+
+fn send_outbox(
+    mut client: Client<Protocol, Channels>,
+    mut outbox: ResMut<Outbox>,
+    view: Res<GameView>,
+) {
+    // The server drops connections it hasn't heard from in a while before the game starts
+    if view.whose_turn.is_none() {
+        client.send_message(Channels::ClientKeepAlive, &ClientKeepAlive);
+    }
+
+    for outgoing in outbox.0.drain(..) {
+        match outgoing {
+            Outgoing::Input(unit, input) => {
+                let mut input = PlayerInput::new_complete(input);
+                if let Some(unit) = unit {
+                    input.relevant_entity.set(&client, &unit);
+                }
+
+                client.send_message(Channels::PlayerInput, &input);
+            }
+            Outgoing::Chat(message) => {
+                client.send_message(Channels::Chat, &SendChat::new(message));
+            }
+        }
+    }
+}
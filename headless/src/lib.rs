@@ -0,0 +1,11 @@
+//! A client with no window, sprites or audio, for soak tests, load tests and bots written outside
+//! of the server. [`HeadlessClient`] connects to a server and keeps a [`GameView`] of what it has
+//! been told, and [`Script`] plays games written as text files.
+
+pub mod client;
+pub mod script;
+pub mod view;
+
+pub use client::{Credentials, HeadlessClient, HeadlessPlugin, ServerAddress, Timeout};
+pub use script::{Command, Script, ScriptError};
+pub use view::{GameView, TileView, UnitView};
@@ -0,0 +1,88 @@
+use std::{fs, net::SocketAddr, path::PathBuf, time::Duration};
+
+use bevy::log::LogPlugin;
+use clap::Parser;
+
+use rgj_headless::{Credentials, HeadlessClient, Script};
+
+/// How long to wait for the server to accept the connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Parser)]
+struct Args {
+    server: SocketAddr,
+    username: String,
+    room_name: String,
+    room_password: String,
+
+    /// The code shown when the game started, to take back a seat after losing the connection
+    #[clap(long)]
+    reconnect_code: Option<String>,
+
+    /// A script of commands to play. Without one the client only stays connected
+    #[clap(long)]
+    script: Option<PathBuf>,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let reconnect_token = args.reconnect_code.as_ref().map(|code| {
+        u64::from_str_radix(code.trim(), 16).unwrap_or_else(|_| {
+            eprintln!("Reconnect code {} is not valid", code);
+            std::process::exit(1);
+        })
+    });
+
+    let script = args.script.as_ref().map(|path| {
+        let parsed = fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| text.parse::<Script>().map_err(|err| err.to_string()));
+
+        parsed.unwrap_or_else(|err| {
+            eprintln!("Could not load script {}: {}", path.display(), err);
+            std::process::exit(1);
+        })
+    });
+
+    let mut client = HeadlessClient::connect(
+        args.server,
+        Credentials {
+            username: args.username,
+            room_name: args.room_name,
+            room_password: args.room_password,
+            reconnect_token,
+        },
+    );
+    client.app_mut().add_plugin(LogPlugin::default());
+
+    let connected = client.update_until(CONNECT_TIMEOUT, |view| view.connected || view.rejected);
+    if connected.is_err() || client.view().rejected {
+        eprintln!("Could not connect to {}", args.server);
+        std::process::exit(1);
+    }
+
+    match script {
+        Some(script) => {
+            if let Err(err) = script.run(&mut client) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        None => {
+            while !client.view().disconnected && client.view().game_over.is_none() {
+                client.update_for(Duration::from_secs(1));
+            }
+        }
+    }
+
+    if let Some(token) = client.view().reconnect_token {
+        println!("Reconnect code: {:016x}", token);
+    }
+    if let Some((victory, standings)) = &client.view().game_over {
+        println!("Game over: {:?}", victory);
+        for standing in standings {
+            println!("{:?}", standing);
+        }
+    }
+}
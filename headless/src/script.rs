@@ -0,0 +1,325 @@
+//! Scripted games. A script is a text file with one command per line, played in order by a
+//! [`HeadlessClient`]. Blank lines and lines starting with `#` are skipped. The commands are:
+//!
+//! - `wait turn`: waits for this player's turn
+//! - `wait secs <secs>`: waits for the given number of seconds
+//! - `wait over`: waits for the game to end
+//! - `move <q> <r> <layer> <to q> <to r>`: moves the unit on the given hex and layer
+//! - `layer <q> <r> <layer> <to layer>`: takes off or lands the unit on the given hex and layer
+//! - `attack <q> <r> <layer> <target q> <target r>`: attacks with the unit on the given hex and layer
+//! - `build <q> <r> <head> <body> <limbs>`: builds a hybrid of the named genomes on a genome facility
+//! - `chat <message>`: sends a chat message
+//! - `end`: ends the turn
+//!
+//! Every command which changes the game waits for the server to carry it out before the next one
+//! is played, so that a script fails where it goes wrong rather than some lines later.
+
+use std::{str::FromStr, time::Duration};
+
+use bevy::prelude::Entity;
+use thiserror::Error;
+
+use rgj_shared::{
+    behavior::AxialCoordinates,
+    components::genome::{unique_genome, AnimalType, Hybrid, DEER},
+    protocol::{game_sync::map_sync::TileStructure, player_input::PlayerInputVariant},
+};
+
+use crate::{client::HeadlessClient, view::GameView};
+
+/// How long a command may take to be carried out before the script gives up
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for this player's turn or the end of the game
+const TURN_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    WaitTurn,
+    WaitSecs(u64),
+    WaitOver,
+    Move {
+        qr: AxialCoordinates,
+        layer: i32,
+        to: AxialCoordinates,
+    },
+    ChangeLayer {
+        qr: AxialCoordinates,
+        layer: i32,
+        to: i32,
+    },
+    Attack {
+        qr: AxialCoordinates,
+        layer: i32,
+        target: AxialCoordinates,
+    },
+    Build {
+        qr: AxialCoordinates,
+        hybrid: Hybrid,
+    },
+    Chat(String),
+    EndTurn,
+}
+
+#[derive(Debug, Error)]
+pub enum ScriptError {
+    #[error("line {line}: unknown command `{command}`")]
+    UnknownCommand { line: usize, command: String },
+    #[error("line {line}: expected {expected} arguments")]
+    WrongArguments { line: usize, expected: usize },
+    #[error("line {line}: `{argument}` is not a number")]
+    NotANumber { line: usize, argument: String },
+    #[error("line {line}: there is no genome called `{name}`")]
+    UnknownGenome { line: usize, name: String },
+
+    #[error("line {line}: there is no unit of yours on ({q}, {r}) layer {layer}")]
+    NoUnit {
+        line: usize,
+        q: i32,
+        r: i32,
+        layer: i32,
+    },
+    #[error("line {line}: it is not your turn")]
+    NotYourTurn { line: usize },
+    #[error("line {line}: the server did not carry the command out in time")]
+    NotCarriedOut { line: usize },
+    #[error("the connection to the server was lost")]
+    Disconnected,
+}
+
+/// A parsed script, each command with the line it came from
+pub struct Script {
+    commands: Vec<(usize, Command)>,
+}
+
+impl FromStr for Script {
+    type Err = ScriptError;
+
+    fn from_str(text: &str) -> Result<Script, ScriptError> {
+        let mut commands = Vec::new();
+
+        for (index, text) in text.lines().enumerate() {
+            let line = index + 1;
+            let text = text.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+
+            commands.push((line, parse_command(line, text)?));
+        }
+
+        Ok(Script { commands })
+    }
+}
+
+impl Script {
+    pub fn commands(&self) -> impl Iterator<Item = &Command> {
+        self.commands.iter().map(|(_, command)| command)
+    }
+
+    /// Plays every command in order, stopping at the first which fails
+    pub fn run(&self, client: &mut HeadlessClient) -> Result<(), ScriptError> {
+        for (line, command) in &self.commands {
+            run_command(client, *line, command)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_command(line: usize, text: &str) -> Result<Command, ScriptError> {
+    let (command, rest) = text.split_once(' ').unwrap_or((text, ""));
+    let args: Vec<&str> = rest.split_whitespace().collect();
+
+    let expect = |expected: usize| {
+        if args.len() == expected {
+            Ok(())
+        } else {
+            Err(ScriptError::WrongArguments { line, expected })
+        }
+    };
+    let number = |index: usize| {
+        args[index]
+            .parse::<i64>()
+            .map_err(|_| ScriptError::NotANumber {
+                line,
+                argument: args[index].to_owned(),
+            })
+    };
+    let hex = |index: usize| -> Result<AxialCoordinates, ScriptError> {
+        Ok(AxialCoordinates::new(
+            number(index)? as i32,
+            number(index + 1)? as i32,
+        ))
+    };
+    let genome = |index: usize| -> Result<AnimalType, ScriptError> {
+        let name = args[index];
+        if name == DEER.name {
+            return Ok(DEER.clone());
+        }
+        unique_genome(name).ok_or_else(|| ScriptError::UnknownGenome {
+            line,
+            name: name.to_owned(),
+        })
+    };
+
+    match (command, args.first().copied()) {
+        ("wait", Some("turn")) => {
+            expect(1)?;
+            Ok(Command::WaitTurn)
+        }
+        ("wait", Some("over")) => {
+            expect(1)?;
+            Ok(Command::WaitOver)
+        }
+        ("wait", Some("secs")) => {
+            expect(2)?;
+            Ok(Command::WaitSecs(number(1)? as u64))
+        }
+        ("move", _) => {
+            expect(5)?;
+            Ok(Command::Move {
+                qr: hex(0)?,
+                layer: number(2)? as i32,
+                to: hex(3)?,
+            })
+        }
+        ("layer", _) => {
+            expect(4)?;
+            Ok(Command::ChangeLayer {
+                qr: hex(0)?,
+                layer: number(2)? as i32,
+                to: number(3)? as i32,
+            })
+        }
+        ("attack", _) => {
+            expect(5)?;
+            Ok(Command::Attack {
+                qr: hex(0)?,
+                layer: number(2)? as i32,
+                target: hex(3)?,
+            })
+        }
+        ("build", _) => {
+            expect(5)?;
+            Ok(Command::Build {
+                qr: hex(0)?,
+                hybrid: Hybrid::new(genome(2)?, genome(3)?, genome(4)?),
+            })
+        }
+        ("chat", _) if !rest.trim().is_empty() => Ok(Command::Chat(rest.trim().to_owned())),
+        ("end", None) => Ok(Command::EndTurn),
+        _ => Err(ScriptError::UnknownCommand {
+            line,
+            command: text.to_owned(),
+        }),
+    }
+}
+
+fn run_command(
+    client: &mut HeadlessClient,
+    line: usize,
+    command: &Command,
+) -> Result<(), ScriptError> {
+    let not_carried_out = |_| ScriptError::NotCarriedOut { line };
+
+    match command {
+        Command::WaitTurn => client
+            .update_until(TURN_TIMEOUT, |view| view.is_my_turn() || view.disconnected)
+            .map_err(not_carried_out)?,
+        Command::WaitSecs(secs) => client.update_for(Duration::from_secs(*secs)),
+        Command::WaitOver => client
+            .update_until(TURN_TIMEOUT, |view| {
+                view.game_over.is_some() || view.disconnected
+            })
+            .map_err(not_carried_out)?,
+        Command::Chat(message) => client.send_chat(message.clone()),
+
+        Command::Move { qr, layer, to } => {
+            let unit = own_unit(client.view(), line, *qr, *layer)?;
+            client.send_input(Some(unit), PlayerInputVariant::MoveEntity(*to));
+            client
+                .update_until(COMMAND_TIMEOUT, |view| {
+                    view.units.get(&unit).map(|unit| unit.position) == Some(*to)
+                })
+                .map_err(not_carried_out)?;
+        }
+        Command::ChangeLayer { qr, layer, to } => {
+            let unit = own_unit(client.view(), line, *qr, *layer)?;
+            client.send_input(Some(unit), PlayerInputVariant::ChangeLayer(*to));
+            client
+                .update_until(COMMAND_TIMEOUT, |view| {
+                    view.units.get(&unit).map(|unit| unit.layer) == Some(*to)
+                })
+                .map_err(not_carried_out)?;
+        }
+        Command::Attack { qr, layer, target } => {
+            let unit = own_unit(client.view(), line, *qr, *layer)?;
+            let combats = client.view().combats.len();
+            client.send_input(Some(unit), PlayerInputVariant::Attack(*target));
+            client
+                .update_until(COMMAND_TIMEOUT, |view| view.combats.len() > combats)
+                .map_err(not_carried_out)?;
+        }
+        Command::Build { qr, hybrid } => {
+            check_turn(client.view(), line)?;
+            client.send_input(None, PlayerInputVariant::BuildHybrid(*qr, hybrid.clone()));
+            client
+                .update_until(COMMAND_TIMEOUT, |view| {
+                    matches!(
+                        view.tile(*qr, 0).map(|tile| &tile.structure),
+                        Some(TileStructure::GenomeFacility {
+                            building: Some(_),
+                            ..
+                        })
+                    )
+                })
+                .map_err(not_carried_out)?;
+        }
+        Command::EndTurn => {
+            check_turn(client.view(), line)?;
+            let turn_number = client.view().turn_number();
+            client.send_input(None, PlayerInputVariant::EndTurn);
+            client
+                .update_until(COMMAND_TIMEOUT, |view| {
+                    !view.is_my_turn()
+                        || view.turn_number() != turn_number
+                        || view.game_over.is_some()
+                })
+                .map_err(not_carried_out)?;
+        }
+    }
+
+    if client.view().disconnected {
+        return Err(ScriptError::Disconnected);
+    }
+
+    Ok(())
+}
+
+fn check_turn(view: &GameView, line: usize) -> Result<(), ScriptError> {
+    if view.is_my_turn() {
+        Ok(())
+    } else {
+        Err(ScriptError::NotYourTurn { line })
+    }
+}
+
+/// The player's own unit on the given hex and layer, if it is their turn to give it orders
+fn own_unit(
+    view: &GameView,
+    line: usize,
+    qr: AxialCoordinates,
+    layer: i32,
+) -> Result<Entity, ScriptError> {
+    check_turn(view, line)?;
+
+    view.unit_at(qr, layer)
+        .filter(|(_, unit)| Some(unit.owner) == view.id())
+        .map(|(entity, _)| entity)
+        .ok_or(ScriptError::NoUnit {
+            line,
+            q: qr.column_q,
+            r: qr.row_r,
+            layer,
+        })
+}
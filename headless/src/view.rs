@@ -0,0 +1,288 @@
+//! Everything the headless client has been told by the server, kept up to date by the systems in
+//! this module as messages and replicated components arrive.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use naia_bevy_client::events::{
+    ConnectionEvent, DespawnEntityEvent, DisconnectionEvent, InsertComponentEvent, MessageEvent,
+    RejectionEvent, UpdateComponentEvent,
+};
+
+use rgj_shared::{
+    behavior::AxialCoordinates,
+    components::{
+        genome::{AnimalType, Hybrid, DEER},
+        players::PlayerId,
+    },
+    protocol::{
+        game_sync::map_sync::{TileStructure, TileType, MAP_HEIGHT},
+        notifications::{
+            combat_result::Combatant,
+            game_over::{Standing, Victory},
+            genome_status_change::LockedStatus,
+            WhoseTurn,
+        },
+        MapChunkSync, Protocol, ProtocolKind, UnitSync,
+    },
+    Channels,
+};
+
+/// A unit as last replicated
+#[derive(Clone, Debug)]
+pub struct UnitView {
+    pub position: AxialCoordinates,
+    pub layer: i32,
+    pub owner: PlayerId,
+    pub hybrid: Hybrid,
+    pub health: u16,
+    pub stamina: u16,
+}
+
+/// A tile of the map as this player perceives it
+#[derive(Clone, Debug)]
+pub struct TileView {
+    pub tile_type: TileType,
+    pub structure: TileStructure,
+    /// Whether the tile was seen before but can't be seen now
+    pub remembered: bool,
+}
+
+/// Everything the server has told this player
+#[derive(Default)]
+pub struct GameView {
+    pub username: String,
+
+    pub connected: bool,
+    /// Set if the server turned the connection down, such as for a wrong password
+    pub rejected: bool,
+    /// Set once a connection that was made has been lost
+    pub disconnected: bool,
+
+    /// How many more players the server is waiting for before the countdown starts
+    pub waiting_on: Option<u8>,
+    pub countdown: Option<u8>,
+    pub reconnect_token: Option<u64>,
+    /// Everybody who connected while this player was waiting, bots included
+    pub players: Vec<(String, PlayerId)>,
+    pub chat: Vec<(Option<PlayerId>, String)>,
+
+    pub seed: Option<u64>,
+    pub whose_turn: Option<WhoseTurn>,
+    /// Seconds left of the current turn and in the bank of the player it belongs to, for timed games
+    pub turn_clock: Option<(u32, u32)>,
+    pub genomes: Vec<AnimalType>,
+    /// Every fight this player's units were in, as the attacker then the defender
+    pub combats: Vec<(Combatant, Combatant)>,
+    pub game_over: Option<(Victory, Vec<Standing>)>,
+
+    pub units: HashMap<Entity, UnitView>,
+    pub tiles: HashMap<(AxialCoordinates, i32), TileView>,
+}
+
+impl GameView {
+    /// The color this player was given, once the server has said so
+    pub fn id(&self) -> Option<PlayerId> {
+        self.players
+            .iter()
+            .find(|(username, _)| *username == self.username)
+            .map(|(_, id)| *id)
+    }
+
+    pub fn is_my_turn(&self) -> bool {
+        matches!(self.whose_turn, Some(WhoseTurn::Yours { .. }))
+    }
+
+    pub fn turn_number(&self) -> Option<u16> {
+        self.whose_turn.as_ref().map(WhoseTurn::turn_number)
+    }
+
+    /// The unit standing on the given hex and layer, if this player can see one there
+    pub fn unit_at(&self, qr: AxialCoordinates, layer: i32) -> Option<(Entity, &UnitView)> {
+        self.units
+            .iter()
+            .find(|(_, unit)| unit.position == qr && unit.layer == layer)
+            .map(|(entity, unit)| (*entity, unit))
+    }
+
+    pub fn tile(&self, qr: AxialCoordinates, layer: i32) -> Option<&TileView> {
+        self.tiles.get(&(qr, layer))
+    }
+}
+
+pub fn connection_event(
+    mut event_reader: EventReader<ConnectionEvent>,
+    mut view: ResMut<GameView>,
+) {
+    for _ in event_reader.iter() {
+        info!("{} connected", view.username);
+        view.connected = true;
+    }
+}
+
+pub fn rejection_event(mut event_reader: EventReader<RejectionEvent>, mut view: ResMut<GameView>) {
+    for _ in event_reader.iter() {
+        warn!("The server rejected {}", view.username);
+        view.rejected = true;
+    }
+}
+
+pub fn disconnection_event(
+    mut event_reader: EventReader<DisconnectionEvent>,
+    mut view: ResMut<GameView>,
+) {
+    for _ in event_reader.iter() {
+        warn!("{} lost their connection", view.username);
+        view.connected = false;
+        view.disconnected = true;
+    }
+}
+
+pub fn receive_message_event(
+    mut event_reader: EventReader<MessageEvent<Protocol, Channels>>,
+    mut view: ResMut<GameView>,
+) {
+    for MessageEvent(_, message) in event_reader.iter() {
+        match message {
+            Protocol::WaitingOnPlayers(waiting) => {
+                view.waiting_on = Some(*waiting.num_waiting_for);
+            }
+            Protocol::ClientConnected(connected) => {
+                view.players
+                    .push(((*connected.username).clone(), *connected.id));
+            }
+            Protocol::Countdown(countdown) => {
+                view.countdown = Some(*countdown.secs_left);
+            }
+            Protocol::ReconnectToken(token) => {
+                view.reconnect_token = Some(*token.token);
+            }
+            Protocol::ReceiveChat(chat) => {
+                view.chat
+                    .push((*chat.sending_player, (*chat.message).clone()));
+            }
+
+            Protocol::GameStartNotification(start) => {
+                view.seed = Some(*start.seed);
+                view.whose_turn = Some((*start.whose_turn).clone());
+                view.genomes = vec![DEER.clone()];
+            }
+            Protocol::RejoinNotification(rejoin) => {
+                view.seed = Some(*rejoin.seed);
+                view.whose_turn = Some((*rejoin.whose_turn).clone());
+                view.genomes = (*rejoin.unlocked_genomes).clone();
+            }
+            Protocol::TurnChangeNotification(change) => {
+                view.whose_turn = Some((*change.whose_turn).clone());
+            }
+            Protocol::TurnClockNotification(clock) => {
+                view.turn_clock = Some((*clock.turn_secs_left, *clock.bank_secs_left));
+            }
+            Protocol::GenomeStatusChange(change) => match *change.status {
+                LockedStatus::Unlocked => view.genomes.push((*change.species).clone()),
+                LockedStatus::Locked => {
+                    if let Some(index) = view
+                        .genomes
+                        .iter()
+                        .position(|genome| *genome == *change.species)
+                    {
+                        view.genomes.remove(index);
+                    }
+                }
+            },
+            Protocol::CombatResultNotification(combat) => {
+                view.combats
+                    .push(((*combat.attacker).clone(), (*combat.defender).clone()));
+            }
+            Protocol::GameOverNotification(game_over) => {
+                view.game_over =
+                    Some(((*game_over.victory).clone(), (*game_over.standings).clone()));
+            }
+
+            _ => {}
+        }
+    }
+}
+
+pub fn insert_component_event(
+    mut event_reader: EventReader<InsertComponentEvent<ProtocolKind>>,
+
+    query_unit: Query<&UnitSync>,
+    query_chunk: Query<&MapChunkSync>,
+
+    mut view: ResMut<GameView>,
+) {
+    for InsertComponentEvent(entity, kind) in event_reader.iter() {
+        replicate(&mut view, *entity, kind, &query_unit, &query_chunk);
+    }
+}
+
+pub fn update_component_event(
+    mut event_reader: EventReader<UpdateComponentEvent<ProtocolKind>>,
+
+    query_unit: Query<&UnitSync>,
+    query_chunk: Query<&MapChunkSync>,
+
+    mut view: ResMut<GameView>,
+) {
+    for UpdateComponentEvent(_tick, entity, kind) in event_reader.iter() {
+        replicate(&mut view, *entity, kind, &query_unit, &query_chunk);
+    }
+}
+
+/// Forgets units the server stopped sending, such as those that died or walked out of sight
+pub fn despawn_entity_event(
+    mut event_reader: EventReader<DespawnEntityEvent>,
+    mut view: ResMut<GameView>,
+) {
+    for DespawnEntityEvent(entity) in event_reader.iter() {
+        view.units.remove(entity);
+    }
+}
+
+/// Copies the replicated state of a unit or map chunk into the view
+fn replicate(
+    view: &mut GameView,
+    entity: Entity,
+    kind: &ProtocolKind,
+
+    query_unit: &Query<&UnitSync>,
+    query_chunk: &Query<&MapChunkSync>,
+) {
+    match kind {
+        ProtocolKind::UnitSync => {
+            if let Ok(unit_sync) = query_unit.get(entity) {
+                view.units.insert(
+                    entity,
+                    UnitView {
+                        position: *unit_sync.position,
+                        layer: *unit_sync.layer,
+                        owner: *unit_sync.player_id,
+                        hybrid: (*unit_sync.hybrid_type).clone(),
+                        health: *unit_sync.current_health,
+                        stamina: *unit_sync.stamina_remaining,
+                    },
+                );
+            }
+        }
+
+        ProtocolKind::MapChunkSync => {
+            if let Ok(chunk) = query_chunk.get(entity) {
+                for layer in 0..MAP_HEIGHT {
+                    for qr in chunk.bounds().hexes() {
+                        view.tiles.insert(
+                            (qr, layer),
+                            TileView {
+                                tile_type: chunk.tile(qr, layer),
+                                structure: chunk.structure(qr, layer),
+                                remembered: chunk.is_remembered(qr, layer),
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        _ => {}
+    }
+}
@@ -0,0 +1,237 @@
+use std::{path::PathBuf, process};
+
+use clap::{ArgEnum, Parser, Subcommand};
+use thiserror::Error;
+
+use rgj_shared::{
+    components::genome::{unique_genomes, AnimalType},
+    map::{
+        generate::{generate, MIN_GENERATED_SIZE},
+        load::{load, MapLoadError, MAP_FORMAT_VERSION},
+        save::{save, MapSaveError},
+        MapLayout, MapMetadata,
+    },
+    resources::MapConfig,
+};
+
+mod render;
+mod stats;
+
+/// Checks, inspects and converts map files without starting a server
+#[derive(Parser)]
+struct Args {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Checks maps against the rules the server loads them with. Exits with an error if any of
+    /// them fail
+    Validate {
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Prints the terrain on each layer, the genome facilities and how the land and water are
+    /// divided into regions
+    Stats { file: PathBuf },
+    /// Draws a map as text, or as a PNG of hexes when `--png` is given
+    Render {
+        file: PathBuf,
+        #[clap(long, arg_enum, default_value_t = Layer::Ground)]
+        layer: Layer,
+        /// Write a PNG to this path instead of printing text
+        #[clap(long)]
+        png: Option<PathBuf>,
+        /// The distance from the center of each hex to its corners in the PNG, in pixels
+        #[clap(long, default_value_t = 12)]
+        hex_size: u32,
+    },
+    /// Converts a map in any format to a versioned map file. Facilities without a genome are
+    /// given the genomes no other facility holds.
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+        /// The version of the map format to write
+        #[clap(long, default_value_t = MAP_FORMAT_VERSION)]
+        to_version: u32,
+    },
+    /// Runs the procedural generator, printing the map or saving it when `--output` is given
+    Generate {
+        size_x: u16,
+        size_y: u16,
+        #[clap(long, default_value_t = 0)]
+        seed: u64,
+        #[clap(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Ground,
+    Sky,
+}
+
+impl Layer {
+    pub fn index(self) -> i32 {
+        match self {
+            Layer::Ground => 0,
+            Layer::Sky => 1,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+enum MapToolError {
+    #[error("{path}: {source}")]
+    Load { path: PathBuf, source: MapLoadError },
+    #[error("{path}: {source}")]
+    Save { path: PathBuf, source: MapSaveError },
+    #[error("{0} maps failed validation")]
+    Invalid(usize),
+    #[error("version {found} of the map format is not supported, the newest is {newest}")]
+    UnsupportedVersion { found: u32, newest: u32 },
+    #[error("generated maps must be at least {min}x{min}")]
+    TooSmall { min: u16 },
+    #[error("could not write the image: {0}")]
+    Image(#[from] image::ImageError),
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if let Err(e) = run(args.command) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run(command: Command) -> Result<(), MapToolError> {
+    match command {
+        Command::Validate { files } => {
+            let mut failed = 0;
+
+            for path in files {
+                match load(&path) {
+                    Ok(_) => println!("{}: ok", path.display()),
+                    Err(e) => {
+                        println!("{}: {}", path.display(), e);
+                        failed += 1;
+                    }
+                }
+            }
+
+            if failed > 0 {
+                return Err(MapToolError::Invalid(failed));
+            }
+        }
+        Command::Stats { file } => {
+            let layout = open(file)?;
+            stats::print(&layout);
+        }
+        Command::Render {
+            file,
+            layer,
+            png,
+            hex_size,
+        } => {
+            let layout = open(file)?;
+
+            match png {
+                Some(path) => render::png(&layout, layer, hex_size).save(path)?,
+                None => print!("{}", render::ascii(&layout, layer)),
+            }
+        }
+        Command::Convert {
+            input,
+            output,
+            to_version,
+        } => {
+            if to_version != MAP_FORMAT_VERSION {
+                return Err(MapToolError::UnsupportedVersion {
+                    found: to_version,
+                    newest: MAP_FORMAT_VERSION,
+                });
+            }
+
+            let mut layout = open(input.clone())?;
+            if layout.metadata.is_none() {
+                layout.metadata = Some(MapMetadata {
+                    name: input
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    ..Default::default()
+                });
+            }
+            assign_genomes(&mut layout);
+
+            write(&layout, output)?;
+        }
+        Command::Generate {
+            size_x,
+            size_y,
+            seed,
+            output,
+        } => {
+            if size_x < MIN_GENERATED_SIZE || size_y < MIN_GENERATED_SIZE {
+                return Err(MapToolError::TooSmall {
+                    min: MIN_GENERATED_SIZE,
+                });
+            }
+
+            let config = MapConfig {
+                size_width: size_x,
+                size_height: size_y,
+            };
+            let mut layout = generate(config, seed);
+
+            match output {
+                Some(path) => {
+                    layout.metadata = Some(MapMetadata {
+                        name: format!("Generated from seed {}", seed),
+                        ..Default::default()
+                    });
+                    assign_genomes(&mut layout);
+
+                    write(&layout, path)?;
+                }
+                None => print!("{}", render::ascii(&layout, Layer::Ground)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn open(path: PathBuf) -> Result<MapLayout, MapToolError> {
+    load(&path).map_err(|source| MapToolError::Load { path, source })
+}
+
+fn write(layout: &MapLayout, path: PathBuf) -> Result<(), MapToolError> {
+    save(layout, &path).map_err(|source| MapToolError::Save { path, source })
+}
+
+/// Gives every facility without a genome one of the genomes not already held by another, in the
+/// order of [`unique_genomes`]
+fn assign_genomes(layout: &mut MapLayout) {
+    let mut free: Vec<AnimalType> = unique_genomes()
+        .into_iter()
+        .rev()
+        .filter(|genome| {
+            !layout
+                .facilities
+                .iter()
+                .any(|facility| facility.genome.as_ref() == Some(genome))
+        })
+        .collect();
+
+    for facility in layout
+        .facilities
+        .iter_mut()
+        .filter(|facility| facility.genome.is_none())
+    {
+        facility.genome = free.pop();
+    }
+}
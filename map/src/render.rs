@@ -0,0 +1,112 @@
+//! Drawing maps the way they look in game, with the last row at the top and each row shifted half a
+//! hex further right than the one below it.
+
+use bevy::{math::Vec2, render::color::Color};
+use image::{Rgba, RgbaImage};
+
+use rgj_shared::{
+    behavior::{AxialCoordinates, HEXAGON_SIZE},
+    map::MapLayout,
+};
+
+use crate::Layer;
+
+const FACILITY_CHARACTER: char = '#';
+const SPAWN_CHARACTER: char = '@';
+
+/// The color genome facilities are drawn in game
+const FACILITY_COLOR: Color = Color::SILVER;
+const SPAWN_COLOR: Color = Color::RED;
+/// How much darker the edges of each hex are than the hex itself
+const EDGE_SHADE: f32 = 0.7;
+
+/// The map as text, using the characters of the map format for tiles
+pub fn ascii(layout: &MapLayout, layer: Layer) -> String {
+    let layer = layer.index();
+    let mut text = String::new();
+
+    for r in (0..layout.config.size_height as i32).rev() {
+        text.push_str(&" ".repeat(r as usize));
+
+        for q in 0..layout.config.size_width as i32 {
+            let hex = AxialCoordinates::new(q, r);
+
+            let character = if layout.facility(hex, layer).is_some() {
+                FACILITY_CHARACTER
+            } else if layer == 0 && layout.spawns.contains(&hex) {
+                SPAWN_CHARACTER
+            } else {
+                layout.tile(hex, layer).to_char().unwrap_or('?')
+            };
+
+            text.push(character);
+            text.push(' ');
+        }
+
+        text.push('\n');
+    }
+
+    text.push_str(&format!(
+        "{} genome facility, {} spawn point\n",
+        FACILITY_CHARACTER, SPAWN_CHARACTER
+    ));
+
+    text
+}
+
+/// The map drawn as hexes `hex_size` pixels from their center to their corners
+pub fn png(layout: &MapLayout, layer: Layer, hex_size: u32) -> RgbaImage {
+    let layer = layer.index();
+
+    let size = hex_size.max(1) as f32;
+    let half_width = f32::sqrt(3.0) / 2.0 * size;
+    let columns = layout.config.size_width as f32 + (layout.config.size_height as f32 - 1.0) / 2.0;
+
+    let width = (2.0 * half_width * columns).ceil() as u32;
+    let height = (size * (1.5 * layout.config.size_height as f32 + 0.5)).ceil() as u32;
+
+    // Hexes are found with the game's own conversion from world space, so pixels are scaled up to
+    // the size the game draws hexes at
+    let scale = HEXAGON_SIZE / size;
+    let hex_at = |x: u32, y: u32| {
+        let world = Vec2::new(
+            x as f32 + 0.5 - half_width,
+            (height - y) as f32 - 0.5 - size,
+        );
+
+        AxialCoordinates::from_world(world * scale)
+    };
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let hex = hex_at(x, y);
+        if !hex.is_in_bounds(&layout.config) {
+            return Rgba([0, 0, 0, 0]);
+        }
+
+        let mut color: Color = layout.tile(hex, layer).into();
+        if layout.facility(hex, layer).is_some() {
+            color = FACILITY_COLOR;
+        } else if layer == 0 && layout.spawns.contains(&hex) {
+            color = SPAWN_COLOR;
+        }
+
+        let edge = (x + 1 < width && hex_at(x + 1, y) != hex)
+            || (y + 1 < height && hex_at(x, y + 1) != hex);
+        if edge {
+            color = Color::rgba(
+                color.r() * EDGE_SHADE,
+                color.g() * EDGE_SHADE,
+                color.b() * EDGE_SHADE,
+                color.a().max(0.5),
+            );
+        }
+
+        let [r, g, b, a] = color.as_rgba_f32();
+        Rgba([
+            (r * 255.0) as u8,
+            (g * 255.0) as u8,
+            (b * 255.0) as u8,
+            (a * 255.0) as u8,
+        ])
+    })
+}
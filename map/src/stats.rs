@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+
+use rgj_shared::{
+    behavior::AxialCoordinates,
+    components::genome::TerrainType,
+    map::MapLayout,
+    protocol::game_sync::map_sync::{TileType, MAP_HEIGHT},
+};
+
+const TILE_TYPES: [TileType; 10] = [
+    TileType::Fog,
+    TileType::Grass,
+    TileType::Forest,
+    TileType::Desert,
+    TileType::Ocean,
+    TileType::River,
+    TileType::DesertOasis,
+    TileType::ClearSky,
+    TileType::WindySky,
+    TileType::StormySky,
+];
+
+/// Prints everything worth knowing about a map when reviewing it
+pub fn print(layout: &MapLayout) {
+    if let Some(metadata) = &layout.metadata {
+        println!(
+            "{} by {}, for {} players",
+            metadata.name, metadata.author, metadata.recommended_players
+        );
+    }
+    println!(
+        "{}x{} hexes",
+        layout.config.size_width, layout.config.size_height
+    );
+
+    let total = layout.hexes().count();
+    for (layer, name) in (0..MAP_HEIGHT).zip(["Ground", "Sky"]) {
+        println!();
+        println!("{} layer:", name);
+
+        for tile in TILE_TYPES {
+            let count = layout
+                .hexes()
+                .filter(|hex| layout.tile(*hex, layer) == tile)
+                .count();
+
+            if count > 0 {
+                println!(
+                    "  {:<12} {:>6} ({:.1}%)",
+                    format!("{:?}", tile),
+                    count,
+                    count as f32 / total as f32 * 100.0
+                );
+            }
+        }
+    }
+
+    println!();
+    println!("{} genome facilities:", layout.facilities.len());
+    for facility in &layout.facilities {
+        let genome = facility
+            .genome
+            .as_ref()
+            .map(|genome| genome.name.as_str())
+            .unwrap_or("random genome");
+
+        println!(
+            "  ({}, {}) on layer {}: {}",
+            facility.position.column_q, facility.position.row_r, facility.layer, genome
+        );
+    }
+
+    println!();
+    println!("{} spawn points", layout.spawns.len());
+
+    for (terrain, name) in [(TerrainType::Ground, "Land"), (TerrainType::Water, "Water")] {
+        let mut sizes: Vec<usize> = regions(layout, terrain)
+            .iter()
+            .map(|region| region.len())
+            .collect();
+        sizes.sort_unstable_by(|a, b| b.cmp(a));
+
+        println!("{} regions: {}, sized {:?}", name, sizes.len(), sizes);
+    }
+}
+
+/// The groups of connected ground-layer tiles that a creature of `terrain` can cross
+pub fn regions(layout: &MapLayout, terrain: TerrainType) -> Vec<Vec<AxialCoordinates>> {
+    let mut seen = HashSet::new();
+    let mut regions = Vec::new();
+
+    for start in layout.hexes() {
+        if layout.tile(start, 0).terrain_type() != Some(terrain) || !seen.insert(start) {
+            continue;
+        }
+
+        let mut region = Vec::new();
+        let mut frontier = vec![start];
+
+        while let Some(hex) = frontier.pop() {
+            region.push(hex);
+
+            for neighbor in hex.neighbors() {
+                if neighbor.is_in_bounds(&layout.config)
+                    && layout.tile(neighbor, 0).terrain_type() == Some(terrain)
+                    && seen.insert(neighbor)
+                {
+                    frontier.push(neighbor);
+                }
+            }
+        }
+
+        regions.push(region);
+    }
+
+    regions
+}
@@ -0,0 +1,339 @@
+//! How bots decide what to do. A bot only knows what its own perceived map shows, and which enemy
+//! units stand on tiles it can currently see, exactly as a client would. It decides on one order
+//! for one unit at a time, so that every order is made knowing how the last one turned out.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+};
+
+use bevy::prelude::*;
+use naia_bevy_server::UserKey;
+
+use rgj_shared::{
+    behavior::{
+        combat::{check_attack, resolve_attack},
+        movement::{step_cost, STAMINA_PER_TURN},
+        AxialCoordinates,
+    },
+    components::genome::{AnimalType, Hybrid},
+    protocol::{
+        game_sync::map_sync::{TileStructure, TileType},
+        player_input::PlayerInputVariant,
+    },
+    resources::MapConfig,
+};
+
+use crate::perspective::Perspectives;
+
+/// How far around a genome facility the terrain is looked at when choosing what to build there
+const SURVEY_RADIUS: u32 = 4;
+
+/// A unit as a bot sees it
+pub struct Unit {
+    pub entity: Entity,
+    pub position: AxialCoordinates,
+    pub layer: i32,
+    pub hybrid: Hybrid,
+    pub health: u16,
+    pub stamina: u16,
+}
+
+/// An order for one of a bot's units, given as the input a client would send for it
+pub struct Order {
+    pub unit: Entity,
+    pub input: PlayerInputVariant,
+}
+
+/// Everything a bot knows when deciding what to do
+pub struct Knowledge<'a, 'w, 's> {
+    seat: UserKey,
+    perspectives: &'a Perspectives<'a, 'w, 's>,
+    map_config: MapConfig,
+
+    units: Vec<Unit>,
+    /// Enemy units standing on tiles the bot can currently see
+    enemies: Vec<Unit>,
+    genomes: &'a [AnimalType],
+    /// Every genome facility the bot has seen
+    facilities: Vec<AxialCoordinates>,
+}
+
+impl<'a, 'w, 's> Knowledge<'a, 'w, 's> {
+    pub fn new(
+        seat: UserKey,
+        perspectives: &'a Perspectives<'a, 'w, 's>,
+        map_config: MapConfig,
+        units: Vec<Unit>,
+        enemies: Vec<Unit>,
+        genomes: &'a [AnimalType],
+    ) -> Knowledge<'a, 'w, 's> {
+        let facilities = hexes(&map_config)
+            .filter(|hex| {
+                matches!(
+                    perspectives.structure(&seat, *hex, 0),
+                    Some(TileStructure::GenomeFacility { .. })
+                )
+            })
+            .collect();
+
+        Knowledge {
+            seat,
+            perspectives,
+            map_config,
+            units,
+            enemies,
+            genomes,
+            facilities,
+        }
+    }
+
+    pub fn units(&self) -> &[Unit] {
+        &self.units
+    }
+
+    fn tile(&self, qr: AxialCoordinates, layer: i32) -> Option<TileType> {
+        self.perspectives.tile(&self.seat, qr, layer)
+    }
+
+    fn is_occupied(&self, qr: AxialCoordinates, layer: i32) -> bool {
+        self.units
+            .iter()
+            .chain(&self.enemies)
+            .any(|unit| unit.position == qr && unit.layer == layer)
+    }
+}
+
+/// What the given unit should do next this turn, if anything. In order of preference a unit
+/// attacks an enemy next to it if the fight is worth it, builds on a genome facility it stands on
+/// and then holds it, heads for a genome facility nobody of its own holds, closes in on an enemy,
+/// and otherwise explores.
+pub fn order(knowledge: &Knowledge, unit: &Unit) -> Option<Order> {
+    let order = |input| {
+        Some(Order {
+            unit: unit.entity,
+            input,
+        })
+    };
+
+    if let Some(target) = best_attack(knowledge, unit) {
+        return order(PlayerInputVariant::Attack(target));
+    }
+
+    if unit.layer == 0 && knowledge.facilities.contains(&unit.position) {
+        return match knowledge
+            .perspectives
+            .structure(&knowledge.seat, unit.position, 0)
+        {
+            Some(TileStructure::GenomeFacility { building: None, .. }) => {
+                let hybrid = best_hybrid(knowledge, unit.position)?;
+                order(PlayerInputVariant::BuildHybrid(unit.position, hybrid))
+            }
+            _ => None,
+        };
+    }
+
+    if unit.stamina == 0 {
+        return None;
+    }
+
+    let reachable = reachable(knowledge, unit);
+
+    let facility = knowledge
+        .facilities
+        .iter()
+        .filter(|facility| {
+            !knowledge
+                .units
+                .iter()
+                .any(|own| own.position == **facility && own.layer == 0)
+        })
+        .min_by_key(|facility| {
+            (
+                facility.distance(unit.position),
+                facility.column_q,
+                facility.row_r,
+            )
+        })
+        .and_then(|facility| step_towards(knowledge, unit, &reachable, *facility));
+
+    let enemy = || {
+        knowledge
+            .enemies
+            .iter()
+            .min_by_key(|enemy| enemy.position.distance(unit.position))
+            .and_then(|enemy| step_towards(knowledge, unit, &reachable, enemy.position))
+    };
+
+    facility
+        .or_else(enemy)
+        .or_else(|| explore(knowledge, unit, &reachable))
+        .and_then(|destination| order(PlayerInputVariant::MoveEntity(destination)))
+}
+
+/// The adjacent enemy most worth attacking: one that would die, or failing that one that would be
+/// hurt more than the unit is hurt back without the unit dying
+fn best_attack(knowledge: &Knowledge, unit: &Unit) -> Option<AxialCoordinates> {
+    knowledge
+        .enemies
+        .iter()
+        .filter(|enemy| {
+            enemy.layer == unit.layer
+                && check_attack(unit.position, unit.stamina, enemy.position).is_ok()
+        })
+        .filter_map(|enemy| {
+            let outcome = resolve_attack(&unit.hybrid, unit.health, &enemy.hybrid, enemy.health);
+            let margin = outcome.damage_dealt as i32 - outcome.damage_taken as i32;
+
+            let worth_it = outcome.defender_died() || (!outcome.attacker_died() && margin >= 0);
+            worth_it.then(|| (outcome.defender_died(), margin, enemy.position))
+        })
+        .max_by_key(|(kills, margin, position)| {
+            (
+                *kills,
+                *margin,
+                Reverse((position.column_q, position.row_r)),
+            )
+        })
+        .map(|(_, _, position)| position)
+}
+
+/// Of every hybrid the bot's genomes allow, the one best suited to the terrain around a genome
+/// facility: the one that crosses it fastest, then the one that hits hardest and lasts longest
+fn best_hybrid(knowledge: &Knowledge, facility: AxialCoordinates) -> Option<Hybrid> {
+    let terrain: Vec<TileType> = facility
+        .range(SURVEY_RADIUS)
+        .into_iter()
+        .filter_map(|hex| knowledge.tile(hex, 0))
+        .filter(|tile| *tile != TileType::Fog)
+        .collect();
+
+    let score = |hybrid: &Hybrid| {
+        // Tiles it can't cross at all count as taking a whole turn
+        let average_cost = terrain
+            .iter()
+            .map(|tile| step_cost(hybrid, *tile).unwrap_or(STAMINA_PER_TURN) as f32)
+            .sum::<f32>()
+            / terrain.len().max(1) as f32;
+        let tiles_per_turn = STAMINA_PER_TURN as f32 / average_cost.max(1.0);
+
+        tiles_per_turn * 2.0 + hybrid.head().attack_damage as f32 + hybrid.body().health as f32
+    };
+
+    let genomes = knowledge.genomes;
+    genomes
+        .iter()
+        .flat_map(|head| {
+            genomes.iter().flat_map(move |body| {
+                genomes
+                    .iter()
+                    .map(move |limbs| Hybrid::new(head.clone(), body.clone(), limbs.clone()))
+            })
+        })
+        .max_by(|a, b| score(a).partial_cmp(&score(b)).unwrap_or(Ordering::Equal))
+}
+
+/// Every hex the unit can walk to this turn on the layer it is on, with the stamina it takes to
+/// get there. Steps are costed over the bot's perceived map exactly as the server checks moves.
+fn reachable(knowledge: &Knowledge, unit: &Unit) -> HashMap<AxialCoordinates, u16> {
+    let mut costs = HashMap::new();
+    let mut frontier = BinaryHeap::new();
+
+    costs.insert(unit.position, 0);
+    frontier.push(Reverse((0u16, unit.position.column_q, unit.position.row_r)));
+
+    while let Some(Reverse((cost, q, r))) = frontier.pop() {
+        let current = AxialCoordinates::new(q, r);
+
+        // Skip stale entries for hexes that have since been reached more cheaply
+        if cost > costs[&current] {
+            continue;
+        }
+
+        for next in current.neighbors() {
+            let step = match knowledge
+                .tile(next, unit.layer)
+                .and_then(|tile| step_cost(&unit.hybrid, tile))
+            {
+                Some(step) => step,
+                None => continue,
+            };
+
+            let new_cost = cost.saturating_add(step);
+            if new_cost <= unit.stamina
+                && costs
+                    .get(&next)
+                    .map(|&old_cost| new_cost < old_cost)
+                    .unwrap_or(true)
+            {
+                costs.insert(next, new_cost);
+                frontier.push(Reverse((new_cost, next.column_q, next.row_r)));
+            }
+        }
+    }
+
+    costs
+}
+
+/// The free hex the unit can reach this turn which is closest to `goal`, if it is any closer than
+/// where the unit already is
+fn step_towards(
+    knowledge: &Knowledge,
+    unit: &Unit,
+    reachable: &HashMap<AxialCoordinates, u16>,
+    goal: AxialCoordinates,
+) -> Option<AxialCoordinates> {
+    free_destinations(knowledge, unit, reachable)
+        .min_by_key(|(hex, cost)| (hex.distance(goal), *cost, hex.column_q, hex.row_r))
+        .filter(|(hex, _)| hex.distance(goal) < unit.position.distance(goal))
+        .map(|(hex, _)| hex)
+}
+
+/// Where the unit would see the most fog from this turn. If fog is out of sight of everywhere it
+/// can reach, it heads for the nearest fog instead.
+fn explore(
+    knowledge: &Knowledge,
+    unit: &Unit,
+    reachable: &HashMap<AxialCoordinates, u16>,
+) -> Option<AxialCoordinates> {
+    let viewing_distance = unit.hybrid.head().viewing_distance as u32;
+    let fog_in_view = |hex: AxialCoordinates| {
+        hex.range(viewing_distance)
+            .into_iter()
+            .filter(|qr| knowledge.tile(*qr, 0) == Some(TileType::Fog))
+            .count()
+    };
+
+    let lookout = free_destinations(knowledge, unit, reachable)
+        .map(|(hex, cost)| (fog_in_view(hex), hex, cost))
+        .filter(|(fog, _, _)| *fog > 0)
+        .max_by_key(|(fog, hex, cost)| (*fog, Reverse((*cost, hex.column_q, hex.row_r))))
+        .map(|(_, hex, _)| hex);
+
+    lookout.or_else(|| {
+        let nearest_fog = hexes(&knowledge.map_config)
+            .filter(|hex| knowledge.tile(*hex, 0) == Some(TileType::Fog))
+            .min_by_key(|hex| (hex.distance(unit.position), hex.column_q, hex.row_r))?;
+
+        step_towards(knowledge, unit, reachable, nearest_fog)
+    })
+}
+
+/// The hexes the unit can reach this turn which no other unit the bot knows of is standing on
+fn free_destinations<'a>(
+    knowledge: &'a Knowledge,
+    unit: &'a Unit,
+    reachable: &'a HashMap<AxialCoordinates, u16>,
+) -> impl Iterator<Item = (AxialCoordinates, u16)> + 'a {
+    reachable
+        .iter()
+        .filter(move |(hex, _)| **hex != unit.position && !knowledge.is_occupied(**hex, unit.layer))
+        .map(|(hex, cost)| (*hex, *cost))
+}
+
+/// Every hex of the map
+fn hexes(map_config: &MapConfig) -> impl Iterator<Item = AxialCoordinates> {
+    let (width, height) = (map_config.size_width as i32, map_config.size_height as i32);
+
+    (0..height).flat_map(move |r| (0..width).map(move |q| AxialCoordinates::new(q, r)))
+}
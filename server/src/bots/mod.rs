@@ -0,0 +1,243 @@
+//! Computer players. Each bot sits in a seat of its own, with a [`UserKey`] no connection is ever
+//! given, and plays its turns by sending the same [`PlayerInput`]s a client would. Bots also play
+//! the turns of players who have lost their connection until they take their seat back.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use naia_bevy_server::{events::MessageEvent, shared::BigMapKey, Server, UserKey};
+
+use rgj_shared::{
+    protocol::{
+        player_input::PlayerInputVariant, ClientConnected, PlayerInput, Protocol, UnitSync,
+    },
+    Channels,
+};
+
+use crate::{
+    matches::Matches,
+    perspective::PerspectiveMaps,
+    resources::{KeyIdAssociation, MatchRoom, UsernameKeyAssociation},
+    waiting_for_connections::events::ID_ORDER,
+    GameState, MAX_PLAYERS,
+};
+
+pub mod brain;
+use brain::{Knowledge, Order, Unit};
+
+/// How many orders a single unit can be given in a turn, so that a bot whose orders keep being
+/// rejected still ends its turn
+const MAX_ORDERS_PER_UNIT: u8 = 4;
+
+/// The seats taken by bots in a match
+#[derive(Default)]
+pub struct Bots {
+    keys: Vec<UserKey>,
+}
+
+impl Bots {
+    /// Seats `count` bots alongside the players already connected to the match with the given
+    /// number, giving each a name and a color nobody has taken
+    pub fn seat(
+        count: u8,
+        match_number: u64,
+        server: &mut Server<Protocol, Channels>,
+        room: &MatchRoom,
+        user_key_assoc: &mut UsernameKeyAssociation,
+        key_id_assoc: &mut KeyIdAssociation,
+    ) -> Bots {
+        let mut keys = Vec::new();
+        let mut free_ids = ID_ORDER
+            .iter()
+            .filter(|id| key_id_assoc.get_from_id(id).is_none())
+            .copied()
+            .collect::<Vec<_>>()
+            .into_iter();
+        let mut number = 1;
+
+        for index in 0..count {
+            // Keys are handed out to connections counting up from zero, so counting down from the
+            // top can never clash with one. Each match counts down from its own block of keys, so
+            // that every bot on the server has a key of its own.
+            let key =
+                UserKey::from_u64(u64::MAX - match_number * MAX_PLAYERS as u64 - index as u64);
+            let id = free_ids
+                .next()
+                .expect("there are never more players than colors");
+
+            let mut username = format!("Bot {}", number);
+            while user_key_assoc.get_from_name(&username).is_some() {
+                number += 1;
+                username = format!("Bot {}", number);
+            }
+            number += 1;
+
+            info!("Seating {}", username);
+
+            for player in room.users() {
+                server.send_message(
+                    &player,
+                    Channels::GameNotification,
+                    &ClientConnected::new(username.clone(), id),
+                );
+            }
+
+            user_key_assoc.insert(username, key);
+            key_id_assoc.insert(key, id);
+            keys.push(key);
+        }
+
+        Bots { keys }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = UserKey> + '_ {
+        self.keys.iter().copied()
+    }
+
+    pub fn contains(&self, key: &UserKey) -> bool {
+        self.keys.contains(key)
+    }
+}
+
+/// What has been ordered so far in the turn being played by a bot
+#[derive(Default)]
+pub struct BotTurn {
+    /// The seat and turn number being played, so that a new turn starts afresh
+    turn: Option<(UserKey, u16)>,
+    orders: HashMap<Entity, u8>,
+    ended: bool,
+}
+
+/// Gives one order a tick in each match for the seat whose turn it is, if that seat is a bot's or
+/// its player has lost their connection. Orders are only given once the last move has finished, and
+/// once no unit has anything left to do the turn is ended.
+pub fn play_turn(
+    server: Server<Protocol, Channels>,
+    mut inputs: EventWriter<MessageEvent<Protocol, Channels>>,
+
+    query_unit: Query<(Entity, &UnitSync)>,
+    perspective_maps: PerspectiveMaps,
+
+    mut matches: ResMut<Matches>,
+) {
+    'matches: for game in matches.in_state_mut(GameState::Playing) {
+        let playing = match &mut game.playing {
+            Some(playing) => playing,
+            None => continue,
+        };
+        let turn_tracker = &playing.turn_tracker;
+        let bot_turn = &mut playing.bot_turn;
+        let seat = turn_tracker.player;
+
+        // With nobody left connected, the game waits for somebody to come back rather than playing
+        // on without them
+        if !(game.bots.contains(&seat) || game.reconnections.is_disconnected(&seat))
+            || game.room.users.is_empty()
+            || playing.move_info.0.is_some()
+            || turn_tracker.is_finished()
+        {
+            continue;
+        }
+
+        let turn = Some((seat, turn_tracker.turn_number));
+        if bot_turn.turn != turn {
+            *bot_turn = BotTurn {
+                turn,
+                ..Default::default()
+            };
+        }
+
+        if bot_turn.ended {
+            continue;
+        }
+
+        let perspectives = perspective_maps.of(&game.key_map_assoc, &game.map_config);
+        let key_units_assoc = &game.key_units_assoc;
+
+        let own_units = key_units_assoc.get_from_key(seat);
+        let unit = |(entity, unit_sync): (Entity, &UnitSync)| Unit {
+            entity,
+            position: *unit_sync.position,
+            layer: *unit_sync.layer,
+            hybrid: (*unit_sync.hybrid_type).clone(),
+            health: *unit_sync.current_health,
+            stamina: *unit_sync.stamina_remaining,
+        };
+
+        let units = own_units
+            .into_iter()
+            .flatten()
+            .filter_map(|entity| query_unit.get(*entity).ok())
+            .map(unit)
+            .collect();
+        // Units of other matches are on maps of their own, so only this match's are enemies
+        let enemies = query_unit
+            .iter()
+            .filter(|(entity, unit_sync)| {
+                key_units_assoc
+                    .get_from_entity(*entity)
+                    .map(|owner| *owner != seat)
+                    .unwrap_or(false)
+                    && perspectives.is_visible(&seat, *unit_sync.position, *unit_sync.layer)
+            })
+            .map(unit)
+            .collect();
+        let genomes = playing
+            .unlocked_genomes
+            .key_to_genomes
+            .get(&seat)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let knowledge = Knowledge::new(
+            seat,
+            &perspectives,
+            game.map_config,
+            units,
+            enemies,
+            genomes,
+        );
+
+        for unit in knowledge.units() {
+            let orders = bot_turn.orders.entry(unit.entity).or_default();
+            if *orders >= MAX_ORDERS_PER_UNIT {
+                continue;
+            }
+
+            match brain::order(&knowledge, unit) {
+                Some(Order { unit, input }) => {
+                    *orders += 1;
+
+                    let mut input = PlayerInput::new_complete(input);
+                    input.relevant_entity.set(&server, &unit);
+                    send(&mut inputs, seat, input);
+
+                    continue 'matches;
+                }
+                // Nothing left for this unit to do this turn
+                None => *orders = MAX_ORDERS_PER_UNIT,
+            }
+        }
+
+        send(
+            &mut inputs,
+            seat,
+            PlayerInput::new_complete(PlayerInputVariant::EndTurn),
+        );
+        bot_turn.ended = true;
+    }
+}
+
+/// Sends input as if it came from the player in the given seat, so that it is checked and carried
+/// out exactly like a client's
+fn send(
+    inputs: &mut EventWriter<MessageEvent<Protocol, Channels>>,
+    seat: UserKey,
+    input: PlayerInput,
+) {
+    inputs.send(MessageEvent(
+        seat,
+        Channels::PlayerInput,
+        Protocol::PlayerInput(input),
+    ));
+}
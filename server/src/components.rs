@@ -1,14 +1,24 @@
 use bevy::prelude::*;
 use naia_bevy_server::UserKey;
 
-use rgj_shared::resources::MapConfig;
-
-/// Component defining an entity as an entire tile map with MapSync entities as children.
+use rgj_shared::{
+    behavior::AxialCoordinates, protocol::game_sync::map_chunk_sync::ChunkBounds,
+    resources::MapConfig,
+};
+
+/// Component defining an entity as an entire tile map. The authoritative map has a MapSync entity
+/// as a child for every tile, while perspectives have a MapChunkSync entity for every chunk in the
+/// order of [`ChunkBounds::all`].
 #[derive(Component)]
 pub struct TileMap {
     pub children: Vec<Entity>,
 }
 impl TileMap {
+    /// The chunk entity of a perspective holding the given hex
+    pub fn chunk_of(&self, map_conf: &MapConfig, qr: AxialCoordinates) -> Entity {
+        self.children[ChunkBounds::index_of(map_conf, qr)]
+    }
+
     /// Utility function for turning xyz coordinates into the index of the 1d [`Vec`] used to
     /// represent the map
     pub fn tile_qrz_to_index(map_conf: &MapConfig, q: i32, r: i32, z: i32) -> usize {
@@ -1,35 +1,40 @@
 use std::time::Duration;
 
 use bevy::{prelude::*, render::texture::DEFAULT_IMAGE_HANDLE};
-use iyes_loopless::state::NextState;
-use naia_bevy_server::{shared::Random, Server};
+use naia_bevy_server::{Server, UserKey};
 
 use rgj_shared::{
-    behavior::AxialCoordinates,
-    components::genome::{Hybrid, CHICKEN, DEER},
+    behavior::{
+        movement::max_stamina,
+        visibility::{Sight, Viewpoint},
+        AxialCoordinates,
+    },
+    components::genome::{CHICKEN, DEER},
     protocol::{
         game_sync::{
-            map_sync::{MapSync, TileStructure, TileType, MAP_HEIGHT},
+            map_chunk_sync::{ChunkBounds, MapChunkSync},
+            map_sync::MapSync,
             unit_sync::UnitSync,
         },
-        Countdown as CountdownPacket, Protocol,
+        Countdown as CountdownPacket, Protocol, ReconnectToken,
     },
-    resources::MapConfig,
     Channels,
 };
 
 use crate::{
     components::{PerspectiveTileMap, TileMap},
-    resources::{KeyIdAssociation, KeyMapAssociation, KeyUnitsAssociation, MainRoom},
-    Args, GameState,
+    matches::Matches,
+    perspective::perceive_chunk,
+    GameState,
 };
 
-pub mod events;
-
 pub mod resources;
-use resources::{Countdown, TimeSinceLastCount};
 
-/// Initializes the countdown state by inserting necessary resources, and loading maps
+pub mod spawns;
+use spawns::{choose_spawns, starting_hybrid};
+
+/// Initializes the countdown state of each match entering it by spawning every player's units and
+/// perceived map
 pub fn init(
     mut commands: Commands,
     mut server: Server<Protocol, Channels>,
@@ -37,185 +42,171 @@ pub fn init(
     query_tilemap: Query<&TileMap>,
     query_tile: Query<&MapSync>,
 
-    args: Res<Args>,
-    main_room: Res<MainRoom>,
-    map_config: Res<MapConfig>,
-    key_id_assoc: Res<KeyIdAssociation>,
-    mut key_map_assoc: ResMut<KeyMapAssociation>,
-    mut key_units_assoc: ResMut<KeyUnitsAssociation>,
+    mut matches: ResMut<Matches>,
 ) {
-    info!("In countdown state -- preparing maps for players");
-
-    let auth_map = &query_tilemap.get(main_room.map_entity).unwrap().children;
-
-    // TODO: Map-aware spawning of intitial units
-    // TODO: Don't spawn players too close
-    let user_count = server.users_count();
-    let mut starting_positions = Vec::with_capacity(user_count);
-    for i in 0..user_count {
-        let q = Random::gen_range_u32(0, map_config.size_width.into()) as i32;
-        let r = Random::gen_range_u32(0, map_config.size_height.into()) as i32;
-
-        starting_positions.push(AxialCoordinates::new(q, r));
-    }
-
-    for (index, key) in server.user_keys().into_iter().enumerate() {
-        let mut sub_map_entities = Vec::with_capacity(
-            map_config.size_width as usize * map_config.size_height as usize * 2,
+    for game in matches.entered_mut(GameState::Countdown) {
+        info!(
+            "{} in countdown state -- preparing maps for players",
+            game.name
         );
 
-        let unit = server
-            .spawn()
-            .enter_room(&main_room.key)
-            .insert(UnitSync::new_complete(
-                starting_positions[index],
-                0,
-                *key_id_assoc.get_from_key(&key).unwrap(),
-                Hybrid::new(DEER.clone(), DEER.clone(), DEER.clone()),
-                DEER.body.health,
-                DEER.limbs.terrain_a.tiles_per_turn.into(),
-            ))
-            .id();
-
-        key_units_assoc.insert(key, unit);
-
-        let viewing_distance = DEER.head.viewing_distance as i32;
-
-        let mut valid_qrs = Vec::new();
-        for q_offset in -viewing_distance..=viewing_distance {
-            for r_offset in std::cmp::max(-viewing_distance, -q_offset - viewing_distance)
-                ..=std::cmp::min(viewing_distance, -q_offset + viewing_distance)
-            {
-                let q = starting_positions[index].column_q as i32 + q_offset;
-                let r = starting_positions[index].row_r as i32 + r_offset;
-
-                if q >= 0 && r >= 0 && q <= i32::MAX.into() && r <= i32::MAX.into() {
-                    valid_qrs.push(AxialCoordinates::new(q as i32, r as i32));
-                }
-            }
-        }
-
-        for z in 0..MAP_HEIGHT as i32 {
-            for r in 0..map_config.size_height as i32 {
-                for q in 0..map_config.size_width as i32 {
-                    let qr = AxialCoordinates::new(q, r);
-
-                    // If the tile is in view of the initial deer entity, send the authoritative
-                    // state, otherwise send fog
-                    if valid_qrs.contains(&qr) {
-                        let map_sync = query_tile
-                            .get(
-                                auth_map[TileMap::tile_qrz_to_index(
-                                    &map_config,
-                                    qr.column_q,
-                                    qr.row_r,
-                                    z,
-                                )],
-                            )
-                            .unwrap()
-                            .clone();
-
-                        sub_map_entities.push(
-                            server
-                                .spawn()
-                                .enter_room(&main_room.key)
-                                .insert(map_sync)
-                                .id(),
-                        );
-                    } else {
-                        sub_map_entities.push(
-                            server
-                                .spawn()
-                                .enter_room(&main_room.key)
-                                .insert(MapSync::new_complete(
-                                    AxialCoordinates::new(q, r),
-                                    z,
-                                    TileType::Fog,
-                                    TileStructure::None,
-                                ))
-                                .id(),
-                        );
-                    }
-                }
+        let auth_map = &query_tilemap.get(game.room.map_entity).unwrap().children;
+        let map_config = game.map_config;
+
+        let seats: Vec<UserKey> = game.room.users().chain(game.bots.keys()).collect();
+
+        let hybrid = starting_hybrid();
+        let starting_positions = choose_spawns(&game.layout, &hybrid, seats.len(), &mut game.rng)
+            .expect("the map was checked for enough starting tiles when the match started");
+
+        for (index, key) in seats.into_iter().enumerate() {
+            let unit = server
+                .spawn()
+                .enter_room(&game.room.key)
+                .insert(UnitSync::new_complete(
+                    starting_positions[index],
+                    0,
+                    *game.key_id_assoc.get_from_key(&key).unwrap(),
+                    hybrid.clone(),
+                    DEER.body.health,
+                    max_stamina(&hybrid),
+                ))
+                .id();
+
+            game.key_units_assoc.insert(key, unit);
+
+            let authoritative = |qr: AxialCoordinates, z| {
+                query_tile
+                    .get(
+                        auth_map[TileMap::tile_qrz_to_index(&map_config, qr.column_q, qr.row_r, z)],
+                    )
+                    .unwrap()
+            };
+
+            // Tiles in sight of the initial deer entity show the authoritative state, and the rest
+            // are fog
+            let mut sight = Sight::new(map_config);
+            sight.set_unit(
+                unit,
+                Viewpoint::new(
+                    starting_positions[index],
+                    0,
+                    DEER.head.viewing_distance as u32,
+                ),
+                |qr, z| {
+                    qr.is_in_bounds(&map_config)
+                        .then(|| *authoritative(qr, z).tile_type)
+                },
+            );
+            sight.take_changed();
+
+            let sub_map_entities = ChunkBounds::all(&map_config)
+                .into_iter()
+                .map(|bounds| {
+                    let (tiles, structures) = perceive_chunk(&bounds, &sight, |qr, z| {
+                        let map_sync = authoritative(qr, z);
+
+                        (*map_sync.tile_type, (*map_sync.structure).clone())
+                    });
+
+                    server
+                        .spawn()
+                        .enter_room(&game.room.key)
+                        .insert(MapChunkSync::new_chunk(bounds, &tiles, structures))
+                        .id()
+                })
+                .collect();
+
+            let subj_map = commands
+                .spawn()
+                .insert(PerspectiveTileMap(key))
+                .insert(TileMap {
+                    children: sub_map_entities,
+                })
+                .id();
+
+            game.key_map_assoc.insert(key, subj_map);
+            game.fog.insert(key, sight);
+
+            if !game.bots.contains(&key) {
+                let token = game
+                    .reconnections
+                    .issue(game.user_key_assoc.get_from_key(&key).unwrap().clone());
+                server.send_message(
+                    &key,
+                    Channels::GameNotification,
+                    &ReconnectToken::new_complete(token),
+                );
             }
         }
 
-        let subj_map = commands
-            .spawn()
-            .insert(PerspectiveTileMap(key))
-            .insert(TileMap {
-                children: sub_map_entities,
-            })
-            .id();
-
-        key_map_assoc.insert(key, subj_map);
+        info!("Done preparing perspectives");
     }
-
-    info!("Done preparing perspectives");
 }
 
-/// Simply does the countdown and handles scoping of all the components inserted above
+/// Simply does the countdown of each match and handles scoping of all the components inserted above
 pub fn tick(
-    mut commands: Commands,
     mut server: Server<Protocol, Channels>,
 
     // Scoping stuff
-    key_map_assoc: Res<KeyMapAssociation>,
     query_tilemap: Query<&TileMap>,
 
-    // Countdown stuff
-    mut countdown: ResMut<Countdown>,
-    mut time: ResMut<TimeSinceLastCount>,
-    key_units_assoc: Res<KeyUnitsAssociation>,
+    mut matches: ResMut<Matches>,
     clock: Res<Time>,
 ) {
-    for (_, user_key, entity) in server.scope_checks() {
-        // Only send updates from tiles in a user's perceived map
-        let tilemap = &query_tilemap
-            .get(*key_map_assoc.get_from_key(&user_key).unwrap())
-            .unwrap()
-            .children;
-
-        let units = key_units_assoc.get_from_key(user_key);
-
-        let mut in_scope = false;
+    let scope_checks = server.scope_checks();
+
+    for game in matches.in_state_mut(GameState::Countdown) {
+        for (_, user_key, entity) in scope_checks
+            .iter()
+            .filter(|(room_key, ..)| *room_key == game.room.key)
+        {
+            // Only send updates from tiles in a user's perceived map
+            let tilemap = &query_tilemap
+                .get(*game.key_map_assoc.get_from_key(user_key).unwrap())
+                .unwrap()
+                .children;
+
+            let units = game.key_units_assoc.get_from_key(*user_key);
+
+            let mut in_scope = false;
+
+            if let Some(units) = units {
+                if units.contains(entity) {
+                    in_scope = true;
+                    server.user_scope(user_key).include(entity);
+                }
+            }
 
-        if let Some(units) = units {
-            if units.contains(&entity) {
+            if tilemap.contains(entity) {
                 in_scope = true;
-                server.user_scope(&user_key).include(&entity);
+                server.user_scope(user_key).include(entity);
             }
-        }
-
-        if tilemap.contains(&entity) {
-            in_scope = true;
-            server.user_scope(&user_key).include(&entity);
-        }
 
-        if !in_scope {
-            server.user_scope(&user_key).exclude(&entity);
+            if !in_scope {
+                server.user_scope(user_key).exclude(entity);
+            }
         }
-    }
 
-    time.0 += Duration::from_secs_f32(clock.delta_seconds() * 1000.0);
+        let time = &mut game.time_since_last_count;
+        time.0 += Duration::from_secs_f32(clock.delta_seconds() * 1000.0);
 
-    if time.0 > Duration::from_secs(1) {
-        time.0 = Duration::from_secs(0);
+        if time.0 > Duration::from_secs(1) {
+            time.0 = Duration::from_secs(0);
 
-        countdown.0 -= 1;
+            game.countdown.0 -= 1;
 
-        if countdown.0 == 0 {
-            commands.insert_resource(NextState(GameState::Playing));
-        }
+            if game.countdown.0 == 0 {
+                game.set_next_state(GameState::Playing);
+            }
 
-        for key in server.user_keys() {
-            server.send_message(
-                &key,
-                Channels::Countdown,
-                &CountdownPacket::new_complete(countdown.0),
-            );
+            for key in game.room.users() {
+                server.send_message(
+                    &key,
+                    Channels::Countdown,
+                    &CountdownPacket::new_complete(game.countdown.0),
+                );
+            }
         }
     }
-
-    server.send_all_updates();
 }
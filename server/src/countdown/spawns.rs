@@ -0,0 +1,190 @@
+//! Choosing where each player's first unit starts.
+//!
+//! Starting positions are only ever tiles the starting hybrid can stand on, kept at least
+//! [`MIN_SPAWN_DISTANCE`] hexes apart where the map allows it. Among the arrangements that manage
+//! that, the one where every player is most nearly as far from their closest genome facility as
+//! everyone else is chosen, so that nobody gets a head start on their first new genome.
+
+use std::collections::HashSet;
+
+use bevy::log::warn;
+use rand::seq::SliceRandom;
+use thiserror::Error;
+
+use rgj_shared::{
+    behavior::{movement::step_cost, AxialCoordinates},
+    components::genome::{Hybrid, DEER},
+    map::MapLayout,
+};
+
+use crate::resources::GameRng;
+
+/// The fewest hexes there should be between any two players' starting positions
+pub const MIN_SPAWN_DISTANCE: u32 = 5;
+
+/// How many random arrangements are tried for each spacing before settling on the fairest
+const ATTEMPTS: usize = 200;
+
+#[derive(Debug, Error)]
+pub enum SpawnError {
+    #[error(
+        "the map only has {tiles} tiles a player can start on, but there are {players} players"
+    )]
+    NotEnoughTiles { players: usize, tiles: usize },
+}
+
+/// The hybrid every player starts with
+pub fn starting_hybrid() -> Hybrid {
+    Hybrid::new(DEER.clone(), DEER.clone(), DEER.clone())
+}
+
+/// The ground-layer tiles `hybrid` could start on. Hybrids only start on the terrain their limbs
+/// are best at, so a deer never begins the game swimming. Genome facilities are left free so that
+/// nobody begins the game already holding one.
+pub fn standable_tiles(layout: &MapLayout, hybrid: &Hybrid) -> Vec<AxialCoordinates> {
+    let native_terrain = hybrid.limbs().terrain_a.terrain_type;
+
+    layout
+        .hexes()
+        .filter(|hex| {
+            let tile = layout.tile(*hex, 0);
+            tile.terrain_type() == Some(native_terrain) && step_cost(hybrid, tile).is_some()
+        })
+        .filter(|hex| layout.facility(*hex, 0).is_none())
+        .collect()
+}
+
+/// Chooses a starting position for each of `players` players.
+///
+/// When the map has spawn points `hybrid` can stand on for everyone, only those are used.
+/// Otherwise any tile from [`standable_tiles`] may be picked. If the players can't all be kept
+/// [`MIN_SPAWN_DISTANCE`] apart, the spacing is relaxed one hex at a time until they fit.
+pub fn choose_spawns(
+    layout: &MapLayout,
+    hybrid: &Hybrid,
+    players: usize,
+    rng: &mut GameRng,
+) -> Result<Vec<AxialCoordinates>, SpawnError> {
+    let standable = standable_tiles(layout, hybrid);
+
+    let spawn_points: Vec<AxialCoordinates> = layout
+        .spawns
+        .iter()
+        .copied()
+        .filter(|hex| standable.contains(hex))
+        .collect();
+
+    let candidates = if spawn_points.len() >= players {
+        spawn_points
+    } else {
+        if !layout.spawns.is_empty() {
+            warn!(
+                "The map has {} usable spawn points for {} players -- ignoring them",
+                spawn_points.len(),
+                players
+            );
+        }
+
+        standable
+    };
+
+    if candidates.len() < players {
+        return Err(SpawnError::NotEnoughTiles {
+            players,
+            tiles: candidates.len(),
+        });
+    }
+
+    for spacing in (1..=MIN_SPAWN_DISTANCE).rev() {
+        if let Some(spawns) = fairest_arrangement(layout, &candidates, players, spacing, rng) {
+            if spacing < MIN_SPAWN_DISTANCE {
+                warn!(
+                    "Players could only be spread {} hexes apart rather than {}",
+                    spacing, MIN_SPAWN_DISTANCE
+                );
+            }
+
+            return Ok(spawns);
+        }
+    }
+
+    // Every candidate is a distinct hex, so a spacing of one always fits
+    unreachable!()
+}
+
+/// Tries many random arrangements of players at least `spacing` hexes apart, returning the one
+/// where the distances from each player to their nearest genome facility differ the least. Ties go
+/// to the arrangement whose closest two players are furthest apart.
+fn fairest_arrangement(
+    layout: &MapLayout,
+    candidates: &[AxialCoordinates],
+    players: usize,
+    spacing: u32,
+    rng: &mut GameRng,
+) -> Option<Vec<AxialCoordinates>> {
+    let mut order = candidates.to_vec();
+    let mut best: Option<((u32, u32), Vec<AxialCoordinates>)> = None;
+    let mut seen = HashSet::new();
+
+    for _ in 0..ATTEMPTS {
+        order.shuffle(rng);
+
+        let mut spawns: Vec<AxialCoordinates> = Vec::with_capacity(players);
+        for hex in &order {
+            if spawns.iter().all(|spawn| spawn.distance(*hex) >= spacing) {
+                spawns.push(*hex);
+
+                if spawns.len() == players {
+                    break;
+                }
+            }
+        }
+
+        if spawns.len() < players {
+            continue;
+        }
+
+        let mut key = spawns.clone();
+        key.sort_by_key(|hex| (hex.column_q, hex.row_r));
+        if !seen.insert(key) {
+            continue;
+        }
+
+        let score = (
+            facility_spread(layout, &spawns),
+            u32::MAX - closest_pair(&spawns),
+        );
+        if best.as_ref().map(|(best, _)| score < *best).unwrap_or(true) {
+            best = Some((score, spawns));
+        }
+    }
+
+    best.map(|(_, spawns)| spawns)
+}
+
+/// The difference between the players furthest from and closest to their nearest genome facility
+fn facility_spread(layout: &MapLayout, spawns: &[AxialCoordinates]) -> u32 {
+    let nearest: Vec<u32> = spawns
+        .iter()
+        .map(|spawn| {
+            layout
+                .facilities
+                .iter()
+                .map(|facility| spawn.distance(facility.position))
+                .min()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    nearest.iter().max().unwrap() - nearest.iter().min().unwrap()
+}
+
+/// The distance between the two players who start closest together
+fn closest_pair(spawns: &[AxialCoordinates]) -> u32 {
+    spawns
+        .iter()
+        .enumerate()
+        .flat_map(|(i, a)| spawns[i + 1..].iter().map(move |b| a.distance(*b)))
+        .min()
+        .unwrap_or(u32::MAX)
+}
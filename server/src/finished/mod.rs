@@ -0,0 +1,15 @@
+//! A module defining systems specific to the Finished GameState, which a match enters once somebody
+//! has won and no longer accepts any input from its players.
+
+use bevy::prelude::*;
+
+use crate::{matches::Matches, GameState};
+
+pub fn init(mut matches: ResMut<Matches>) {
+    for game in matches.entered_mut(GameState::Finished) {
+        info!(
+            "Game over in {} -- no longer accepting input from players",
+            game.name
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use bevy::prelude::*;
+use clap::{Parser, Subcommand};
+use naia_bevy_server::{Plugin as ServerPlugin, ServerConfig, Stage};
+use thiserror::Error;
+
+use rgj_shared::{
+    map::{
+        generate::MIN_GENERATED_SIZE,
+        load::{load, MapLoadError},
+        NUM_FACILITIES,
+    },
+    protocol::Protocol,
+    resources::MapConfig,
+    shared_config, Channels,
+};
+
+pub mod bots;
+pub mod components;
+pub mod matches;
+use matches::Matches;
+pub mod perspective;
+pub mod reconnection;
+pub mod resources;
+use resources::MapSource;
+
+pub mod waiting_for_connections;
+use waiting_for_connections::{
+    events as waiting_events, listen as waiting_listen, tick as waiting_tick,
+};
+
+pub mod countdown;
+use countdown::{
+    init as countdown_init,
+    spawns::{standable_tiles, starting_hybrid},
+    tick as countdown_tick,
+};
+
+pub mod playing;
+use playing::{
+    events as playing_events, finish_game as playing_finish_game, init as playing_init,
+    tick as playing_tick, turn_clock as playing_turn_clock,
+};
+
+pub mod finished;
+use finished::init as finished_init;
+
+pub const MAX_PLAYERS: u8 = 6;
+pub const MIN_PLAYERS: u8 = 2;
+
+#[derive(Parser)]
+pub struct Args {
+    bind_udp: SocketAddr,
+    bind_web_rtc: SocketAddr,
+
+    /// How many people to wait for before starting each match
+    num_players: u8,
+
+    /// How many matches may be played at once. Each is started by the first player to connect
+    /// with a room name nobody else is using
+    #[clap(long, default_value_t = 8)]
+    max_matches: u16,
+    /// Seconds a match which has started may go with nobody connected to it, giving players time
+    /// to reconnect, before it is ended and cleaned up
+    #[clap(long, default_value_t = 60)]
+    abandon_after: u64,
+
+    /// Computer players to fill seats with alongside the `num-players` people
+    #[clap(long, default_value_t = 0)]
+    bots: u8,
+
+    /// Win by holding this many genome facilities for `--hold-turns` turns in a row
+    #[clap(long)]
+    hold_facilities: Option<u8>,
+    /// How many turns in a row `--hold-facilities` genome facilities must be held for to win
+    #[clap(long, default_value_t = 5)]
+    hold_turns: u16,
+
+    /// Ends each player's turn for them after this many seconds. Turns are untimed if not given
+    #[clap(long)]
+    turn_time: Option<u64>,
+    /// Seconds each player may go over `--turn-time` by over the whole game, chess clock style
+    #[clap(long, default_value_t = 0)]
+    time_bank: u64,
+
+    /// Seed for all of the game's randomness, so that a game can be replayed. Chosen at random if
+    /// not given
+    #[clap(long)]
+    seed: Option<u64>,
+
+    #[clap(subcommand)]
+    map_option: MapOption,
+}
+
+#[derive(Subcommand)]
+pub enum MapOption {
+    Generate { size_x: u16, size_y: u16 },
+    Load { file_path: PathBuf },
+}
+
+/// The state of a single match. Each match moves through these on its own, so the server as a whole
+/// is in all of them at once.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum GameState {
+    WaitingForConnections,
+    Countdown,
+    Playing,
+    Finished,
+}
+
+/// Orders the systems which move matches between states, and the systems which tick them
+#[derive(Debug, Clone, Eq, PartialEq, Hash, SystemLabel)]
+enum MatchSystems {
+    ChangeStates,
+    Tick,
+}
+
+/// Everything that can stop a server from starting with the given [`Args`]
+#[derive(Debug, Error)]
+pub enum StartupError {
+    #[error(
+        "Number of players, counting bots, must be between {} and {}",
+        MIN_PLAYERS,
+        MAX_PLAYERS
+    )]
+    SeatCount,
+    #[error("There must be at least one player who isn't a bot")]
+    NoPlayers,
+    #[error("There must be room for at least one match")]
+    NoMatches,
+    #[error(
+        "The number of genome facilities to hold must be between 1 and the {} on every map",
+        NUM_FACILITIES
+    )]
+    HoldFacilities,
+    #[error("Generated maps must be at least {0} by {0} tiles", MIN_GENERATED_SIZE)]
+    MapTooSmall,
+    #[error("Could not load map {}: {}", .path.display(), .source)]
+    MapLoad { path: PathBuf, source: MapLoadError },
+    #[error(
+        "The map only has {starting_tiles} tiles a player can start on, but there are {seats} \
+         players"
+    )]
+    NotEnoughStartingTiles { starting_tiles: usize, seats: usize },
+}
+
+/// Builds the server for the given [`Args`], ready to be run or updated a frame at a time. Logging
+/// is left to the caller, as it can only be set up once per process.
+pub fn app(args: Args) -> Result<App, StartupError> {
+    let seats = args.num_players as usize + args.bots as usize;
+    if seats > MAX_PLAYERS as usize || seats < MIN_PLAYERS as usize {
+        return Err(StartupError::SeatCount);
+    }
+
+    if args.num_players == 0 {
+        return Err(StartupError::NoPlayers);
+    }
+
+    if args.max_matches == 0 {
+        return Err(StartupError::NoMatches);
+    }
+
+    if let Some(facilities) = args.hold_facilities {
+        if facilities == 0 || facilities as usize > NUM_FACILITIES {
+            return Err(StartupError::HoldFacilities);
+        }
+    }
+
+    // Generated maps are made for each match from its own seed, so only maps loaded from a file can
+    // be checked up front
+    let maps = match &args.map_option {
+        MapOption::Generate { size_x, size_y } => {
+            if *size_x < MIN_GENERATED_SIZE || *size_y < MIN_GENERATED_SIZE {
+                return Err(StartupError::MapTooSmall);
+            }
+
+            MapSource::Generate(MapConfig {
+                size_width: *size_x,
+                size_height: *size_y,
+            })
+        }
+
+        MapOption::Load { file_path } => {
+            let layout = load(file_path).map_err(|source| StartupError::MapLoad {
+                path: file_path.clone(),
+                source,
+            })?;
+
+            let starting_tiles = standable_tiles(&layout, &starting_hybrid()).len();
+            if starting_tiles < seats {
+                return Err(StartupError::NotEnoughStartingTiles {
+                    starting_tiles,
+                    seats,
+                });
+            }
+
+            MapSource::Load(layout)
+        }
+    };
+
+    let mut app = App::new();
+
+    app
+        // Basic ECS stuff
+        .add_plugins(MinimalPlugins)
+        // Entity hierarchies and positions in space
+        .add_plugin(HierarchyPlugin::default())
+        .add_plugin(TransformPlugin::default())
+        // naia server plugin
+        .add_plugin(ServerPlugin::<Protocol, Channels>::new(
+            ServerConfig::default(),
+            shared_config(),
+        ))
+        // Insert resources
+        .insert_resource(args)
+        .insert_resource(maps)
+        .init_resource::<Matches>()
+        .add_startup_system(waiting_listen)
+        // Moving matches between states, and setting them up for the state they moved into
+        .add_system_set_to_stage(
+            CoreStage::PreUpdate,
+            SystemSet::new()
+                .with_system(matches::clean_up.before(MatchSystems::ChangeStates))
+                .with_system(matches::change_states.label(MatchSystems::ChangeStates)),
+        )
+        .add_system_set_to_stage(
+            CoreStage::PreUpdate,
+            SystemSet::new()
+                .after(MatchSystems::ChangeStates)
+                .with_system(countdown_init)
+                .with_system(playing_init)
+                .with_system(finished_init),
+        )
+        // Events are handed to the match of whoever they came from
+        .add_system_set_to_stage(
+            Stage::ReceiveEvents,
+            SystemSet::new()
+                // WaitingForConnections state, along with starting new matches
+                .with_system(waiting_events::authorization_event)
+                .with_system(waiting_events::connection_event)
+                .with_system(waiting_events::disconnection_event)
+                .with_system(waiting_events::receive_message_event)
+                // Countdown, Playing and Finished states
+                .with_system(reconnection::authorization_event)
+                .with_system(reconnection::connection_event)
+                .with_system(reconnection::disconnection_event)
+                // Playing state
+                .with_system(playing_events::receive_input_event),
+        )
+        .add_system_set_to_stage(
+            Stage::Tick,
+            SystemSet::new()
+                .label(MatchSystems::Tick)
+                .with_system(waiting_tick)
+                .with_system(countdown_tick)
+                .with_system(playing_tick)
+                .with_system(playing_turn_clock)
+                .with_system(bots::play_turn)
+                .with_system(playing_finish_game),
+        )
+        // Every match's updates go out together, once all of them have been ticked
+        .add_system_to_stage(Stage::Tick, matches::send_updates.after(MatchSystems::Tick));
+
+    Ok(app)
+}
@@ -0,0 +1,330 @@
+//! Every match being played on the server. Each match has a naia room, a map, seats and a
+//! [`GameState`] of its own, and players choose which to join by the room name they connect with. A
+//! match is started by the first player to name its room, and is torn down again once it is over
+//! and everybody has left.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use naia_bevy_server::{Server, UserKey};
+use rand::Rng;
+use thiserror::Error;
+
+use rgj_shared::{
+    map::{generate::generate, MapLayout},
+    protocol::Protocol,
+    resources::MapConfig,
+    Channels,
+};
+
+use crate::{
+    bots::{BotTurn, Bots},
+    components::TileMap,
+    countdown::{
+        resources::{Countdown, TimeSinceLastCount},
+        spawns::{standable_tiles, starting_hybrid},
+    },
+    perspective::FogOfWar,
+    playing::resources::{KeyToUnlockedGenomesMap, TurnTracker, UnitMoveInformation},
+    resources::{
+        GameRng, KeyIdAssociation, KeyMapAssociation, KeyUnitsAssociation, MapSource, MatchRoom,
+        Reconnections, UsernameKeyAssociation,
+    },
+    waiting_for_connections::spawn_map,
+    Args, GameState,
+};
+
+/// Everything only needed once a match is being played
+pub struct Playing {
+    pub turn_tracker: TurnTracker,
+    pub move_info: UnitMoveInformation,
+    pub bot_turn: BotTurn,
+    pub unlocked_genomes: KeyToUnlockedGenomesMap,
+}
+
+/// A single game, with everything that belongs to it
+pub struct Match {
+    pub name: String,
+    password: String,
+    /// Counts up with every match started, so that bots in different matches never share a key
+    pub number: u64,
+
+    state: GameState,
+    next_state: Option<GameState>,
+    /// Whether the match moved into its state this frame
+    entered: bool,
+    /// When the last player connected to the match left, if nobody has come back since
+    empty_since: Option<Instant>,
+
+    pub room: MatchRoom,
+    pub rng: GameRng,
+    pub layout: MapLayout,
+    pub map_config: MapConfig,
+
+    pub user_key_assoc: UsernameKeyAssociation,
+    pub key_id_assoc: KeyIdAssociation,
+    pub key_map_assoc: KeyMapAssociation,
+    pub key_units_assoc: KeyUnitsAssociation,
+
+    pub bots: Bots,
+    pub countdown: Countdown,
+    pub time_since_last_count: TimeSinceLastCount,
+    pub fog: FogOfWar,
+    pub reconnections: Reconnections,
+    /// Only there once the game is being played
+    pub playing: Option<Playing>,
+}
+
+impl Match {
+    pub fn state(&self) -> GameState {
+        self.state
+    }
+
+    /// Moves the match on to the given state at the start of the next frame
+    pub fn set_next_state(&mut self, state: GameState) {
+        self.next_state = Some(state);
+    }
+
+    pub fn is_password(&self, password: &str) -> bool {
+        self.password == password
+    }
+
+    /// Whether the connection with the given key has a seat in this match, or is taking one back
+    pub fn has_user(&self, key: &UserKey) -> bool {
+        self.user_key_assoc.get_from_key(key).is_some() || self.reconnections.is_pending(key)
+    }
+}
+
+/// Everything that can stop a new match from being started
+#[derive(Debug, Error)]
+pub enum MatchError {
+    #[error("There are already {0} matches being played")]
+    TooManyMatches(usize),
+    #[error(
+        "The map generated for {room} only has {starting_tiles} tiles a player can start on, but \
+         there are {seats} players"
+    )]
+    NotEnoughStartingTiles {
+        room: String,
+        starting_tiles: usize,
+        seats: usize,
+    },
+}
+
+/// Every match on the server, by room name
+#[derive(Default)]
+pub struct Matches {
+    matches: HashMap<String, Match>,
+    started: u64,
+}
+
+impl Matches {
+    /// Starts a new match in the room with the given name, which players must give the password
+    /// of to join. Spawns its authoritative map from the [`MapSource`].
+    pub fn start(
+        &mut self,
+        name: String,
+        password: String,
+
+        commands: &mut Commands,
+        server: &mut Server<Protocol, Channels>,
+
+        args: &Args,
+        maps: &MapSource,
+    ) -> Result<&mut Match, MatchError> {
+        if self.matches.len() >= args.max_matches as usize {
+            return Err(MatchError::TooManyMatches(self.matches.len()));
+        }
+
+        let mut rng = GameRng::new(args.seed.unwrap_or_else(rand::random));
+
+        let layout = match maps {
+            MapSource::Generate(config) => generate(*config, rng.gen()),
+            MapSource::Load(layout) => layout.clone(),
+        };
+
+        let seats = args.num_players as usize + args.bots as usize;
+        let starting_tiles = standable_tiles(&layout, &starting_hybrid()).len();
+        if starting_tiles < seats {
+            return Err(MatchError::NotEnoughStartingTiles {
+                room: name,
+                starting_tiles,
+                seats,
+            });
+        }
+
+        info!("Starting match {} with seed {}", name, rng.seed());
+
+        if let Some(metadata) = &layout.metadata {
+            info!(
+                "Playing on {} by {}, made for {} players",
+                metadata.name, metadata.author, metadata.recommended_players
+            );
+
+            if metadata.recommended_players != args.num_players {
+                warn!(
+                    "This map was made for {} players, but {} are expected",
+                    metadata.recommended_players, args.num_players
+                );
+            }
+        }
+
+        let room_key = server.make_room().key();
+        let map_entity = spawn_map(commands, &layout, &mut rng);
+
+        let number = self.started;
+        self.started += 1;
+
+        let game = Match {
+            name: name.clone(),
+            password,
+            number,
+
+            state: GameState::WaitingForConnections,
+            next_state: None,
+            entered: false,
+            empty_since: None,
+
+            room: MatchRoom {
+                key: room_key,
+                map_entity,
+                users: Vec::new(),
+                keep_alives: Vec::new(),
+            },
+            rng,
+            map_config: layout.config,
+            layout,
+
+            user_key_assoc: UsernameKeyAssociation::new(),
+            key_id_assoc: KeyIdAssociation::new(),
+            key_map_assoc: KeyMapAssociation::new(),
+            key_units_assoc: KeyUnitsAssociation::new(),
+
+            bots: Bots::default(),
+            countdown: Countdown(3),
+            time_since_last_count: TimeSinceLastCount(Duration::from_secs(0)),
+            fog: FogOfWar::default(),
+            reconnections: Reconnections::new(),
+            playing: None,
+        };
+
+        Ok(self.matches.entry(name).or_insert(game))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Match> {
+        self.matches.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Match> {
+        self.matches.get_mut(name)
+    }
+
+    /// The match the connection or bot with the given key has a seat in
+    pub fn of_user_mut(&mut self, key: &UserKey) -> Option<&mut Match> {
+        self.matches.values_mut().find(|game| game.has_user(key))
+    }
+
+    /// Every match in the given state
+    pub fn in_state_mut(&mut self, state: GameState) -> impl Iterator<Item = &mut Match> {
+        self.matches
+            .values_mut()
+            .filter(move |game| game.state == state)
+    }
+
+    /// Every match which moved into the given state this frame, to be set up for it
+    pub fn entered_mut(&mut self, state: GameState) -> impl Iterator<Item = &mut Match> {
+        self.matches
+            .values_mut()
+            .filter(move |game| game.entered && game.state == state)
+    }
+}
+
+/// Moves every match which was told to on to its next state
+pub fn change_states(mut matches: ResMut<Matches>) {
+    for game in matches.matches.values_mut() {
+        game.entered = false;
+
+        if let Some(state) = game.next_state.take() {
+            game.state = state;
+            game.entered = true;
+        }
+    }
+}
+
+/// Tears down matches which are over once everybody has left them, matches everybody left before
+/// the game started, and matches nobody has come back to for `--abandon-after` seconds after
+/// everybody lost their connection
+pub fn clean_up(
+    mut commands: Commands,
+    mut server: Server<Protocol, Channels>,
+
+    query_tilemap: Query<&TileMap>,
+
+    args: Res<Args>,
+    mut matches: ResMut<Matches>,
+) {
+    let grace_period = Duration::from_secs(args.abandon_after);
+
+    let mut abandoned = Vec::new();
+    for game in matches.matches.values_mut() {
+        let is_abandoned = match game.state {
+            GameState::WaitingForConnections => game.user_key_assoc.is_empty(),
+            GameState::Finished => game.room.users.is_empty(),
+            GameState::Countdown | GameState::Playing => {
+                if game.room.users.is_empty() && !game.reconnections.any_pending() {
+                    let empty_since = *game.empty_since.get_or_insert_with(Instant::now);
+                    empty_since.elapsed() >= grace_period
+                } else {
+                    game.empty_since = None;
+                    false
+                }
+            }
+        };
+
+        if is_abandoned {
+            abandoned.push(game.name.clone());
+        }
+    }
+
+    for name in abandoned {
+        let game = matches.matches.remove(&name).unwrap();
+        info!("Everybody has left {}, cleaning it up", name);
+
+        // Units and perceived maps are replicated, so they go through naia
+        let seats: Vec<UserKey> = game.key_map_assoc.keys().copied().collect();
+        for key in seats {
+            for unit in game.key_units_assoc.get_from_key(key).into_iter().flatten() {
+                server.entity_mut(unit).despawn();
+            }
+
+            let map_entity = *game.key_map_assoc.get_from_key(&key).unwrap();
+            if let Ok(perspective) = query_tilemap.get(map_entity) {
+                for chunk in &perspective.children {
+                    server.entity_mut(chunk).despawn();
+                }
+            }
+            commands.entity(map_entity).despawn();
+        }
+
+        for keep_alive in &game.room.keep_alives {
+            server.entity_mut(keep_alive).despawn();
+        }
+
+        if let Ok(auth_map) = query_tilemap.get(game.room.map_entity) {
+            for tile in &auth_map.children {
+                commands.entity(*tile).despawn();
+            }
+        }
+        commands.entity(game.room.map_entity).despawn();
+
+        server.room_mut(&game.room.key).destroy();
+    }
+}
+
+/// Sends what changed in every match this tick out to the players who can see it
+pub fn send_updates(mut server: Server<Protocol, Channels>) {
+    server.send_all_updates();
+}
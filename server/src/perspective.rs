@@ -0,0 +1,209 @@
+//! Players' perceptions of the map. Each player is sent the map a chunk at a time as
+//! [`MapChunkSync`] entities, which only show what is really on the tiles their units can see.
+//! Tiles they saw before are remembered as they last were, and the rest are fog. What each player
+//! can see is kept in the [`FogOfWar`], which only works out again what changed around units that
+//! moved, appeared or died.
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use naia_bevy_server::UserKey;
+
+use rgj_shared::{
+    behavior::{
+        visibility::{Sight, Viewpoint},
+        AxialCoordinates,
+    },
+    protocol::{
+        game_sync::{
+            map_chunk_sync::{ChunkBounds, ChunkStructure},
+            map_sync::{TileStructure, TileType, MAP_HEIGHT},
+        },
+        MapChunkSync,
+    },
+    resources::MapConfig,
+};
+
+use crate::{components::TileMap, resources::KeyMapAssociation};
+
+/// The contents of a chunk for a player with the given sight, taking what is really on each
+/// visible tile from `authoritative`
+pub fn perceive_chunk(
+    bounds: &ChunkBounds,
+    sight: &Sight<Entity>,
+    mut authoritative: impl FnMut(AxialCoordinates, i32) -> (TileType, TileStructure),
+) -> (Vec<TileType>, Vec<ChunkStructure>) {
+    bounds.gather(|hex, layer| {
+        if sight.is_visible(hex) {
+            authoritative(hex, layer)
+        } else {
+            (TileType::Fog, TileStructure::None)
+        }
+    })
+}
+
+/// A tile of a player's perceived map which has to be brought up to date
+pub struct StaleTile {
+    pub position: AxialCoordinates,
+    pub layer: i32,
+    /// Whether the player can see the tile, and so should be sent what is really there rather than
+    /// what they remember of it
+    pub visible: bool,
+}
+
+/// What every player's units can see
+#[derive(Default)]
+pub struct FogOfWar {
+    sights: HashMap<UserKey, Sight<Entity>>,
+    /// Tiles of the authoritative map whose contents changed since the perspectives were last
+    /// brought up to date
+    changed_tiles: HashSet<(AxialCoordinates, i32)>,
+}
+
+impl FogOfWar {
+    pub fn insert(&mut self, key: UserKey, sight: Sight<Entity>) {
+        self.sights.insert(key, sight);
+    }
+
+    /// Hands the sight of the player with the `old` key to `new`, such as when they reconnect
+    pub fn replace_key(&mut self, old: &UserKey, new: UserKey) {
+        if let Some(sight) = self.sights.remove(old) {
+            self.sights.insert(new, sight);
+        }
+    }
+
+    /// Notes that something on a tile of the authoritative map changed, so that players who can
+    /// see it are sent it again
+    pub fn tile_changed(&mut self, qr: AxialCoordinates, layer: i32) {
+        self.changed_tiles.insert((qr, layer));
+    }
+
+    /// Brings a player's sight up to date with every unit they have, given as each unit with where
+    /// it looks from, over the terrain given by `tile_at`. Returns the tiles of their perceived map
+    /// that are out of date: those on hexes which came into or went out of view, and the changed
+    /// tiles they can see.
+    pub fn refresh(
+        &mut self,
+        key: &UserKey,
+        units: impl IntoIterator<Item = (Entity, Viewpoint)>,
+        tile_at: impl Fn(AxialCoordinates, i32) -> Option<TileType>,
+    ) -> Vec<StaleTile> {
+        let sight = match self.sights.get_mut(key) {
+            Some(sight) => sight,
+            None => return Vec::new(),
+        };
+
+        sight.sync_units(units, tile_at);
+
+        let mut stale = Vec::new();
+        for position in sight.take_changed() {
+            let visible = sight.is_visible(position);
+
+            for layer in 0..MAP_HEIGHT {
+                stale.push(StaleTile {
+                    position,
+                    layer,
+                    visible,
+                });
+            }
+        }
+
+        stale.extend(
+            self.changed_tiles
+                .iter()
+                .filter(|(position, _)| sight.is_visible(*position))
+                .map(|(position, layer)| StaleTile {
+                    position: *position,
+                    layer: *layer,
+                    visible: true,
+                }),
+        );
+
+        stale
+    }
+
+    /// Forgets the changed tiles once every player has been refreshed
+    pub fn clear_changed_tiles(&mut self) {
+        self.changed_tiles.clear();
+    }
+}
+
+/// Read access to the perceived maps of every player in every match
+#[derive(SystemParam)]
+pub struct PerspectiveMaps<'w, 's> {
+    tilemaps: Query<'w, 's, &'static TileMap>,
+    chunks: Query<'w, 's, &'static MapChunkSync>,
+}
+
+impl<'w, 's> PerspectiveMaps<'w, 's> {
+    /// The perceived maps of the players in a single match
+    pub fn of<'a>(
+        &'a self,
+        key_map_assoc: &'a KeyMapAssociation,
+        map_config: &'a MapConfig,
+    ) -> Perspectives<'a, 'w, 's> {
+        Perspectives {
+            maps: self,
+            key_map_assoc,
+            map_config,
+        }
+    }
+}
+
+/// Read access to the perceived map of every player in a match
+pub struct Perspectives<'a, 'w, 's> {
+    maps: &'a PerspectiveMaps<'w, 's>,
+    key_map_assoc: &'a KeyMapAssociation,
+    map_config: &'a MapConfig,
+}
+
+impl<'a, 'w, 's> Perspectives<'a, 'w, 's> {
+    /// Whether the player has a perceived map at all
+    pub fn contains(&self, key: &UserKey) -> bool {
+        self.key_map_assoc
+            .get_from_key(key)
+            .map(|entity| self.maps.tilemaps.get(*entity).is_ok())
+            .unwrap_or(false)
+    }
+
+    /// The tile on the given hex and layer as the player perceives it, or [`None`] if the hex is
+    /// off the map or the player has no perceived map
+    pub fn tile(&self, key: &UserKey, qr: AxialCoordinates, layer: i32) -> Option<TileType> {
+        Some(self.chunk(key, qr)?.tile(qr, layer))
+    }
+
+    /// The structure on the given hex and layer as the player perceives it, which for remembered
+    /// tiles is never being built on
+    pub fn structure(
+        &self,
+        key: &UserKey,
+        qr: AxialCoordinates,
+        layer: i32,
+    ) -> Option<TileStructure> {
+        Some(self.chunk(key, qr)?.structure(qr, layer))
+    }
+
+    /// Whether the player can currently see the given hex and layer, rather than remembering it
+    pub fn is_visible(&self, key: &UserKey, qr: AxialCoordinates, layer: i32) -> bool {
+        self.chunk(key, qr)
+            .map(|chunk| chunk.is_visible(qr, layer))
+            .unwrap_or(false)
+    }
+
+    fn chunk(&self, key: &UserKey, qr: AxialCoordinates) -> Option<&MapChunkSync> {
+        if !qr.is_in_bounds(self.map_config) {
+            return None;
+        }
+
+        let tilemap = self
+            .maps
+            .tilemaps
+            .get(*self.key_map_assoc.get_from_key(key)?)
+            .ok()?;
+
+        self.maps
+            .chunks
+            .get(tilemap.chunk_of(self.map_config, qr))
+            .ok()
+    }
+}
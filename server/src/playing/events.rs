@@ -4,26 +4,35 @@ use bevy::prelude::*;
 use naia_bevy_server::{events::MessageEvent, Server, UserKey};
 
 use rgj_shared::{
-    behavior::AxialCoordinates,
-    components::genome::TerrainType,
+    behavior::{
+        combat::{check_attack, resolve_attack, CombatError},
+        movement::{layer_change_cost, LayerChangeError},
+        pathfinding::{find_path, PathfindingError},
+        AxialCoordinates,
+    },
     protocol::{
         game_sync::map_sync::{
-            tile_qrz_to_index, ConstructionStatus, MapSync, TileStructure, TileType,
+            tile_qrz_to_index, ConstructionStatus, MapSync, TileStructure, MAP_HEIGHT,
+        },
+        notifications::{
+            combat_result::Combatant,
+            genome_status_change::{GenomeStatusChange, LockedStatus},
+            WhoseTurn,
         },
-        notifications::WhoseTurn,
         player_input::PlayerInputVariant,
-        PlayerInput, Protocol, TurnChangeNotification, UnitSync,
+        CombatResultNotification, PlayerInput, Protocol, TurnChangeNotification, UnitSync,
     },
     resources::MapConfig,
     Channels,
 };
 
-use super::resources::{KeyToUnlockedGenomesMap, ShouldUpdate, TurnTracker, UnitMoveInformation};
 use crate::{
     components::TileMap,
-    resources::{
-        KeyIdAssociation, KeyMapAssociation, KeyUnitsAssociation, MainRoom, UsernameKeyAssociation,
-    },
+    matches::{Match, Matches, Playing},
+    perspective::{PerspectiveMaps, Perspectives},
+    playing::resources::KeyToUnlockedGenomesMap,
+    resources::{KeyUnitsAssociation, UsernameKeyAssociation},
+    GameState,
 };
 
 pub fn receive_input_event(
@@ -33,21 +42,40 @@ pub fn receive_input_event(
 
     query_tilemap: Query<&TileMap>,
     mut query_tile: Query<(Entity, &mut MapSync)>,
-    query_unit: Query<&UnitSync>,
-
-    mut turn_tracker: ResMut<TurnTracker>,
-    mut move_information: ResMut<UnitMoveInformation>,
-    mut should_update: ResMut<ShouldUpdate>,
-
-    map_conf: Res<MapConfig>,
-    user_key_assoc: Res<UsernameKeyAssociation>,
-    key_id_assoc: Res<KeyIdAssociation>,
-    mut key_units_assoc: ResMut<KeyUnitsAssociation>,
-    key_genomes: Res<KeyToUnlockedGenomesMap>,
-    main_room: Res<MainRoom>,
+    mut query_unit: Query<&mut UnitSync>,
+    query_unit_entities: Query<Entity, With<UnitSync>>,
+    perspective_maps: PerspectiveMaps,
+
+    mut matches: ResMut<Matches>,
 ) {
     for event in event_reader.iter() {
         if let MessageEvent(user_key, Channels::PlayerInput, Protocol::PlayerInput(input)) = event {
+            let game = match matches.of_user_mut(user_key) {
+                Some(game) if game.state() == GameState::Playing => game,
+                _ => continue,
+            };
+            let Match {
+                room,
+                map_config,
+                user_key_assoc,
+                key_id_assoc,
+                key_map_assoc,
+                key_units_assoc,
+                fog,
+                playing,
+                ..
+            } = game;
+            let Playing {
+                turn_tracker,
+                move_info: move_information,
+                unlocked_genomes: key_genomes,
+                ..
+            } = match playing {
+                Some(playing) => playing,
+                None => continue,
+            };
+            let perspectives = perspective_maps.of(key_map_assoc, map_config);
+
             if turn_tracker.player == *user_key {
                 match &*input.partial_turn {
                     PlayerInputVariant::MoveEntity(axial_coordinates) => {
@@ -58,19 +86,79 @@ pub fn receive_input_event(
                                 *user_key,
                                 input,
                                 *axial_coordinates,
-                                &query_tilemap,
-                                &query_tile,
+                                &perspectives,
                                 &query_unit,
-                                &key_units_assoc,
-                                &user_key_assoc,
-                                &main_room,
-                                &map_conf,
+                                key_units_assoc,
+                                user_key_assoc,
                             ) {
-                                Ok(CanTravel::CanTravel(entity, steps_through)) => {
+                                Ok(CanTravel::CanTravel(entity, layer, steps_through)) => {
                                     move_information.0 =
-                                        Some((entity, steps_through.into_iter().collect()));
+                                        Some((entity, layer, steps_through.into_iter().collect()));
+                                }
+                                Ok(CanTravel::InvalidTravel(reason)) => {
+                                    info!("Rejecting move: {}", reason)
+                                }
+                                Err(Error::Warn(msg)) => warn!("{}", msg),
+                                Err(Error::Error(msg)) => error!("{}", msg),
+                            }
+                        }
+                    }
+
+                    PlayerInputVariant::ChangeLayer(layer) => {
+                        // Only process if there is no active move
+                        if move_information.0.is_none() {
+                            match handle_change_layer(
+                                &server,
+                                *user_key,
+                                input,
+                                *layer,
+                                &perspectives,
+                                &query_unit,
+                                key_units_assoc,
+                            ) {
+                                Ok(CanChangeLayer::CanChangeLayer(entity, layer, position)) => {
+                                    move_information.0 = Some((entity, layer, [position].into()));
+                                }
+                                Ok(CanChangeLayer::InvalidChangeLayer(reason)) => {
+                                    info!("Rejecting layer change: {}", reason)
+                                }
+                                Err(Error::Warn(msg)) => warn!("{}", msg),
+                                Err(Error::Error(msg)) => error!("{}", msg),
+                            }
+                        }
+                    }
+
+                    PlayerInputVariant::Attack(target) => {
+                        // Units can't fight while one of them is still walking
+                        if move_information.0.is_none() {
+                            match handle_attack(
+                                &server,
+                                *user_key,
+                                input,
+                                *target,
+                                &query_unit,
+                                &query_unit_entities,
+                                key_units_assoc,
+                            ) {
+                                Ok(CanAttack::CanAttack { attacker, defender }) => {
+                                    let auth_map =
+                                        &query_tilemap.get(room.map_entity).unwrap().children;
+
+                                    resolve_combat(
+                                        &mut server,
+                                        attacker,
+                                        defender,
+                                        auth_map,
+                                        &query_tile,
+                                        &mut query_unit,
+                                        map_config,
+                                        key_units_assoc,
+                                        key_genomes,
+                                    );
+                                }
+                                Ok(CanAttack::InvalidAttack(reason)) => {
+                                    info!("Rejecting attack: {}", reason)
                                 }
-                                Ok(CanTravel::InvalidTravel) => {}
                                 Err(Error::Warn(msg)) => warn!("{}", msg),
                                 Err(Error::Error(msg)) => error!("{}", msg),
                             }
@@ -80,35 +168,36 @@ pub fn receive_input_event(
                     PlayerInputVariant::EndTurn => {
                         turn_tracker.next(
                             &mut server,
-                            &user_key_assoc,
-                            &key_id_assoc,
+                            user_key_assoc,
+                            key_id_assoc,
                             &query_tilemap,
                             &mut query_tile,
-                            *map_conf,
-                            &main_room,
-                            &mut key_units_assoc,
-                            &mut should_update,
+                            &mut query_unit,
+                            *map_config,
+                            room,
+                            key_units_assoc,
+                            fog,
                         );
                     }
 
                     PlayerInputVariant::BuildHybrid(pos, hybrid) => {
-                        let tilemap = &query_tilemap.get(main_room.map_entity).unwrap().children;
+                        let tilemap = &query_tilemap.get(room.map_entity).unwrap().children;
                         let (_e, mut tile) = query_tile
                             .get_mut(
-                                tilemap[tile_qrz_to_index(&map_conf, pos.column_q, pos.row_r, 0)],
+                                tilemap[tile_qrz_to_index(map_config, pos.column_q, pos.row_r, 0)],
                             )
                             .unwrap();
 
-                        // Ensure the player has a unit on that tile
+                        // Ensure the player has a unit on that tile, and not flying above it
                         let units = key_units_assoc.get_from_key(*user_key).unwrap();
                         if units
                             .iter()
                             .map(|u| {
                                 let u = query_unit.get(*u).unwrap();
-                                *u.position
+                                (*u.position, *u.layer)
                             })
                             .collect::<Vec<_>>()
-                            .contains(&*tile.position)
+                            .contains(&(*tile.position, *tile.layer))
                         {
                             // Ensure the unit to be built is all genomes the player has
                             let genomes = key_genomes.key_to_genomes.get(user_key).unwrap();
@@ -147,7 +236,7 @@ pub fn receive_input_event(
                                         finished_on: turn,
                                     });
 
-                                    should_update.0 = true;
+                                    fog.tile_changed(*tile.position, *tile.layer);
                                 }
                             }
                         }
@@ -164,8 +253,8 @@ pub enum Error {
 }
 
 pub enum CanTravel {
-    CanTravel(Entity, Vec<AxialCoordinates>),
-    InvalidTravel,
+    CanTravel(Entity, i32, Vec<AxialCoordinates>),
+    InvalidTravel(PathfindingError),
 }
 
 fn handle_move_entity(
@@ -175,14 +264,11 @@ fn handle_move_entity(
     input: &PlayerInput,
     axial_coordianates: AxialCoordinates,
 
-    query_tilemap: &Query<&TileMap>,
-    query_tile: &Query<(Entity, &mut MapSync)>,
-    query_unit: &Query<&UnitSync>,
+    perspectives: &Perspectives,
+    query_unit: &Query<&mut UnitSync>,
 
     key_units_assoc: &KeyUnitsAssociation,
     user_key_assoc: &UsernameKeyAssociation,
-    main_room: &MainRoom,
-    map_conf: &MapConfig,
 ) -> Result<CanTravel, Error> {
     let entity = input.relevant_entity.get(server).ok_or(Error::Warn(
         "Invalid Input: No EntityProperty with MoveEntity PlayerInput event".to_owned(),
@@ -210,119 +296,232 @@ fn handle_move_entity(
         .get(entity)
         .map_err(|_| Error::Error("Known unit Entity does not contain UnitSync".to_owned()))?;
 
-    // TODO: Don't copy and paste this from client
-    let entity_pos = *unit_sync.position;
-    let desired_pos = axial_coordianates;
+    if !perspectives.contains(&senders_key) {
+        return Err(Error::Error(
+            "UserKey associated with input has no subjective map".to_owned(),
+        ));
+    }
+
+    // Paths are found on the player's own perspective of the map, exactly as the client does, so
+    // that a unit can never be routed through tiles its owner has not seen. Units walk or fly
+    // along whichever layer they are on
+    let layer = *unit_sync.layer;
+    let path = find_path(
+        &unit_sync.hybrid_type,
+        *unit_sync.stamina_remaining,
+        *unit_sync.position,
+        axial_coordianates,
+        |point| perspectives.tile(&senders_key, point, layer),
+    );
+
+    match path {
+        Ok(path) => Ok(CanTravel::CanTravel(entity, layer, path.steps)),
+        Err(e) => Ok(CanTravel::InvalidTravel(e)),
+    }
+}
+
+pub enum CanChangeLayer {
+    CanChangeLayer(Entity, i32, AxialCoordinates),
+    InvalidChangeLayer(LayerChangeError),
+}
 
-    // TODO: More hex utils
+fn handle_change_layer(
+    server: &Server<Protocol, Channels>,
 
-    // Calculate the distance between the the desired position and the current position,
-    // caching what's needed
-    let (dist, diff_q, diff_r) = {
-        let (diff_q, diff_r) = (
-            entity_pos.column_q as i32 - desired_pos.column_q as i32,
-            entity_pos.row_r as i32 - desired_pos.row_r as i32,
-        );
+    senders_key: UserKey,
+    input: &PlayerInput,
+    layer: i32,
 
-        let abs_q = i32::abs(diff_q);
-        let abs_q_plus_r = i32::abs(diff_q + diff_r);
-        let abs_r_div_2 = i32::abs(diff_r) / 2;
+    perspectives: &Perspectives,
+    query_unit: &Query<&mut UnitSync>,
 
-        ((abs_q + abs_q_plus_r + abs_r_div_2) as i32, diff_q, diff_r)
-    };
+    key_units_assoc: &KeyUnitsAssociation,
+) -> Result<CanChangeLayer, Error> {
+    let entity = input.relevant_entity.get(server).ok_or(Error::Warn(
+        "Invalid Input: No EntityProperty with ChangeLayer PlayerInput event".to_owned(),
+    ))?;
 
-    if dist == 0 {
-        return Ok(CanTravel::InvalidTravel);
+    if key_units_assoc.get_from_entity(entity) != Some(&senders_key) {
+        return Err(Error::Warn(
+            "ChangeLayer EntityProperty was not a unit owned by the sender".to_owned(),
+        ));
     }
 
-    // Draw a line through the two points
-    let mut travels_through: Vec<AxialCoordinates> = Vec::new();
+    let unit_sync = query_unit
+        .get(entity)
+        .map_err(|_| Error::Error("Known unit Entity does not contain UnitSync".to_owned()))?;
 
-    for i in 0..=dist {
-        let step_q = desired_pos.column_q as f32 + diff_q as f32 * 1.0 / dist as f32 * i as f32;
-        let step_r = desired_pos.row_r as f32 + diff_r as f32 * 1.0 / dist as f32 * i as f32;
+    let position = *unit_sync.position;
 
-        travels_through.push(AxialCoordinates::new(
-            step_q.round() as i32,
-            step_r.round() as i32,
+    if !(0..MAP_HEIGHT).contains(&layer) {
+        return Ok(CanChangeLayer::InvalidChangeLayer(
+            LayerChangeError::NoSuchLayer,
         ));
     }
 
-    // Ensure that the player's unit has enough stamina and has the requisite terrain
-    // types to cross this terrain
-    let mut entity_stamina = *unit_sync.stamina_remaining;
+    let target = perspectives
+        .tile(&senders_key, position, layer)
+        .ok_or(Error::Error(
+            "UserKey associated with input has no subjective map".to_owned(),
+        ))?;
+
+    match layer_change_cost(
+        &unit_sync.hybrid_type,
+        *unit_sync.stamina_remaining,
+        *unit_sync.layer,
+        layer,
+        target,
+    ) {
+        Ok(_) => Ok(CanChangeLayer::CanChangeLayer(entity, layer, position)),
+        Err(e) => Ok(CanChangeLayer::InvalidChangeLayer(e)),
+    }
+}
 
-    let mut terrain_types = Vec::with_capacity(2);
-    terrain_types.push(unit_sync.hybrid_type.limbs().terrain_a.terrain_type);
-    if let Some(terrain_b) = unit_sync.hybrid_type.limbs().terrain_b {
-        terrain_types.push(terrain_b.terrain_type);
+pub enum CanAttack {
+    CanAttack { attacker: Entity, defender: Entity },
+    InvalidAttack(CombatError),
+}
+
+fn handle_attack(
+    server: &Server<Protocol, Channels>,
+
+    senders_key: UserKey,
+    input: &PlayerInput,
+    target: AxialCoordinates,
+
+    query_unit: &Query<&mut UnitSync>,
+    query_unit_entities: &Query<Entity, With<UnitSync>>,
+
+    key_units_assoc: &KeyUnitsAssociation,
+) -> Result<CanAttack, Error> {
+    let attacker = input.relevant_entity.get(server).ok_or(Error::Warn(
+        "Invalid Input: No EntityProperty with Attack PlayerInput event".to_owned(),
+    ))?;
+
+    if key_units_assoc.get_from_entity(attacker) != Some(&senders_key) {
+        return Err(Error::Warn(
+            "Attack EntityProperty was not a unit owned by the sender".to_owned(),
+        ));
     }
 
-    let mut can_travel = true;
-
-    // Step through each point in the line
-    for point in &travels_through {
-        let map_entity = main_room.map_entity;
-
-        // TODO: Eliminate unwraps
-        let map = &query_tilemap.get(map_entity).unwrap().children;
-        let tile = map[tile_qrz_to_index(&map_conf, point.column_q.into(), point.row_r.into(), 0)];
-        let (_e, tile_state) = query_tile.get(tile).unwrap();
-
-        // TODO: More complex movement rules here -- traversing different terrains
-        // differently
-        // TODO: Queueing movement that cancels or pauses if cannont be completed
-        // ever or immediately
-        match *tile_state.tile_type {
-            TileType::Fog => {
-                can_travel = false;
-                break;
-            }
-            TileType::Grass | TileType::Forest | TileType::Desert => {
-                if terrain_types.contains(&TerrainType::Ground) {
-                    if entity_stamina > 0 {
-                        entity_stamina -= 1;
-                    } else {
-                        can_travel = false;
-                        break;
-                    }
-                } else {
-                    can_travel = false;
-                    break;
-                }
-            }
-            TileType::Ocean | TileType::River | TileType::DesertOasis => {
-                if terrain_types.contains(&TerrainType::Water) {
-                    if entity_stamina > 0 {
-                        entity_stamina -= 1;
-                    } else {
-                        can_travel = false;
-                        break;
-                    }
-                } else {
-                    can_travel = false;
-                    break;
-                }
-            }
-            TileType::ClearSky | TileType::WindySky | TileType::StormySky => {
-                if terrain_types.contains(&TerrainType::Air) {
-                    if entity_stamina > 0 {
-                        entity_stamina -= 1;
-                    } else {
-                        can_travel = false;
-                        break;
-                    }
-                } else {
-                    can_travel = false;
-                    break;
-                }
-            }
+    let attacker_sync = query_unit
+        .get(attacker)
+        .map_err(|_| Error::Error("Known unit Entity does not contain UnitSync".to_owned()))?;
+
+    if let Err(e) = check_attack(
+        *attacker_sync.position,
+        *attacker_sync.stamina_remaining,
+        target,
+    ) {
+        return Ok(CanAttack::InvalidAttack(e));
+    }
+
+    // Other matches are played over the same hexes, so only units in this one can be hit
+    let defender = query_unit_entities
+        .iter()
+        .filter(|entity| key_units_assoc.get_from_entity(*entity).is_some())
+        .find(|entity| {
+            query_unit
+                .get(*entity)
+                .map(|unit| *unit.position == target && *unit.layer == *attacker_sync.layer)
+                .unwrap_or(false)
+        });
+
+    match defender {
+        None => Ok(CanAttack::InvalidAttack(CombatError::NoTarget)),
+        Some(defender) if key_units_assoc.get_from_entity(defender) == Some(&senders_key) => {
+            Ok(CanAttack::InvalidAttack(CombatError::FriendlyTarget))
+        }
+        Some(defender) => Ok(CanAttack::CanAttack { attacker, defender }),
+    }
+}
+
+/// Applies the outcome of a validated attack, telling both players what happened and removing any
+/// unit which died. A unit dying on a genome facility takes that genome away from its owner, just as
+/// walking off of it would.
+fn resolve_combat(
+    server: &mut Server<Protocol, Channels>,
+
+    attacker: Entity,
+    defender: Entity,
+
+    auth_map: &[Entity],
+    query_tile: &Query<(Entity, &mut MapSync)>,
+    query_unit: &mut Query<&mut UnitSync>,
+
+    map_config: &MapConfig,
+    key_units_assoc: &mut KeyUnitsAssociation,
+    key_genomes: &mut KeyToUnlockedGenomesMap,
+) {
+    let [mut attacker_sync, mut defender_sync] =
+        query_unit.get_many_mut([attacker, defender]).unwrap();
+
+    let attacker_tile = (*attacker_sync.position, *attacker_sync.layer);
+    let defender_tile = (*defender_sync.position, *defender_sync.layer);
+
+    let outcome = resolve_attack(
+        &attacker_sync.hybrid_type,
+        *attacker_sync.current_health,
+        &defender_sync.hybrid_type,
+        *defender_sync.current_health,
+    );
+
+    *attacker_sync.current_health = outcome.attacker_health;
+    *defender_sync.current_health = outcome.defender_health;
+
+    // Attacking takes the rest of the unit's turn
+    *attacker_sync.stamina_remaining = 0;
+
+    let attacker_result = Combatant {
+        owner: *attacker_sync.player_id,
+        hybrid_name: attacker_sync.hybrid_type.name().to_owned(),
+        position: *attacker_sync.position,
+        damage_taken: outcome.damage_taken,
+        health_remaining: outcome.attacker_health,
+    };
+    let defender_result = Combatant {
+        owner: *defender_sync.player_id,
+        hybrid_name: defender_sync.hybrid_type.name().to_owned(),
+        position: *defender_sync.position,
+        damage_taken: outcome.damage_dealt,
+        health_remaining: outcome.defender_health,
+    };
+
+    let notification = CombatResultNotification::new(attacker_result, defender_result);
+    for unit in [attacker, defender] {
+        if let Some(key) = key_units_assoc.get_from_entity(unit) {
+            server.send_message(key, Channels::GameNotification, &notification);
         }
     }
 
-    if can_travel {
-        Ok(CanTravel::CanTravel(entity, travels_through))
-    } else {
-        Ok(CanTravel::InvalidTravel)
+    for (unit, (position, layer), died) in [
+        (attacker, attacker_tile, outcome.attacker_died()),
+        (defender, defender_tile, outcome.defender_died()),
+    ] {
+        if died {
+            let (_, auth_tile) = query_tile
+                .get(
+                    auth_map
+                        [tile_qrz_to_index(map_config, position.column_q, position.row_r, layer)],
+                )
+                .unwrap();
+
+            if let (TileStructure::GenomeFacility { unique_genome, .. }, Some(key)) =
+                (&*auth_tile.structure, key_units_assoc.get_from_entity(unit))
+            {
+                if let Some(genomes) = key_genomes.key_to_genomes.get_mut(key) {
+                    server.send_message(
+                        key,
+                        Channels::GameNotification,
+                        &GenomeStatusChange::new(unique_genome.clone(), LockedStatus::Locked),
+                    );
+
+                    genomes.retain(|genome| genome != unique_genome);
+                }
+            }
+
+            key_units_assoc.delete_from_entity(unit);
+            server.entity_mut(&unit).despawn();
+        }
     }
 }
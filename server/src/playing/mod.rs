@@ -1,342 +1,417 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
 
 use bevy::prelude::*;
-use naia_bevy_server::Server;
+use naia_bevy_server::{Server, UserKey};
 
 use rgj_shared::{
-    behavior::AxialCoordinates,
+    behavior::{movement::step_cost, visibility::Viewpoint, AxialCoordinates},
     components::genome::DEER,
     protocol::{
-        game_sync::map_sync::{
-            tile_qrz_to_index, ConstructionStatus, TileStructure, TileType, MAP_HEIGHT,
-        },
-        notifications::{
-            genome_status_change::{GenomeStatusChange, LockedStatus},
-            WhoseTurn,
-        },
-        MapSync, Protocol, UnitSync,
+        game_sync::map_sync::{tile_qrz_to_index, TileStructure},
+        notifications::genome_status_change::{GenomeStatusChange, LockedStatus},
+        MapChunkSync, MapSync, Protocol, TurnClockNotification, UnitSync,
     },
     resources::MapConfig,
     Channels,
 };
 
 use crate::{
+    bots::BotTurn,
     components::TileMap,
-    resources::{
-        KeyIdAssociation, KeyMapAssociation, KeyUnitsAssociation, MainRoom, UsernameKeyAssociation,
-    },
+    matches::{Matches, Playing},
+    perspective::{FogOfWar, StaleTile},
+    resources::{KeyMapAssociation, KeyUnitsAssociation, MatchRoom},
+    waiting_for_connections::events::ID_ORDER,
+    Args, GameState,
 };
 
 pub mod events;
 
 pub mod resources;
-use resources::{KeyToUnlockedGenomesMap, ShouldUpdate, TurnTracker, UnitMoveInformation};
-
-pub fn init(
-    mut commands: Commands,
-    mut server: Server<Protocol, Channels>,
+use resources::{KeyToUnlockedGenomesMap, TurnClock, TurnTracker, UnitMoveInformation};
+
+pub mod victory;
+use victory::WinConditions;
+
+pub fn init(mut server: Server<Protocol, Channels>, args: Res<Args>, mut matches: ResMut<Matches>) {
+    for game in matches.entered_mut(GameState::Playing) {
+        // Turns go in the order of the players' colors, which are handed out as seats are taken so
+        // that people go before bots. Players who lost their connection during the countdown keep
+        // their seat.
+        let keys: VecDeque<UserKey> = ID_ORDER
+            .iter()
+            .filter_map(|id| game.key_id_assoc.get_from_id(id))
+            .filter(|key| game.key_map_assoc.get_from_key(key).is_some())
+            .copied()
+            .collect();
+        let clock = args.turn_time.map(|turn_time| {
+            TurnClock::new(
+                Duration::from_secs(turn_time),
+                Duration::from_secs(args.time_bank),
+                keys.iter().copied(),
+            )
+        });
+
+        let turn_tracker = TurnTracker::new(
+            &mut server,
+            &game.room,
+            &game.user_key_assoc,
+            &game.key_id_assoc,
+            keys,
+            clock,
+            WinConditions::from_args(&args),
+            game.rng.seed(),
+        );
+
+        let mut key_to_genomes = HashMap::new();
+        for key in game.key_map_assoc.keys() {
+            key_to_genomes.insert(*key, vec![DEER.clone()]);
+        }
 
-    user_key_assoc: Res<UsernameKeyAssociation>,
-    key_id_assoc: Res<KeyIdAssociation>,
-) {
-    let keys = server.user_keys().into_iter().collect();
-    let turn_tracker = TurnTracker::new(&mut server, &user_key_assoc, &key_id_assoc, keys, None);
-    commands.insert_resource(turn_tracker);
-    commands.insert_resource(UnitMoveInformation(None));
-
-    let mut key_to_genomes = HashMap::new();
-    for key in server.user_keys() {
-        key_to_genomes.insert(key, vec![DEER.clone()]);
+        game.playing = Some(Playing {
+            turn_tracker,
+            move_info: UnitMoveInformation(None),
+            bot_turn: BotTurn::default(),
+            unlocked_genomes: KeyToUnlockedGenomesMap { key_to_genomes },
+        });
     }
+}
 
-    commands.insert_resource(KeyToUnlockedGenomesMap { key_to_genomes });
-    commands.insert_resource(ShouldUpdate(false));
+/// Moves each match somebody has won on to the finished state
+pub fn finish_game(mut matches: ResMut<Matches>) {
+    for game in matches.in_state_mut(GameState::Playing) {
+        let finished = game
+            .playing
+            .as_ref()
+            .map(|playing| playing.turn_tracker.is_finished())
+            .unwrap_or(false);
+
+        if finished {
+            game.set_next_state(GameState::Finished);
+        }
+    }
 }
 
-pub fn tick(
+/// Ends the turn of a player who has run out of time, and tells everybody in their match how long
+/// the player whose turn it is has left
+pub fn turn_clock(
     mut server: Server<Protocol, Channels>,
 
     query_tilemap: Query<&TileMap>,
-    mut query_tile: Query<&mut MapSync>,
-    mut query_units: Query<&mut UnitSync>,
+    mut query_tile: Query<(Entity, &mut MapSync)>,
+    mut query_unit: Query<&mut UnitSync>,
 
-    mut move_info: ResMut<UnitMoveInformation>,
-    mut unlocked_genomes: ResMut<KeyToUnlockedGenomesMap>,
-    mut should_update: ResMut<ShouldUpdate>,
-
-    turn_tracker: Res<TurnTracker>,
-    map_config: Res<MapConfig>,
-    main_room: Res<MainRoom>,
-    user_key_assoc: Res<UsernameKeyAssociation>,
-    key_id_assoc: Res<KeyIdAssociation>,
-    key_map_assoc: Res<KeyMapAssociation>,
-    mut key_units_assoc: ResMut<KeyUnitsAssociation>,
+    mut matches: ResMut<Matches>,
 ) {
-    if let Some((entity, ref mut path)) = &mut move_info.0 {
-        let mut unit_sync = query_units.get_mut(*entity).unwrap();
-        let len = path.len();
-        let run_updates = match path.pop_front() {
-            Some(next_stop) => {
-                // If the current position is a genome facility and you're moving off it, then you
-                // should no longer have that genome unlockd
-                let auth_map = &query_tilemap.get(main_room.map_entity).unwrap().children;
-                let auth_tile_old = query_tile
-                    .get(
-                        auth_map[tile_qrz_to_index(
-                            &map_config,
-                            unit_sync.position.column_q,
-                            unit_sync.position.row_r,
-                            0,
-                        )],
-                    )
-                    .unwrap();
-
-                *unit_sync.stamina_remaining -= len as u16 - 1;
+    for game in matches.in_state_mut(GameState::Playing) {
+        let playing = match &mut game.playing {
+            Some(playing) => playing,
+            None => continue,
+        };
+        let turn_tracker = &mut playing.turn_tracker;
 
-                if let TileStructure::GenomeFacility { unique_genome, .. } =
-                    &*auth_tile_old.structure
-                {
-                    let key = key_units_assoc.get_from_entity(*entity).unwrap();
-                    let genomes = unlocked_genomes.key_to_genomes.get_mut(key).unwrap();
+        let player = turn_tracker.player;
+        let expired = match &turn_tracker.clock {
+            Some(clock) => clock.is_expired(player),
+            None => continue,
+        };
 
-                    server.send_message(
-                        key,
-                        Channels::GameNotification,
-                        &GenomeStatusChange::new(unique_genome.clone(), LockedStatus::Locked),
-                    );
+        // Let a unit finish walking before the turn is taken away
+        if expired && playing.move_info.0.is_none() && !turn_tracker.is_finished() {
+            info!(
+                "{} ran out of time",
+                game.user_key_assoc.get_from_key(&player).unwrap()
+            );
+
+            turn_tracker.next(
+                &mut server,
+                &game.user_key_assoc,
+                &game.key_id_assoc,
+                &query_tilemap,
+                &mut query_tile,
+                &mut query_unit,
+                game.map_config,
+                &game.room,
+                &mut game.key_units_assoc,
+                &mut game.fog,
+            );
+        }
 
-                    *genomes = genomes
-                        .drain(..)
-                        .filter(|elem| elem != unique_genome)
-                        .collect();
-                }
+        let player = turn_tracker.player;
+        if let Some((turn_secs, bank_secs)) = turn_tracker
+            .clock
+            .as_mut()
+            .and_then(|clock| clock.seconds_to_send(player))
+        {
+            let notification = TurnClockNotification::new_complete(turn_secs, bank_secs);
 
-                // And if the new position is a genome facility that you're moving onto, then it
-                // shoulld be added
-                let auth_tile_new = query_tile
-                    .get(
-                        auth_map[tile_qrz_to_index(
-                            &map_config,
-                            next_stop.column_q,
-                            next_stop.row_r,
-                            0,
-                        )],
-                    )
-                    .unwrap();
+            for key in game.room.users() {
+                server.send_message(&key, Channels::GameNotification, &notification);
+            }
+        }
+    }
+}
 
-                if let TileStructure::GenomeFacility { unique_genome, .. } =
-                    &*auth_tile_new.structure
-                {
-                    let key = key_units_assoc.get_from_entity(*entity).unwrap();
-                    let genomes = unlocked_genomes.key_to_genomes.get_mut(key).unwrap();
+pub fn tick(
+    mut server: Server<Protocol, Channels>,
 
-                    server.send_message(
-                        key,
-                        Channels::GameNotification,
-                        &GenomeStatusChange::new(unique_genome.clone(), LockedStatus::Unlocked),
-                    );
+    query_tilemap: Query<&TileMap>,
+    query_tile: Query<&MapSync>,
+    mut query_chunk: Query<&mut MapChunkSync>,
+    mut query_units: Query<&mut UnitSync>,
 
-                    genomes.push(unique_genome.clone());
-                }
+    mut matches: ResMut<Matches>,
+) {
+    let scope_checks = server.scope_checks();
 
-                // Process the move making sure to update
-                *unit_sync.position = next_stop;
-                move_info.0 = None;
-                true
-            }
-            None => {
-                // Clear an empty path
-                move_info.0 = None;
-                false
-            }
+    for game in matches.in_state_mut(GameState::Playing) {
+        let playing = match &mut game.playing {
+            Some(playing) => playing,
+            None => continue,
         };
+        let move_info = &mut playing.move_info;
+        let unlocked_genomes = &mut playing.unlocked_genomes;
+        let map_config = game.map_config;
+
+        if let Some((entity, layer, ref mut path)) = &mut move_info.0 {
+            let layer = *layer;
+            let mut unit_sync = query_units.get_mut(*entity).unwrap();
+            match path.pop_front() {
+                Some(next_stop) => {
+                    // If the current position is a genome facility and you're moving off it, then
+                    // you should no longer have that genome unlockd
+                    let auth_map = &query_tilemap.get(game.room.map_entity).unwrap().children;
+                    let auth_tile_old = query_tile
+                        .get(
+                            auth_map[tile_qrz_to_index(
+                                &map_config,
+                                unit_sync.position.column_q,
+                                unit_sync.position.row_r,
+                                *unit_sync.layer,
+                            )],
+                        )
+                        .unwrap();
+
+                    if let TileStructure::GenomeFacility { unique_genome, .. } =
+                        &*auth_tile_old.structure
+                    {
+                        let key = game.key_units_assoc.get_from_entity(*entity).unwrap();
+                        let genomes = unlocked_genomes.key_to_genomes.get_mut(key).unwrap();
+
+                        server.send_message(
+                            key,
+                            Channels::GameNotification,
+                            &GenomeStatusChange::new(unique_genome.clone(), LockedStatus::Locked),
+                        );
+
+                        *genomes = genomes
+                            .drain(..)
+                            .filter(|elem| elem != unique_genome)
+                            .collect();
+                    }
 
-        std::mem::drop(unit_sync);
-
-        if run_updates {
-            should_update.0 = false;
-            let auth_map = &query_tilemap.get(main_room.map_entity).unwrap().children;
-            // Updates tiles in view for every player
-            for user_key in server.user_keys() {
-                if let Some(units) = key_units_assoc.get_from_key(user_key) {
-                    // Build a list of hexes visible to any unit belonging to this player
-                    let mut valid_qrs = Vec::new();
-                    for unit in units {
-                        if let Ok(unit_sync) = query_units.get(*unit) {
-                            let viewing_distance =
-                                unit_sync.hybrid_type.head().viewing_distance as i32;
-                            let pos = *unit_sync.position;
-
-                            // Finds all tiles within viewing_distance
-                            for q_offset in -viewing_distance..=viewing_distance {
-                                for r_offset in
-                                    std::cmp::max(-viewing_distance, -q_offset - viewing_distance)
-                                        ..=std::cmp::min(
-                                            viewing_distance,
-                                            -q_offset + viewing_distance,
-                                        )
-                                {
-                                    let q = pos.column_q as i32 + q_offset;
-                                    let r = pos.row_r as i32 + r_offset;
-
-                                    if q >= 0
-                                        && r >= 0
-                                        && q <= i32::MAX.into()
-                                        && r <= i32::MAX.into()
-                                    {
-                                        valid_qrs.push(AxialCoordinates::new(q as i32, r as i32));
-                                    }
-                                }
-                            }
-                        }
+                    // And if the new position is a genome facility that you're moving onto, then
+                    // it shoulld be added
+                    let auth_tile_new = query_tile
+                        .get(
+                            auth_map[tile_qrz_to_index(
+                                &map_config,
+                                next_stop.column_q,
+                                next_stop.row_r,
+                                layer,
+                            )],
+                        )
+                        .unwrap();
+
+                    if let TileStructure::GenomeFacility { unique_genome, .. } =
+                        &*auth_tile_new.structure
+                    {
+                        let key = game.key_units_assoc.get_from_entity(*entity).unwrap();
+                        let genomes = unlocked_genomes.key_to_genomes.get_mut(key).unwrap();
+
+                        server.send_message(
+                            key,
+                            Channels::GameNotification,
+                            &GenomeStatusChange::new(unique_genome.clone(), LockedStatus::Unlocked),
+                        );
+
+                        genomes.push(unique_genome.clone());
                     }
 
-                    // With the tiles in range of all units, update the subjective map
-                    let subjective_map = &query_tilemap
-                        .get(*key_map_assoc.get_from_key(&user_key).unwrap())
-                        .unwrap()
-                        .children;
-
-                    for z in 0..MAP_HEIGHT as i32 {
-                        for r in 0..map_config.size_height as i32 {
-                            for q in 0..map_config.size_width as i32 {
-                                let qr = AxialCoordinates::new(q, r);
-
-                                let [mut subj_tile, auth_tile] = query_tile
-                                    .get_many_mut([
-                                        subjective_map
-                                            [TileMap::tile_qrz_to_index(&map_config, q, r, z)],
-                                        auth_map[TileMap::tile_qrz_to_index(&map_config, q, r, z)],
-                                    ])
-                                    .unwrap();
-
-                                if valid_qrs.contains(&qr) {
-                                    *subj_tile.tile_type = *auth_tile.tile_type.clone();
-                                    *subj_tile.structure = (*auth_tile.structure).clone();
-                                } else {
-                                    if *subj_tile.tile_type != TileType::Fog {
-                                        *subj_tile.tile_type = TileType::Fog;
-                                        *subj_tile.structure = TileStructure::None;
-                                    }
-                                }
-                            }
-                        }
+                    // Pay for the step with the same costs the path was validated against
+                    let cost = step_cost(&unit_sync.hybrid_type, *auth_tile_new.tile_type)
+                        .expect("validated path stepped onto an impassable tile");
+                    *unit_sync.stamina_remaining = unit_sync.stamina_remaining.saturating_sub(cost);
+
+                    // Process the move making sure to update, one tile per tick
+                    *unit_sync.position = next_stop;
+                    if *unit_sync.layer != layer {
+                        *unit_sync.layer = layer;
+                    }
+                    if path.is_empty() {
+                        move_info.0 = None;
                     }
                 }
+                None => {
+                    // Clear an empty path
+                    move_info.0 = None;
+                }
             }
         }
-    } else if should_update.0 {
-        // TODO: Don't Copy/Paste
-        should_update.0 = false;
-        let auth_map = &query_tilemap.get(main_room.map_entity).unwrap().children;
-        // Updates tiles in view for every player
-        for user_key in server.user_keys() {
-            if let Some(units) = key_units_assoc.get_from_key(user_key) {
-                // Build a list of hexes visible to any unit belonging to this player
-                let mut valid_qrs = Vec::new();
-                for unit in units {
-                    if let Ok(unit_sync) = query_units.get(*unit) {
-                        let viewing_distance = unit_sync.hybrid_type.head().viewing_distance as i32;
-                        let pos = *unit_sync.position;
-
-                        // Finds all tiles within viewing_distance
-                        for q_offset in -viewing_distance..=viewing_distance {
-                            for r_offset in
-                                std::cmp::max(-viewing_distance, -q_offset - viewing_distance)
-                                    ..=std::cmp::min(viewing_distance, -q_offset + viewing_distance)
-                            {
-                                let q = pos.column_q as i32 + q_offset;
-                                let r = pos.row_r as i32 + r_offset;
-
-                                if q >= 0 && r >= 0 && q <= i32::MAX.into() && r <= i32::MAX.into()
-                                {
-                                    valid_qrs.push(AxialCoordinates::new(q as i32, r as i32));
-                                }
-                            }
-                        }
-                    }
+
+        // Cheap when nothing moved, as only what changed around units is looked at again
+        update_perspectives(
+            &query_tilemap,
+            &query_tile,
+            &mut query_chunk,
+            &query_units,
+            &mut game.fog,
+            &map_config,
+            &game.room,
+            &game.key_map_assoc,
+            &game.key_units_assoc,
+        );
+
+        for (_, user_key, entity) in scope_checks
+            .iter()
+            .filter(|(room_key, ..)| *room_key == game.room.key)
+        {
+            // Only send updates from tiles in a user's perceived map
+            let tilemap = query_tilemap
+                .get(*game.key_map_assoc.get_from_key(user_key).unwrap())
+                .unwrap();
+            let units = game.key_units_assoc.get_from_key(*user_key);
+
+            let mut in_scope = false;
+
+            // If the unit belongs to a player, it should be in scope
+            if let Some(units) = units {
+                if units.contains(entity) {
+                    in_scope = true;
+                    server.user_scope(user_key).include(entity);
                 }
+            }
+
+            // If the chunk is a part of that player's subjective map, it should be in scope
+            if tilemap.children.contains(entity) {
+                in_scope = true;
+                server.user_scope(user_key).include(entity);
+            }
 
-                // With the tiles in range of all units, update the subjective map
-                let subjective_map = &query_tilemap
-                    .get(*key_map_assoc.get_from_key(&user_key).unwrap())
-                    .unwrap()
-                    .children;
-
-                for z in 0..MAP_HEIGHT as i32 {
-                    for r in 0..map_config.size_height as i32 {
-                        for q in 0..map_config.size_width as i32 {
-                            let qr = AxialCoordinates::new(q, r);
-
-                            let [mut subj_tile, auth_tile] = query_tile
-                                .get_many_mut([
-                                    subjective_map
-                                        [TileMap::tile_qrz_to_index(&map_config, q, r, z)],
-                                    auth_map[TileMap::tile_qrz_to_index(&map_config, q, r, z)],
-                                ])
-                                .unwrap();
-
-                            if valid_qrs.contains(&qr) {
-                                *subj_tile.tile_type = *auth_tile.tile_type.clone();
-                                *subj_tile.structure = (*auth_tile.structure).clone();
-                            } else {
-                                if *subj_tile.tile_type != TileType::Fog {
-                                    *subj_tile.tile_type = TileType::Fog;
-                                    *subj_tile.structure = TileStructure::None;
-                                }
-                            }
-                        }
+            // If the unit is simply in view, it should be in scope
+            if let Ok(unit_any_player) = query_units.get(*entity) {
+                let pos = *unit_any_player.position;
+                let layer = *unit_any_player.layer;
+
+                // So check if the tile a given unit is on is in view, if it is, the unit is also
+                // in view. Remembered tiles don't count, as units there can't be seen any more.
+                if let Ok(chunk) = query_chunk.get(tilemap.chunk_of(&map_config, pos)) {
+                    if chunk.is_visible(pos, layer) {
+                        in_scope = true;
+                        server.user_scope(user_key).include(entity);
+                    } else {
+                        server.user_scope(user_key).exclude(entity);
                     }
                 }
             }
+
+            if !in_scope {
+                server.user_scope(user_key).exclude(entity);
+            }
         }
     }
+}
 
-    for (_, user_key, entity) in server.scope_checks() {
-        // Only send updates from tiles in a user's perceived map
-        let tilemap = &query_tilemap
-            .get(*key_map_assoc.get_from_key(&user_key).unwrap())
-            .unwrap()
-            .children;
-        let units = key_units_assoc.get_from_key(user_key);
+/// Brings every player's perceived map up to date with what their units can currently see,
+/// touching only the tiles whose visibility or contents changed
+fn update_perspectives(
+    query_tilemap: &Query<&TileMap>,
+    query_tile: &Query<&MapSync>,
+    query_chunk: &mut Query<&mut MapChunkSync>,
+    query_units: &Query<&mut UnitSync>,
 
-        let mut in_scope = false;
+    fog: &mut FogOfWar,
 
-        // If the unit belongs to a player, it should be in scope
-        if let Some(units) = units {
-            if units.contains(&entity) {
-                in_scope = true;
-                server.user_scope(&user_key).include(&entity);
-            }
+    map_config: &MapConfig,
+    room: &MatchRoom,
+    key_map_assoc: &KeyMapAssociation,
+    key_units_assoc: &KeyUnitsAssociation,
+) {
+    let auth_map = &query_tilemap.get(room.map_entity).unwrap().children;
+
+    let terrain = |qr: AxialCoordinates, z| {
+        qr.is_in_bounds(map_config).then(|| {
+            *query_tile
+                .get(auth_map[tile_qrz_to_index(map_config, qr.column_q, qr.row_r, z)])
+                .unwrap()
+                .tile_type
+        })
+    };
+
+    // Bots and players who lost their connection are kept up to date too, as bots play from what
+    // their perceived maps show
+    for user_key in key_map_assoc.keys().copied() {
+        // A player whose units have all died sees nothing
+        let units = key_units_assoc
+            .get_from_key(user_key)
+            .into_iter()
+            .flatten()
+            .filter_map(|unit| {
+                let unit_sync = query_units.get(*unit).ok()?;
+                let viewing_distance = unit_sync.hybrid_type.head().viewing_distance as u32;
+
+                Some((
+                    *unit,
+                    Viewpoint::new(*unit_sync.position, *unit_sync.layer, viewing_distance),
+                ))
+            });
+        let stale = fog.refresh(&user_key, units, terrain);
+
+        if stale.is_empty() {
+            continue;
         }
 
-        // If the tilemap is a part of that player's subjective map, it should be in scope
-        if tilemap.contains(&entity) {
-            in_scope = true;
-            server.user_scope(&user_key).include(&entity);
-        }
+        let subjective_map = query_tilemap
+            .get(*key_map_assoc.get_from_key(&user_key).unwrap())
+            .unwrap();
+
+        for StaleTile {
+            position,
+            layer,
+            visible,
+        } in stale
+        {
+            let mut chunk = query_chunk
+                .get_mut(subjective_map.chunk_of(map_config, position))
+                .unwrap();
+
+            if visible {
+                let auth_tile = query_tile
+                    .get(
+                        auth_map[tile_qrz_to_index(
+                            map_config,
+                            position.column_q,
+                            position.row_r,
+                            layer,
+                        )],
+                    )
+                    .unwrap();
 
-        // If the unit is simply in view, it should be in scope
-        if let Ok(unit_any_player) = query_units.get(entity) {
-            let pos = *unit_any_player.position;
-
-            // So check if the tile a given unit is on is in view, if it is, the unit is also in view
-            if let Ok(tile) = query_tile.get(
-                tilemap[tile_qrz_to_index(&map_config, pos.column_q.into(), pos.row_r.into(), 0)],
-            ) {
-                if *tile.tile_type == TileType::Fog {
-                    server.user_scope(&user_key).exclude(&entity);
-                } else {
-                    in_scope = true;
-                    server.user_scope(&user_key).include(&entity);
-                }
+                chunk.set_tile(
+                    position,
+                    layer,
+                    *auth_tile.tile_type,
+                    (*auth_tile.structure).clone(),
+                );
+            } else {
+                // Terrain stays as it was last seen, but whatever happens there now is hidden
+                chunk.remember_tile(position, layer);
             }
         }
-
-        if !in_scope {
-            server.user_scope(&user_key).exclude(&entity);
-        }
     }
 
-    server.send_all_updates();
+    fog.clear_changed_tiles();
 }
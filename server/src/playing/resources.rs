@@ -1,73 +1,168 @@
 use std::{
     collections::{HashMap, HashSet, VecDeque},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use bevy::prelude::*;
 use naia_bevy_server::{Server, UserKey};
 
 use rgj_shared::{
-    behavior::AxialCoordinates,
+    behavior::{movement::max_stamina, AxialCoordinates},
     components::genome::AnimalType,
     protocol::{
         game_sync::{
             map_sync::{tile_qrz_to_index, ConstructionStatus, MapSync, TileStructure},
             unit_sync::UnitSync,
         },
-        notifications::WhoseTurn,
-        GameStartNotification, Protocol, TurnChangeNotification,
+        notifications::{game_over::Standing, WhoseTurn},
+        GameOverNotification, GameStartNotification, Protocol, TurnChangeNotification,
     },
     resources::MapConfig,
     Channels,
 };
 
+use super::victory::{VictoryTracker, WinConditions};
 use crate::{
     components::TileMap,
-    resources::{KeyIdAssociation, KeyUnitsAssociation, MainRoom, UsernameKeyAssociation},
+    perspective::FogOfWar,
+    resources::{KeyIdAssociation, KeyUnitsAssociation, MatchRoom, UsernameKeyAssociation},
 };
 
-/// Tracks the current moving unit so that it walks along the given path
+/// Tracks the current moving unit so that it walks along the given path on the given layer. Taking
+/// off or landing is a single step onto the same hex on a different layer.
 /// NOTE: This assumes the path has been verified as valid
-pub struct UnitMoveInformation(pub Option<(Entity, VecDeque<AxialCoordinates>)>);
+pub struct UnitMoveInformation(pub Option<(Entity, i32, VecDeque<AxialCoordinates>)>);
+
+/// Limits how long each turn can last. Once a turn's own time has run out the player's time bank
+/// is drawn from, and only once that is empty too is their turn over.
+pub struct TurnClock {
+    turn_time: Duration,
+    banks: HashMap<UserKey, Duration>,
+    turn_started: Instant,
+    /// The whole seconds last sent to the players, so that they are only sent again once changed
+    last_sent: Option<(u32, u32)>,
+}
+
+impl TurnClock {
+    pub fn new(
+        turn_time: Duration,
+        time_bank: Duration,
+        players: impl IntoIterator<Item = UserKey>,
+    ) -> TurnClock {
+        TurnClock {
+            turn_time,
+            banks: players.into_iter().map(|key| (key, time_bank)).collect(),
+            turn_started: Instant::now(),
+            last_sent: None,
+        }
+    }
+
+    /// The time left of the current turn, and in the bank of the player whose turn it is
+    pub fn remaining(&self, player: UserKey) -> (Duration, Duration) {
+        let elapsed = self.turn_started.elapsed();
+        let bank = self.banks.get(&player).copied().unwrap_or_default();
+
+        (
+            self.turn_time.saturating_sub(elapsed),
+            bank.saturating_sub(elapsed.saturating_sub(self.turn_time)),
+        )
+    }
+
+    pub fn is_expired(&self, player: UserKey) -> bool {
+        let (turn, bank) = self.remaining(player);
+
+        turn.is_zero() && bank.is_zero()
+    }
+
+    /// The whole seconds left of the turn and in the player's bank, rounded up, if they changed
+    /// since this last returned them
+    pub fn seconds_to_send(&mut self, player: UserKey) -> Option<(u32, u32)> {
+        let (turn, bank) = self.remaining(player);
+        let seconds = (ceil_secs(turn), ceil_secs(bank));
+
+        if self.last_sent == Some(seconds) {
+            None
+        } else {
+            self.last_sent = Some(seconds);
+            Some(seconds)
+        }
+    }
+
+    /// Makes the next call to [`TurnClock::seconds_to_send`] return the time left even if it
+    /// hasn't changed, for a player who missed it
+    pub fn resend(&mut self) {
+        self.last_sent = None;
+    }
+
+    pub fn replace_key(&mut self, old: &UserKey, new: UserKey) {
+        if let Some(bank) = self.banks.remove(old) {
+            self.banks.insert(new, bank);
+        }
+    }
+
+    /// Stops the clock on the player whose turn is ending, taking whatever time they went over by
+    /// out of their bank, and starts it again for the next turn
+    fn restart(&mut self, player: UserKey) {
+        let (_, bank) = self.remaining(player);
+        self.banks.insert(player, bank);
+
+        self.turn_started = Instant::now();
+        self.last_sent = None;
+    }
+}
+
+fn ceil_secs(duration: Duration) -> u32 {
+    (duration.as_secs() + (duration.subsec_nanos() > 0) as u64) as u32
+}
 
 pub struct TurnTracker {
     pub player: UserKey,
     pub turn_number: u16,
-    pub time_left: Option<Duration>,
+    /// Ends turns which take too long, when the game has a time limit
+    pub clock: Option<TurnClock>,
 
     first_player: UserKey,
     players: VecDeque<UserKey>,
+
+    victory: VictoryTracker,
+    finished: bool,
 }
 
 impl TurnTracker {
     pub fn new(
         server: &mut Server<Protocol, Channels>,
 
+        room: &MatchRoom,
         user_key_assoc: &UsernameKeyAssociation,
         key_id_assoc: &KeyIdAssociation,
 
         mut players: VecDeque<UserKey>,
-        turn_timers: Option<Duration>,
+        clock: Option<TurnClock>,
+        win_conditions: WinConditions,
+        seed: u64,
     ) -> TurnTracker {
         let player = players.pop_front().unwrap();
         players.push_back(player);
 
-        for key in server.user_keys() {
+        for key in room.users() {
             if key == player {
                 server.send_message(
                     &key,
                     Channels::GameNotification,
-                    &GameStartNotification::new_complete(WhoseTurn::Yours { turn_number: 1 }),
+                    &GameStartNotification::new_complete(WhoseTurn::Yours { turn_number: 1 }, seed),
                 );
             } else {
                 server.send_message(
                     &key,
                     Channels::GameNotification,
-                    &GameStartNotification::new_complete(WhoseTurn::Player {
-                        username: user_key_assoc.get_from_key(&player).unwrap().to_owned(),
-                        id: *key_id_assoc.get_from_key(&player).unwrap(),
-                        turn_number: 1,
-                    }),
+                    &GameStartNotification::new_complete(
+                        WhoseTurn::Player {
+                            username: user_key_assoc.get_from_key(&player).unwrap().to_owned(),
+                            id: *key_id_assoc.get_from_key(&player).unwrap(),
+                            turn_number: 1,
+                        },
+                        seed,
+                    ),
                 );
             }
         }
@@ -77,13 +172,64 @@ impl TurnTracker {
         TurnTracker {
             player: player,
             turn_number: 1,
-            time_left: turn_timers,
+            clock,
             first_player: player,
             players,
+            victory: VictoryTracker::new(win_conditions),
+            finished: false,
         }
     }
 
-    // TODO: OnTurn event
+    /// Whether somebody has won the game
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Whose turn it is, as told to the player with the given key
+    pub fn whose_turn_for(
+        &self,
+        key: UserKey,
+        user_key_assoc: &UsernameKeyAssociation,
+        key_id_assoc: &KeyIdAssociation,
+    ) -> WhoseTurn {
+        if key == self.player {
+            WhoseTurn::Yours {
+                turn_number: self.turn_number,
+            }
+        } else {
+            WhoseTurn::Player {
+                username: user_key_assoc
+                    .get_from_key(&self.player)
+                    .unwrap()
+                    .to_owned(),
+                id: *key_id_assoc.get_from_key(&self.player).unwrap(),
+                turn_number: self.turn_number,
+            }
+        }
+    }
+
+    /// Gives the seat of the player with the `old` key to `new`, keeping their place in the turn
+    /// order, their time bank and their progress towards winning
+    pub fn replace_key(&mut self, old: UserKey, new: UserKey) {
+        for key in self.players.iter_mut() {
+            if *key == old {
+                *key = new;
+            }
+        }
+
+        if self.player == old {
+            self.player = new;
+        }
+        if self.first_player == old {
+            self.first_player = new;
+        }
+
+        if let Some(clock) = &mut self.clock {
+            clock.replace_key(&old, new);
+        }
+        self.victory.replace_key(old, new);
+    }
+
     pub fn next(
         &mut self,
         server: &mut Server<Protocol, Channels>,
@@ -93,15 +239,39 @@ impl TurnTracker {
         query_tilemap: &Query<&TileMap>,
         // TODO: Query<&mut MapSync, With<Authoritative>>,
         query_tile: &mut Query<(Entity, &mut MapSync)>,
+        query_unit: &mut Query<&mut UnitSync>,
 
         map_config: MapConfig,
-        main_room: &MainRoom,
+        room: &MatchRoom,
         key_units_assoc: &mut KeyUnitsAssociation,
-        should_update: &mut ShouldUpdate,
+        fog: &mut FogOfWar,
     ) {
+        if self.finished {
+            return;
+        }
+
+        self.check_victory(
+            server,
+            user_key_assoc,
+            key_id_assoc,
+            query_tilemap,
+            query_tile,
+            query_unit,
+            map_config,
+            room,
+            key_units_assoc,
+        );
+
+        if self.finished {
+            return;
+        }
+
         let player = self.players.pop_front().unwrap();
         self.players.push_back(player);
 
+        if let Some(clock) = &mut self.clock {
+            clock.restart(self.player);
+        }
         self.player = player;
 
         if player == self.first_player {
@@ -114,7 +284,7 @@ impl TurnTracker {
             username: user_key_assoc.get_from_key(&self.player).unwrap().clone(),
         };
 
-        let auth_map = &query_tilemap.get(main_room.map_entity).unwrap().children;
+        let auth_map = &query_tilemap.get(room.map_entity).unwrap().children;
 
         let mut tiles_to_change = Vec::new();
 
@@ -136,18 +306,17 @@ impl TurnTracker {
                         if finished_on == &mut current_turn {
                             let id = server
                                 .spawn()
-                                .enter_room(&main_room.key)
+                                .enter_room(&room.key)
                                 .insert(UnitSync::new_complete(
                                     **position,
                                     **layer,
                                     *key_id_assoc.get_from_key(&self.player).unwrap(),
                                     building.clone(),
                                     building.body().health,
-                                    building.limbs().terrain_a.tiles_per_turn.into(),
+                                    max_stamina(building),
                                 ))
                                 .id();
 
-                            should_update.0 = true;
                             key_units_assoc.insert(self.player, id);
                             tiles_to_change.push((**position, **layer));
                         }
@@ -165,10 +334,13 @@ impl TurnTracker {
             } = *tile.structure
             {
                 *building = None;
+                fog.tile_changed(*qr, *z);
             }
         }
 
-        for key in server.user_keys() {
+        self.start_turn(key_units_assoc, query_unit);
+
+        for key in room.users() {
             if key == player {
                 server.send_message(
                     &key,
@@ -190,10 +362,137 @@ impl TurnTracker {
             }
         }
     }
+
+    /// Eliminates any players with no units left and ends the game if a win condition has been met
+    fn check_victory(
+        &mut self,
+        server: &mut Server<Protocol, Channels>,
+        user_key_assoc: &UsernameKeyAssociation,
+        key_id_assoc: &KeyIdAssociation,
+
+        query_tilemap: &Query<&TileMap>,
+        query_tile: &Query<(Entity, &mut MapSync)>,
+        query_unit: &Query<&mut UnitSync>,
+
+        map_config: MapConfig,
+        room: &MatchRoom,
+        key_units_assoc: &KeyUnitsAssociation,
+    ) {
+        let auth_map = &query_tilemap.get(room.map_entity).unwrap().children;
+
+        // Count the distinct genome facilities each player has a unit standing on
+        let mut facilities_held = HashMap::new();
+        for key in &self.players {
+            let mut facilities = HashSet::new();
+
+            for unit in key_units_assoc.get_from_key(*key).into_iter().flatten() {
+                if let Ok(unit_sync) = query_unit.get(*unit) {
+                    let (q, r, z) = (
+                        unit_sync.position.column_q,
+                        unit_sync.position.row_r,
+                        *unit_sync.layer,
+                    );
+
+                    if let Ok((_e, tile)) =
+                        query_tile.get(auth_map[tile_qrz_to_index(&map_config, q, r, z)])
+                    {
+                        if let TileStructure::GenomeFacility { .. } = *tile.structure {
+                            facilities.insert((q, r, z));
+                        }
+                    }
+                }
+            }
+
+            facilities_held.insert(*key, facilities.len());
+        }
+
+        let remaining: Vec<UserKey> = self.players.iter().copied().collect();
+        let check = self.victory.check(
+            self.turn_number,
+            &remaining,
+            |key| {
+                key_units_assoc
+                    .get_from_key(key)
+                    .map(|units| !units.is_empty())
+                    .unwrap_or(false)
+            },
+            &facilities_held,
+        );
+
+        for key in check.eliminated {
+            info!(
+                "{} has no units left and is eliminated",
+                user_key_assoc.get_from_key(&key).unwrap()
+            );
+            self.remove_player(key);
+        }
+
+        if let Some((winner, victory)) = check.winner {
+            let remaining: Vec<UserKey> = self.players.iter().copied().collect();
+            let standings = self
+                .victory
+                .standings(winner, &remaining, &facilities_held)
+                .into_iter()
+                .map(|(key, eliminated_on_turn)| Standing {
+                    username: user_key_assoc.get_from_key(&key).unwrap().to_owned(),
+                    id: *key_id_assoc.get_from_key(&key).unwrap(),
+                    eliminated_on_turn,
+                })
+                .collect::<Vec<_>>();
+
+            info!(
+                "{} has won the game: {:?}",
+                user_key_assoc.get_from_key(&winner).unwrap(),
+                victory
+            );
+
+            let notification = GameOverNotification::new(victory, standings);
+            for key in room.users() {
+                server.send_message(&key, Channels::GameNotification, &notification);
+            }
+
+            self.finished = true;
+        }
+    }
+
+    /// Takes a player out of the turn order, handing the start of each round to the next player if
+    /// it was theirs
+    fn remove_player(&mut self, key: UserKey) {
+        let index = match self.players.iter().position(|player| *player == key) {
+            Some(index) => index,
+            None => return,
+        };
+
+        self.players.remove(index);
+
+        if key == self.first_player && !self.players.is_empty() {
+            self.first_player = self.players[index % self.players.len()];
+        }
+    }
+
+    /// The turn-start phase for the player whose turn is beginning. Any effect which should be
+    /// applied to a player's units once per turn belongs here.
+    fn start_turn(
+        &self,
+        key_units_assoc: &KeyUnitsAssociation,
+        query_unit: &mut Query<&mut UnitSync>,
+    ) {
+        let units = match key_units_assoc.get_from_key(self.player) {
+            Some(units) => units,
+            None => return,
+        };
+
+        for unit in units {
+            if let Ok(mut unit_sync) = query_unit.get_mut(*unit) {
+                let stamina = max_stamina(&unit_sync.hybrid_type);
+                if *unit_sync.stamina_remaining != stamina {
+                    *unit_sync.stamina_remaining = stamina;
+                }
+            }
+        }
+    }
 }
 
 pub struct KeyToUnlockedGenomesMap {
     pub key_to_genomes: HashMap<UserKey, Vec<AnimalType>>,
 }
-
-pub struct ShouldUpdate(pub bool);
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use naia_bevy_server::UserKey;
+
+use rgj_shared::protocol::notifications::game_over::Victory;
+
+use crate::Args;
+
+/// Holding at least `facilities` genome facilities for `turns` turns in a row wins the game
+#[derive(Clone, Copy, Debug)]
+pub struct HoldFacilities {
+    pub facilities: u8,
+    pub turns: u16,
+}
+
+/// The ways a game may be won. Being the last player with any units left always wins, the rest
+/// are optional.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WinConditions {
+    pub hold_facilities: Option<HoldFacilities>,
+}
+
+impl WinConditions {
+    pub fn from_args(args: &Args) -> WinConditions {
+        WinConditions {
+            hold_facilities: args.hold_facilities.map(|facilities| HoldFacilities {
+                facilities,
+                turns: args.hold_turns,
+            }),
+        }
+    }
+}
+
+/// What changed in a single check of the win conditions
+#[derive(Default)]
+pub struct VictoryCheck {
+    pub eliminated: Vec<UserKey>,
+    pub winner: Option<(UserKey, Victory)>,
+}
+
+/// Tracks everything needed to decide when, and in what order, players win or lose
+pub struct VictoryTracker {
+    conditions: WinConditions,
+
+    /// Every eliminated player in the order they were eliminated, with the turn it happened on
+    eliminated: Vec<(UserKey, u16)>,
+    /// The turn on which each player began holding enough genome facilities without a break
+    holding_since: HashMap<UserKey, u16>,
+}
+
+impl VictoryTracker {
+    pub fn new(conditions: WinConditions) -> VictoryTracker {
+        VictoryTracker {
+            conditions,
+            eliminated: Vec::new(),
+            holding_since: HashMap::new(),
+        }
+    }
+
+    /// Carries everything tracked for the player with the `old` key over to `new`
+    pub fn replace_key(&mut self, old: UserKey, new: UserKey) {
+        for (key, _) in &mut self.eliminated {
+            if *key == old {
+                *key = new;
+            }
+        }
+
+        if let Some(turn) = self.holding_since.remove(&old) {
+            self.holding_since.insert(new, turn);
+        }
+    }
+
+    /// Checks every win condition against the players still in the game.
+    ///
+    /// `has_units` says whether a player has any units left, and `facilities_held` is the number of
+    /// genome facilities each remaining player currently has a unit standing on.
+    pub fn check<F>(
+        &mut self,
+        turn_number: u16,
+        remaining: &[UserKey],
+        has_units: F,
+        facilities_held: &HashMap<UserKey, usize>,
+    ) -> VictoryCheck
+    where
+        F: Fn(UserKey) -> bool,
+    {
+        let mut check = VictoryCheck::default();
+
+        // Players with nothing left are out of the game, unless that would leave nobody at all
+        let (alive, out): (Vec<UserKey>, Vec<UserKey>) =
+            remaining.iter().partition(|key| has_units(**key));
+        if !alive.is_empty() {
+            for key in out {
+                self.eliminated.push((key, turn_number));
+                self.holding_since.remove(&key);
+                check.eliminated.push(key);
+            }
+        }
+
+        let alive = if alive.is_empty() {
+            remaining.to_vec()
+        } else {
+            alive
+        };
+
+        if alive.len() == 1 {
+            check.winner = Some((alive[0], Victory::LastPlayerStanding));
+            return check;
+        }
+
+        if let Some(HoldFacilities { facilities, turns }) = self.conditions.hold_facilities {
+            for key in &alive {
+                let held = facilities_held.get(key).copied().unwrap_or(0);
+
+                if held >= facilities as usize {
+                    let since = *self.holding_since.entry(*key).or_insert(turn_number);
+
+                    if check.winner.is_none() && turn_number - since >= turns {
+                        check.winner = Some((*key, Victory::HeldFacilities { facilities, turns }));
+                    }
+                } else {
+                    self.holding_since.remove(key);
+                }
+            }
+        }
+
+        check
+    }
+
+    /// Every player in the order they placed, along with the turn they were eliminated on. The
+    /// winner comes first, then the players still in the game by facilities held, then the
+    /// eliminated players starting with whoever lasted longest.
+    pub fn standings(
+        &self,
+        winner: UserKey,
+        remaining: &[UserKey],
+        facilities_held: &HashMap<UserKey, usize>,
+    ) -> Vec<(UserKey, Option<u16>)> {
+        let mut others: Vec<UserKey> = remaining
+            .iter()
+            .copied()
+            .filter(|key| *key != winner)
+            .collect();
+        others.sort_by_key(|key| std::cmp::Reverse(facilities_held.get(key).copied().unwrap_or(0)));
+
+        std::iter::once((winner, None))
+            .chain(others.into_iter().map(|key| (key, None)))
+            .chain(
+                self.eliminated
+                    .iter()
+                    .rev()
+                    .filter(|(key, _)| *key != winner)
+                    .map(|(key, turn)| (*key, Some(*turn))),
+            )
+            .collect()
+    }
+}
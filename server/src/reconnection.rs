@@ -0,0 +1,170 @@
+//! Letting players who lose their connection part way through a game take their seat back. These
+//! systems handle connections in every state after the game has started.
+
+use bevy::prelude::*;
+use naia_bevy_server::{
+    events::{AuthorizationEvent, ConnectionEvent, DisconnectionEvent},
+    Server,
+};
+
+use rgj_shared::{
+    protocol::{Protocol, RejoinNotification},
+    Channels,
+};
+
+use crate::{components::PerspectiveTileMap, matches::Matches, GameState};
+
+/// Only lets in players taking back a seat they lost the connection to, with the token they were
+/// given when the game started
+pub fn authorization_event(
+    mut event_reader: EventReader<AuthorizationEvent<Protocol>>,
+    mut server: Server<Protocol, Channels>,
+
+    mut matches: ResMut<Matches>,
+) {
+    for event in event_reader.iter() {
+        if let AuthorizationEvent(user_key, Protocol::Identification(auth)) = event {
+            // Rooms still waiting for players, or with nobody in them yet, are left to
+            // waiting_for_connections
+            let game = match matches.get_mut(&auth.room_name) {
+                Some(game) if game.state() != GameState::WaitingForConnections => game,
+                _ => continue,
+            };
+
+            let seat = game
+                .user_key_assoc
+                .get_from_name(&*auth.username)
+                .copied()
+                .filter(|old| game.reconnections.is_disconnected(old));
+
+            match (seat, *auth.reconnect_token) {
+                _ if game.state() == GameState::Finished => {
+                    info!("Rejecting connection: the game in {} is over", game.name);
+                    server.reject_connection(user_key);
+                }
+                (Some(old), Some(token))
+                    if game.is_password(&auth.room_password)
+                        && game.reconnections.is_valid(&*auth.username, token) =>
+                {
+                    info!("Accepting reconnection from {}", *auth.username);
+                    game.reconnections.authorize(*user_key, old);
+                    server.accept_connection(user_key);
+                }
+                _ => {
+                    info!("Rejecting connection: the game has already started");
+                    server.reject_connection(user_key);
+                }
+            }
+        }
+    }
+}
+
+/// Hands everything that belonged to a reconnecting player's old connection to their new one, and
+/// catches them up on what isn't replicated
+pub fn connection_event(
+    mut event_reader: EventReader<ConnectionEvent>,
+    mut server: Server<Protocol, Channels>,
+
+    mut query_perspective: Query<&mut PerspectiveTileMap>,
+
+    mut matches: ResMut<Matches>,
+) {
+    for ConnectionEvent(user_key) in event_reader.iter() {
+        let game = match matches.of_user_mut(user_key) {
+            Some(game) => game,
+            None => continue,
+        };
+        let old = match game.reconnections.take_pending(user_key) {
+            Some(old) => old,
+            None => continue,
+        };
+        let new = *user_key;
+
+        game.user_key_assoc.replace_key(&old, new);
+        game.key_id_assoc.replace_key(&old, new);
+        game.key_map_assoc.replace_key(&old, new);
+        game.key_units_assoc.replace_key(old, new);
+        game.fog.replace_key(&old, new);
+
+        if let Some(map_entity) = game.key_map_assoc.get_from_key(&new) {
+            if let Ok(mut perspective) = query_perspective.get_mut(*map_entity) {
+                perspective.0 = new;
+            }
+        }
+
+        server.user_mut(&new).enter_room(&game.room.key);
+        game.room.join(new);
+        info!(
+            "{} reconnected and took back their seat in {}",
+            game.user_key_assoc.get_from_key(&new).unwrap(),
+            game.name
+        );
+
+        // Everything replicated is sent again as the player is scoped back in, but whose turn it
+        // is and their genomes are only ever sent as they change
+        if let Some(playing) = &mut game.playing {
+            let turn_tracker = &mut playing.turn_tracker;
+            let unlocked_genomes = &mut playing.unlocked_genomes;
+
+            turn_tracker.replace_key(old, new);
+            if let Some(clock) = &mut turn_tracker.clock {
+                clock.resend();
+            }
+
+            let genomes = unlocked_genomes
+                .key_to_genomes
+                .remove(&old)
+                .unwrap_or_default();
+            unlocked_genomes.key_to_genomes.insert(new, genomes.clone());
+
+            server.send_message(
+                &new,
+                Channels::GameNotification,
+                &RejoinNotification::new_complete(
+                    turn_tracker.whose_turn_for(new, &game.user_key_assoc, &game.key_id_assoc),
+                    game.rng.seed(),
+                    genomes,
+                ),
+            );
+        }
+    }
+}
+
+/// Holds the seat of a player who lost their connection so that they can take it back
+pub fn disconnection_event(
+    mut event_reader: EventReader<DisconnectionEvent>,
+
+    mut matches: ResMut<Matches>,
+) {
+    for DisconnectionEvent(user_key, user) in event_reader.iter() {
+        let game = match matches.of_user_mut(user_key) {
+            Some(game) if game.state() != GameState::WaitingForConnections => game,
+            _ => continue,
+        };
+
+        // A reconnection lost before it finished connecting never took its seat back
+        if game.reconnections.is_pending(user_key) {
+            info!("Lost a reconnection to {} before it finished", user.address);
+            game.reconnections.cancel_pending(user_key);
+            continue;
+        }
+
+        game.room.leave(user_key);
+
+        let username = match game.user_key_assoc.get_from_key(user_key) {
+            Some(username) => username,
+            None => continue,
+        };
+        if game.state() == GameState::Finished {
+            info!("{} on {} left after the game", username, user.address);
+            continue;
+        }
+
+        info!(
+            "Lost the connection to {} on {}, taking over their seat until they are back",
+            username, user.address
+        );
+
+        game.reconnections.disconnected(*user_key);
+    }
+}
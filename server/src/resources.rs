@@ -1,16 +1,157 @@
 //! A module for resource definitions that apply to multiple states. Resources that only apply to an
 //! individual state are defined in that state's module.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use bevy::prelude::*;
 use naia_bevy_server::{RoomKey, UserKey};
-use rgj_shared::components::players::PlayerId;
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use rgj_shared::{components::players::PlayerId, map::MapLayout, resources::MapConfig};
 
-/// The [`RoomKey`] of the overworld map that every player is apart of.
-pub struct MainRoom {
+/// The naia room a match is played in and the entity of its authoritative map
+pub struct MatchRoom {
     pub key: RoomKey,
     pub map_entity: Entity,
+    /// Players connected to the match, in the order they connected. Bots and players who lost
+    /// their connection are not in here.
+    pub users: Vec<UserKey>,
+    /// The [`ClientKeepAlive`](rgj_shared::protocol::ClientKeepAlive) entity spawned for each
+    /// connection while waiting for players
+    pub keep_alives: Vec<Entity>,
+}
+
+impl MatchRoom {
+    pub fn join(&mut self, key: UserKey) {
+        if !self.users.contains(&key) {
+            self.users.push(key);
+        }
+    }
+
+    pub fn leave(&mut self, key: &UserKey) {
+        self.users.retain(|user| user != key);
+    }
+
+    pub fn users(&self) -> impl Iterator<Item = UserKey> + '_ {
+        self.users.iter().copied()
+    }
+}
+
+/// Where the map of each new match comes from
+pub enum MapSource {
+    /// A new map of this size is generated for every match, from the match's seed
+    Generate(MapConfig),
+    /// Every match is played on the same map, loaded once when the server starts
+    Load(MapLayout),
+}
+
+/// The source of all of the game's randomness. Every random choice the server makes goes through
+/// this, so that replaying a game with the same seed reproduces the same map and spawns.
+pub struct GameRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+/// Lets players who lose their connection in the middle of a game take their seat back. Each player
+/// is handed a token when the game starts, and connecting again with the same username and that
+/// token hands everything that was theirs over to their new [`UserKey`].
+pub struct Reconnections {
+    tokens: HashMap<String, u64>,
+    /// Players who have lost their connection, by the [`UserKey`] they had
+    disconnected: HashSet<UserKey>,
+    /// Connections allowed to take a seat back which haven't finished connecting yet, from their
+    /// new [`UserKey`] to the one they had
+    pending: HashMap<UserKey, UserKey>,
+}
+
+impl Reconnections {
+    pub fn new() -> Self {
+        Reconnections {
+            tokens: HashMap::new(),
+            disconnected: HashSet::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Hands a player a new token. Tokens are drawn from outside of the [`GameRng`], as its seed
+    /// is shared with every player.
+    pub fn issue(&mut self, username: String) -> u64 {
+        let token = rand::random();
+        self.tokens.insert(username, token);
+
+        token
+    }
+
+    pub fn is_valid(&self, username: &str, token: u64) -> bool {
+        self.tokens.get(username) == Some(&token)
+    }
+
+    pub fn disconnected(&mut self, key: UserKey) {
+        self.disconnected.insert(key);
+    }
+
+    pub fn is_disconnected(&self, key: &UserKey) -> bool {
+        self.disconnected.contains(key)
+    }
+
+    /// Lets the connection with the `new` key take the seat of the disconnected `old` key once it
+    /// has connected
+    pub fn authorize(&mut self, new: UserKey, old: UserKey) {
+        self.pending.insert(new, old);
+    }
+
+    pub fn is_pending(&self, new: &UserKey) -> bool {
+        self.pending.contains_key(new)
+    }
+
+    /// Whether anybody is part way through taking a seat back
+    pub fn any_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Forgets a connection which was allowed to take a seat back but was lost before it finished
+    /// connecting. The seat stays held for the next attempt.
+    pub fn cancel_pending(&mut self, new: &UserKey) {
+        self.pending.remove(new);
+    }
+
+    /// The key a newly made connection is taking the seat of, if it is reconnecting
+    pub fn take_pending(&mut self, new: &UserKey) -> Option<UserKey> {
+        let old = self.pending.remove(new)?;
+        self.disconnected.remove(&old);
+
+        Some(old)
+    }
 }
 
 /// A simple enum used with two-way associations
@@ -45,6 +186,22 @@ impl UsernameKeyAssociation {
         self.key_to_name.get(key)
     }
 
+    /// How many seats have been taken, including by players who haven't finished connecting
+    pub fn len(&self) -> usize {
+        self.key_to_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.key_to_name.is_empty()
+    }
+
+    /// Hands the username associated with `old` to `new`, such as when a player reconnects
+    pub fn replace_key(&mut self, old: &UserKey, new: UserKey) {
+        if let Some(name) = self.key_to_name.remove(old) {
+            self.insert(name, new);
+        }
+    }
+
     pub fn delete_from_name(&mut self, name: &str) -> DeletedStatus {
         if let Some(key) = self.name_to_key.remove(name) {
             self.key_to_name.remove(&key);
@@ -99,6 +256,13 @@ impl KeyIdAssociation {
         }
     }
 
+    /// Hands the [`PlayerId`] associated with `old` to `new`, such as when a player reconnects
+    pub fn replace_key(&mut self, old: &UserKey, new: UserKey) {
+        if let Some(id) = self.key_to_id.remove(old) {
+            self.insert(new, id);
+        }
+    }
+
     pub fn delete_from_id(&mut self, id: &PlayerId) -> DeletedStatus {
         if let Some(key) = self.id_to_key.remove(id) {
             self.key_to_id.remove(&key);
@@ -136,6 +300,11 @@ impl KeyMapAssociation {
         self.map_entity_to_key.get(entity)
     }
 
+    /// Every player with a perceived map, whether or not they are still connected
+    pub fn keys(&self) -> impl Iterator<Item = &UserKey> {
+        self.key_to_map_entity.keys()
+    }
+
     pub fn delete_from_key(&mut self, key: &UserKey) -> DeletedStatus {
         if let Some(entity) = self.key_to_map_entity.remove(key) {
             self.map_entity_to_key.remove(&entity);
@@ -145,6 +314,13 @@ impl KeyMapAssociation {
         }
     }
 
+    /// Hands the perceived map associated with `old` to `new`, such as when a player reconnects
+    pub fn replace_key(&mut self, old: &UserKey, new: UserKey) {
+        if let Some(entity) = self.key_to_map_entity.remove(old) {
+            self.insert(new, entity);
+        }
+    }
+
     pub fn delete_from_entity(&mut self, entity: &Entity) -> DeletedStatus {
         if let Some(key) = self.map_entity_to_key.remove(entity) {
             self.key_to_map_entity.remove(&key);
@@ -186,6 +362,15 @@ impl KeyUnitsAssociation {
         self.unit_to_key_association.get(&entity)
     }
 
+    /// Hands every unit associated with `old` to `new`, such as when a player reconnects
+    pub fn replace_key(&mut self, old: UserKey, new: UserKey) {
+        if let Some(entities) = self.key_to_units_association.remove(&old) {
+            for entity in entities {
+                self.insert(new, entity);
+            }
+        }
+    }
+
     /// WARNING: Deletes ALL entities associated with this key
     pub fn delete_from_key(&mut self, key: UserKey) -> DeletedStatus {
         if let Some(entities) = self.key_to_units_association.remove(&key) {
@@ -12,10 +12,7 @@ use rgj_shared::{
     Channels,
 };
 
-use crate::{
-    resources::{KeyIdAssociation, MainRoom, UsernameKeyAssociation},
-    Args,
-};
+use crate::{matches::Matches, resources::MapSource, Args, GameState};
 
 pub const ID_ORDER: &[PlayerId] = &[
     PlayerId::Red,
@@ -26,32 +23,66 @@ pub const ID_ORDER: &[PlayerId] = &[
     PlayerId::Orange,
 ];
 
+/// Lets players into the match named by the room they give, starting the match if there isn't one
 pub fn authorization_event(
     mut event_reader: EventReader<AuthorizationEvent<Protocol>>,
+    mut commands: Commands,
     mut server: Server<Protocol, Channels>,
 
-    mut association: ResMut<UsernameKeyAssociation>,
+    mut matches: ResMut<Matches>,
     config: Res<Args>,
+    maps: Res<MapSource>,
 ) {
     for event in event_reader.iter() {
         if let AuthorizationEvent(user_key, Protocol::Identification(auth)) = event {
-            if *auth.room_password != config.room_password {
+            let room_name = &*auth.room_name;
+
+            if matches.get(room_name).is_none() {
+                match matches.start(
+                    room_name.clone(),
+                    auth.room_password.to_string(),
+                    &mut commands,
+                    &mut server,
+                    &config,
+                    &maps,
+                ) {
+                    Ok(_) => info!("{} opened room {}", *auth.username, room_name),
+                    Err(err) => {
+                        info!("Rejecting connection: {}", err);
+                        server.reject_connection(user_key);
+                        continue;
+                    }
+                }
+            }
+
+            let game = matches.get_mut(room_name).unwrap();
+
+            // Players taking back their seat in a game already underway are let in separately
+            if game.state() != GameState::WaitingForConnections {
+                continue;
+            }
+
+            if !game.is_password(&auth.room_password) {
                 info!("Rejecting connection: password invalid");
                 server.reject_connection(user_key);
+                continue;
             }
 
-            if association.get_from_name(&*auth.username).is_some() {
+            if game.user_key_assoc.get_from_name(&*auth.username).is_some() {
                 info!("Rejecting connection: {} already connected", *auth.username);
                 server.reject_connection(user_key);
+                continue;
             }
 
-            if server.users_count() > config.num_players as usize {
+            if game.user_key_assoc.len() >= config.num_players as usize {
                 info!("Rejecting connection, num_players exceeded");
                 server.reject_connection(user_key);
+                continue;
             }
 
-            info!("Accepting connection");
-            association.insert(auth.username.to_string(), *user_key);
+            info!("Accepting connection to {}", room_name);
+            game.user_key_assoc
+                .insert(auth.username.to_string(), *user_key);
             server.accept_connection(user_key);
         }
     }
@@ -61,24 +92,32 @@ pub fn connection_event<'world, 'state>(
     mut event_reader: EventReader<ConnectionEvent>,
     mut server: Server<'world, 'state, Protocol, Channels>,
 
-    main_room: Res<MainRoom>,
-    username_key_assoc: Res<UsernameKeyAssociation>,
-    mut key_id_assoc: ResMut<KeyIdAssociation>,
+    mut matches: ResMut<Matches>,
 ) {
     for ConnectionEvent(user_key) in event_reader.iter() {
+        let game = match matches.of_user_mut(user_key) {
+            Some(game) if game.state() == GameState::WaitingForConnections => game,
+            _ => continue,
+        };
+
         let address = server
             .user_mut(user_key)
-            .enter_room(&main_room.key)
+            .enter_room(&game.room.key)
             .address();
+        game.room.join(*user_key);
 
-        let username = username_key_assoc.get_from_key(user_key).unwrap();
-        info!("Formed connection with {} on {}", username, address);
+        let username = game.user_key_assoc.get_from_key(user_key).unwrap();
+        info!(
+            "Formed connection with {} on {} in {}",
+            username, address, game.name
+        );
 
-        let _entity = server
+        let keep_alive = server
             .spawn()
-            .enter_room(&main_room.key)
+            .enter_room(&game.room.key)
             .insert(ClientKeepAlive)
             .id();
+        game.room.keep_alives.push(keep_alive);
 
         server.send_message(
             user_key,
@@ -86,28 +125,39 @@ pub fn connection_event<'world, 'state>(
             &WaitingOnPlayers::new_complete(0),
         );
 
-        // TODO: Ensure that PlayerId's don't mess up if multiple players connect on the same tick
-        let id = ID_ORDER[server.users_count() - 1];
-        for key in server.user_keys() {
+        // Colors given up by players who left before the game started are handed out again
+        let id = *ID_ORDER
+            .iter()
+            .find(|id| game.key_id_assoc.get_from_id(id).is_none())
+            .expect("there are never more players than colors");
+        game.key_id_assoc.insert(*user_key, id);
+
+        for key in game.room.users() {
             server.send_message(
                 &key,
                 Channels::GameNotification,
                 &ClientConnected::new(username.clone(), id),
             );
-
-            key_id_assoc.insert(*user_key, id);
         }
     }
 }
 
 pub fn disconnection_event(
     mut event_reader: EventReader<DisconnectionEvent>,
-    mut association: ResMut<UsernameKeyAssociation>,
+    mut matches: ResMut<Matches>,
 ) {
     for DisconnectionEvent(user_key, user) in event_reader.iter() {
-        let username = association.get_from_key(user_key).unwrap();
+        let game = match matches.of_user_mut(user_key) {
+            Some(game) if game.state() == GameState::WaitingForConnections => game,
+            _ => continue,
+        };
+
+        let username = game.user_key_assoc.get_from_key(user_key).unwrap();
         info!("Disconnecting from {} on {}", username, user.address);
-        association.delete_from_key(user_key);
+
+        game.user_key_assoc.delete_from_key(user_key);
+        game.key_id_assoc.delete_from_key(user_key);
+        game.room.leave(user_key);
     }
 }
 
@@ -116,15 +166,21 @@ pub fn receive_message_event(
 
     mut event_reader: EventReader<MessageEvent<Protocol, Channels>>,
 
-    key_id_assoc: Res<KeyIdAssociation>,
+    mut matches: ResMut<Matches>,
 ) {
     for event in event_reader.iter() {
         if let MessageEvent(user_key, Channels::Chat, Protocol::SendChat(SendChat { message })) =
             event
         {
-            let id = key_id_assoc.get_from_key(&user_key).unwrap();
+            let game = match matches.of_user_mut(user_key) {
+                Some(game) if game.state() == GameState::WaitingForConnections => game,
+                _ => continue,
+            };
+
+            let id = game.key_id_assoc.get_from_key(user_key).unwrap();
 
-            for key in server.user_keys() {
+            // Chat only goes to the players in the same match
+            for key in game.room.users() {
                 server.send_message(
                     &key,
                     Channels::Chat,
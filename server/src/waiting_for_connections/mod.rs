@@ -1,38 +1,29 @@
 //! A module defining components, resources, and systems specific to the WaitingForConnections GameState.
 
-use std::time::Duration;
-
 use bevy::prelude::*;
-use iyes_loopless::prelude::*;
 use naia_bevy_server::{Server, ServerAddrs};
 use rand::prelude::*;
 
 use rgj_shared::{
     behavior::AxialCoordinates,
     components::{
-        genome::{
-            self, CHICKEN, DEER, ELECTRIC_EEL, ELEPHANT, RATTLESNAKE, SAILFISH, VAMPIRE_BAT,
-            VULTURE, WHALE,
-        },
+        genome::{self, unique_genomes, AnimalType, DEER},
         players::PlayerId,
     },
+    map::MapLayout,
     protocol::{
-        game_sync::map_sync::{
-            MapCharacterUnrecognized, MapSync, TileStructure, TileType, MAP_HEIGHT,
-        },
+        game_sync::map_sync::{MapSync, TileStructure, TileType, MAP_HEIGHT},
         Protocol, WaitingOnPlayers,
     },
-    resources::MapConfig,
     Channels,
 };
 
 use crate::{
+    bots::Bots,
     components::{AuthoritativeTileMap, TileMap},
-    countdown::resources::{Countdown, TimeSinceLastCount},
-    resources::{
-        KeyIdAssociation, KeyMapAssociation, KeyUnitsAssociation, MainRoom, UsernameKeyAssociation,
-    },
-    Args, GameState, MapOption,
+    matches::Matches,
+    resources::GameRng,
+    Args, GameState,
 };
 
 pub mod events;
@@ -56,199 +47,27 @@ fn init_tile(
         .id()
 }
 
-/// Initialization system
-pub fn init(mut commands: Commands, mut server: Server<Protocol, Channels>, args: Res<Args>) {
-    info!("Server running -- awaiting connections");
-
-    let main_room_key = server.make_room().key();
-    match &args.map_option {
-        MapOption::Generate { size_x, size_y } => {
-            let size_x = *size_x;
-            let size_y = *size_y;
-
-            // Build AuthoritativeTileMap
-            let mut auth_map_entities = Vec::with_capacity(size_x as usize * size_y as usize * 2);
-
-            // TODO: Procedurally generate
-            for z in 0..MAP_HEIGHT as i32 {
-                for r in 0..size_y as i32 {
-                    for q in 0..size_x as i32 {
-                        if z == 1 {
-                            auth_map_entities.push(init_tile(
-                                &mut commands,
-                                q,
-                                r,
-                                z,
-                                TileType::ClearSky,
-                                TileStructure::None,
-                            ));
-                        } else {
-                            auth_map_entities.push(init_tile(
-                                &mut commands,
-                                q,
-                                r,
-                                z,
-                                TileType::Ocean,
-                                TileStructure::None,
-                            ));
-                        }
-                    }
-                }
-            }
-
-            let auth_map = commands
-                .spawn()
-                .insert(AuthoritativeTileMap)
-                .insert(TileMap {
-                    children: auth_map_entities,
-                })
-                .id();
-
-            commands.insert_resource(MainRoom {
-                key: main_room_key,
-                map_entity: auth_map,
-            });
-
-            commands.insert_resource(MapConfig {
-                size_width: size_x,
-                size_height: size_y,
-            });
-        }
-
-        MapOption::Load { file_path } => {
-            let file_string = std::fs::read_to_string(file_path).expect("Given map invalid");
-
-            let mut auth_map_entities = Vec::with_capacity(file_string.chars().count() * 2 / 3);
-
-            // Will be the length of each line
-            let mut x_size = 0;
-
-            // Scan through for validity
-            let mut char_count_for_line = 0;
-            let mut line_count = 0;
-            for c in file_string.chars() {
-                match c {
-                    '\n' => {
-                        // If X_size hasn't been set, set it to the char_count_for_line for the
-                        // first nonempty line, otherwise ensure lines are equal in length and panic
-                        // otherwise
-                        if x_size == 0 {
-                            x_size = char_count_for_line;
-                        } else if x_size != char_count_for_line {
-                            panic!("Given map's lines must be equal in length")
-                        }
-
-                        char_count_for_line = 0;
-                        line_count += 1;
-                    }
-
-                    _ => {
-                        char_count_for_line += 1;
-                    }
-                }
-            }
-
-            if line_count % 3 != 0 {
-                panic!("Given map must havea number of lines divisible by three")
-            }
-
-            let y_size = line_count / 3;
-
-            // Read the tiles from the ground and air layers
-            let tiles = file_string
-                .chars()
-                .filter(|c| *c != '\n')
-                .take(y_size * 2 * x_size)
-                .map(TryInto::try_into)
-                .collect::<Result<Vec<TileType>, _>>()
-                .expect("Unrecognized character");
-
-            let mut genomes = vec![
-                VAMPIRE_BAT.clone(),
-                CHICKEN.clone(),
-                ELECTRIC_EEL.clone(),
-                ELEPHANT.clone(),
-                RATTLESNAKE.clone(),
-                SAILFISH.clone(),
-                VULTURE.clone(),
-                WHALE.clone(),
-            ];
-
-            genomes.shuffle(&mut rand::thread_rng());
-
-            let mut genomes = genomes.into_iter();
-
-            // Then read structures from the remaining layer
-            let structures = file_string
-                .chars()
-                .filter(|c| *c != '\n')
-                .skip(y_size * 2 * x_size)
-                .map(|c| match c {
-                    '_' => Ok(TileStructure::None),
-                    'g' => {
-                        let genome = genomes
-                            .next()
-                            .expect("Map expects exactly eight genome facilities");
-
-                        Ok(TileStructure::GenomeFacility {
-                            unique_genome: genome,
-                            building: None,
-                        })
-                    }
-                    _ => Err(MapCharacterUnrecognized),
-                })
-                .collect::<Result<Vec<TileStructure>, _>>()
-                .expect("Unrecognized character");
-
-            if structures
+/// The unique genomes not already fixed to a genome facility by the map, in a random order
+fn shuffled_genomes(layout: &MapLayout, rng: &mut GameRng) -> impl Iterator<Item = AnimalType> {
+    let mut genomes: Vec<AnimalType> = unique_genomes()
+        .into_iter()
+        .filter(|genome| {
+            !layout
+                .facilities
                 .iter()
-                .filter(|t| matches!(t, TileStructure::GenomeFacility { .. }))
-                .count()
-                != 8
-            {
-                panic!(
-                    "Map expects exactly eight genome facilities, has {}",
-                    structures.len()
-                );
-            }
+                .any(|facility| facility.genome.as_ref() == Some(genome))
+        })
+        .collect();
 
-            // Consume them one by one filling the Vec
-            let mut tiles = tiles.into_iter();
-            let mut structures = structures.into_iter();
-            for z in 0..MAP_HEIGHT {
-                for r in 0..y_size {
-                    for q in 0..x_size {
-                        auth_map_entities.push(init_tile(
-                            &mut commands,
-                            q as i32,
-                            r as i32,
-                            z as i32,
-                            tiles.next().unwrap(),
-                            structures.next().unwrap_or(TileStructure::None),
-                        ));
-                    }
-                }
-            }
+    genomes.shuffle(rng);
 
-            let auth_map = commands
-                .spawn()
-                .insert(AuthoritativeTileMap)
-                .insert(TileMap {
-                    children: auth_map_entities,
-                })
-                .id();
-
-            commands.insert_resource(MainRoom {
-                key: main_room_key,
-                map_entity: auth_map,
-            });
+    genomes.into_iter()
+}
 
-            commands.insert_resource(MapConfig {
-                size_width: x_size as u16,
-                size_height: y_size as u16,
-            });
-        }
-    }
+/// Startup system. Starts listening for connections, each of which joins or starts a match by the
+/// room name it gives
+pub fn listen(mut server: Server<Protocol, Channels>, args: Res<Args>) {
+    info!("Server running -- awaiting connections");
 
     let server_addresses = ServerAddrs::new(
         args.bind_udp,
@@ -256,36 +75,82 @@ pub fn init(mut commands: Commands, mut server: Server<Protocol, Channels>, args
         &format!("http://{}", args.bind_web_rtc),
     );
     server.listen(&server_addresses);
-
-    commands.insert_resource(UsernameKeyAssociation::new());
-    commands.insert_resource(KeyMapAssociation::new());
-    commands.insert_resource(KeyUnitsAssociation::new());
-    commands.insert_resource(KeyIdAssociation::new());
 }
 
-/// The tick fn will simply wait for the number of players to equal the configured, then enter the
-/// countdown state
-pub fn tick(mut commands: Commands, mut server: Server<Protocol, Channels>, args: Res<Args>) {
-    // If there are exactly enough players, start the countdown
-    if server.users_count() == args.num_players as usize {
-        info!("Transitioning to countdown phase");
-        commands.insert_resource(NextState(GameState::Countdown));
-
-        // Insert resources needed for next state
-        commands.insert_resource(Countdown(3));
-        commands.insert_resource(TimeSinceLastCount(Duration::from_secs(0)));
+/// Spawns the authoritative map of a new match from its [`MapLayout`], returning the entity of the
+/// map
+pub fn spawn_map(commands: &mut Commands, layout: &MapLayout, rng: &mut GameRng) -> Entity {
+    let mut genomes = shuffled_genomes(layout, rng);
+
+    // Build AuthoritativeTileMap
+    let mut auth_map_entities = Vec::with_capacity(layout.tiles.len());
+    for z in 0..MAP_HEIGHT as i32 {
+        for hex in layout.hexes() {
+            let structure = match layout.facility(hex, z) {
+                Some(facility) => TileStructure::GenomeFacility {
+                    unique_genome: facility
+                        .genome
+                        .clone()
+                        .unwrap_or_else(|| genomes.next().unwrap()),
+                    building: None,
+                },
+                None => TileStructure::None,
+            };
+
+            auth_map_entities.push(init_tile(
+                commands,
+                hex.column_q,
+                hex.row_r,
+                z,
+                layout.tile(hex, z),
+                structure,
+            ));
+        }
     }
 
-    for (_, user_key, entity) in server.scope_checks() {
-        server.user_scope(&user_key).include(&entity);
-    }
+    commands
+        .spawn()
+        .insert(AuthoritativeTileMap)
+        .insert(TileMap {
+            children: auth_map_entities,
+        })
+        .id()
+}
 
-    // Update players on how many new connections they're waiting on
-    // XXX: Be VERY certain the user count never exceeds the num_players so that it may never exceed u8::MAX.
-    let waiting_on = WaitingOnPlayers::new_complete(args.num_players - server.users_count() as u8);
-    for key in server.user_keys() {
-        server.send_message(&key, Channels::WaitingOnPlayers, &waiting_on);
-    }
+/// The tick fn will simply wait for the number of players in each match to equal the configured,
+/// then seat any bots and enter the countdown state
+pub fn tick(mut server: Server<Protocol, Channels>, args: Res<Args>, mut matches: ResMut<Matches>) {
+    let scope_checks = server.scope_checks();
+
+    for game in matches.in_state_mut(GameState::WaitingForConnections) {
+        // If there are exactly enough players, start the countdown
+        if game.room.users.len() == args.num_players as usize {
+            info!("Transitioning {} to countdown phase", game.name);
+            game.set_next_state(GameState::Countdown);
+
+            game.bots = Bots::seat(
+                args.bots,
+                game.number,
+                &mut server,
+                &game.room,
+                &mut game.user_key_assoc,
+                &mut game.key_id_assoc,
+            );
+        }
+
+        for (_, user_key, entity) in scope_checks
+            .iter()
+            .filter(|(room_key, ..)| *room_key == game.room.key)
+        {
+            server.user_scope(user_key).include(entity);
+        }
 
-    server.send_all_updates();
+        // Update players on how many new connections they're waiting on
+        // XXX: Be VERY certain the user count never exceeds the num_players so that it may never exceed u8::MAX.
+        let waiting_on =
+            WaitingOnPlayers::new_complete(args.num_players - game.room.users.len() as u8);
+        for key in game.room.users() {
+            server.send_message(&key, Channels::WaitingOnPlayers, &waiting_on);
+        }
+    }
 }
@@ -0,0 +1,359 @@
+//! Plays games against a server running in the same process. The server and every headless client
+//! are updated in lockstep, one frame each in turn, so that a test sees exactly the states the
+//! server steps through and can check the authoritative state at any point between frames.
+
+use std::{
+    net::{SocketAddr, TcpListener},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use clap::Parser;
+use naia_bevy_server::UserKey;
+
+use rgj_headless::{Credentials, HeadlessClient};
+use rgj_server::{
+    app,
+    components::TileMap,
+    matches::{Match, Matches},
+    playing::resources::TurnTracker,
+    Args, GameState,
+};
+use rgj_shared::{
+    behavior::AxialCoordinates,
+    components::genome::AnimalType,
+    protocol::{
+        game_sync::map_sync::{tile_qrz_to_index, MapSync},
+        player_input::PlayerInputVariant,
+        UnitSync,
+    },
+};
+
+/// A two player map with a genome facility next to each of its two spawn points: an Elephant
+/// facility at (2, 1) next to (1, 1), and a Chicken facility at (7, 4) next to (8, 4)
+pub const DUEL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/maps/duel.ron");
+
+/// The room every player joins, unless a test puts them in rooms of its own
+pub const ROOM: &str = "duel";
+const ROOM_PASSWORD: &str = "password";
+const SEED: &str = "1";
+
+/// How long anything a test waits on may take before it fails
+const TIMEOUT: Duration = Duration::from_secs(20);
+/// How long to sleep after each frame, so that packets have time to arrive
+const FRAME_TIME: Duration = Duration::from_millis(2);
+
+pub struct Harness {
+    server: App,
+    /// Each room with the usernames of the players in it
+    rooms: Vec<(String, Vec<String>)>,
+    clients: Vec<(String, HeadlessClient)>,
+}
+
+impl Harness {
+    /// Starts a server on localhost for the given players on [`DUEL`], passing it any extra
+    /// arguments, and connects a headless client for each player to [`ROOM`]
+    pub fn start(usernames: &[&str], extra_args: &[&str]) -> Harness {
+        Harness::start_in_rooms(&[(ROOM, usernames)], extra_args)
+    }
+
+    /// Like [`Harness::start`], but with the players split between rooms. Every room must have the
+    /// same number of players, and usernames must be unique across all of them.
+    pub fn start_in_rooms(rooms: &[(&str, &[&str])], extra_args: &[&str]) -> Harness {
+        let bind_udp = free_address();
+        let bind_web_rtc = free_address();
+
+        let num_players = rooms[0].1.len().to_string();
+        let args = [
+            "rgj_server",
+            &bind_udp.to_string(),
+            &bind_web_rtc.to_string(),
+            &num_players,
+            "--seed",
+            SEED,
+        ]
+        .into_iter()
+        .map(str::to_owned)
+        .chain(extra_args.iter().map(|arg| arg.to_string()))
+        .chain(["load".to_owned(), DUEL.to_owned()])
+        .collect::<Vec<_>>();
+
+        let mut server = app(Args::parse_from(args)).expect("the server should start");
+        // Starts listening
+        server.update();
+
+        let clients = rooms
+            .iter()
+            .flat_map(|(room, usernames)| usernames.iter().map(move |username| (room, username)))
+            .map(|(room, username)| {
+                let client = HeadlessClient::connect(
+                    bind_udp,
+                    Credentials {
+                        username: username.to_string(),
+                        room_name: room.to_string(),
+                        room_password: ROOM_PASSWORD.to_owned(),
+                        reconnect_token: None,
+                    },
+                );
+
+                (username.to_string(), client)
+            })
+            .collect();
+
+        let rooms = rooms
+            .iter()
+            .map(|(room, usernames)| {
+                (
+                    room.to_string(),
+                    usernames.iter().map(|name| name.to_string()).collect(),
+                )
+            })
+            .collect();
+
+        Harness {
+            server,
+            rooms,
+            clients,
+        }
+    }
+
+    /// Runs a frame of the server, then of every client
+    pub fn step(&mut self) {
+        self.server.update();
+        for (_, client) in &mut self.clients {
+            client.update();
+        }
+
+        thread::sleep(FRAME_TIME);
+    }
+
+    /// Steps until `condition` holds, failing the test if it takes too long
+    pub fn step_until(&mut self, waiting_for: &str, mut condition: impl FnMut(&Harness) -> bool) {
+        let started = Instant::now();
+
+        while !condition(self) {
+            if started.elapsed() > TIMEOUT {
+                panic!("timed out waiting for {}", waiting_for);
+            }
+
+            self.step();
+        }
+    }
+
+    /// Steps for the given time, to show that something doesn't happen
+    pub fn step_for(&mut self, duration: Duration) {
+        let started = Instant::now();
+
+        while started.elapsed() < duration {
+            self.step();
+        }
+    }
+
+    /// Steps through waiting for connections and the countdown, until every player in every room
+    /// has been told the game started and can see their first unit
+    pub fn start_game(&mut self) {
+        self.step_until("the game to start", |harness| {
+            harness
+                .rooms
+                .iter()
+                .all(|(room, _)| harness.state_in(room) == Some(GameState::Playing))
+                && harness.clients.iter().all(|(_, client)| {
+                    client.view().whose_turn.is_some() && !client.view().units.is_empty()
+                })
+        });
+    }
+
+    /// The state of the match in the first room, if it has been started
+    pub fn state(&self) -> Option<GameState> {
+        self.state_in(&self.rooms[0].0)
+    }
+
+    pub fn state_in(&self, room: &str) -> Option<GameState> {
+        self.resource::<Matches>().get(room).map(Match::state)
+    }
+
+    /// The match in the given room
+    pub fn game(&self, room: &str) -> &Match {
+        self.resource::<Matches>()
+            .get(room)
+            .unwrap_or_else(|| panic!("nobody has started a match in {}", room))
+    }
+
+    /// The room the player is in
+    fn room_of(&self, username: &str) -> &str {
+        self.rooms
+            .iter()
+            .find(|(_, usernames)| usernames.iter().any(|name| name == username))
+            .map(|(room, _)| room.as_str())
+            .unwrap_or_else(|| panic!("{} is in no room", username))
+    }
+
+    /// The match the player is in
+    fn game_of(&self, username: &str) -> &Match {
+        self.game(self.room_of(username))
+    }
+
+    /// The match in the first room, which most tests have all of their players in
+    fn first_game(&self) -> &Match {
+        self.game(&self.rooms[0].0)
+    }
+
+    fn turn_tracker(&self, room: &str) -> &TurnTracker {
+        &self
+            .game(room)
+            .playing
+            .as_ref()
+            .expect("the game has started")
+            .turn_tracker
+    }
+
+    pub fn resource<R: Send + Sync + 'static>(&self) -> &R {
+        self.server.world.resource::<R>()
+    }
+
+    pub fn client(&self, username: &str) -> &HeadlessClient {
+        self.clients
+            .iter()
+            .find(|(name, _)| name == username)
+            .map(|(_, client)| client)
+            .unwrap_or_else(|| panic!("{} has no client", username))
+    }
+
+    fn client_mut(&mut self, username: &str) -> &mut HeadlessClient {
+        self.clients
+            .iter_mut()
+            .find(|(name, _)| name == username)
+            .map(|(_, client)| client)
+            .unwrap_or_else(|| panic!("{} has no client", username))
+    }
+
+    pub fn key(&self, username: &str) -> UserKey {
+        *self
+            .game_of(username)
+            .user_key_assoc
+            .get_from_name(username)
+            .unwrap_or_else(|| panic!("{} has no seat", username))
+    }
+
+    /// The username of the player whose turn it is in the first room
+    pub fn whose_turn(&self) -> String {
+        self.whose_turn_in(&self.rooms[0].0)
+    }
+
+    pub fn whose_turn_in(&self, room: &str) -> String {
+        let player = self.turn_tracker(room).player;
+
+        self.game(room)
+            .user_key_assoc
+            .get_from_key(&player)
+            .unwrap()
+            .clone()
+    }
+
+    pub fn turn_number(&self) -> u16 {
+        self.turn_tracker(&self.rooms[0].0).turn_number
+    }
+
+    /// Every unit the player has, as the server has them
+    pub fn units(&self, username: &str) -> Vec<&UnitSync> {
+        self.game_of(username)
+            .key_units_assoc
+            .get_from_key(self.key(username))
+            .into_iter()
+            .flatten()
+            .filter_map(|entity| self.server.world.get::<UnitSync>(*entity))
+            .collect()
+    }
+
+    pub fn unlocked_genomes(&self, username: &str) -> &[AnimalType] {
+        &self
+            .game_of(username)
+            .playing
+            .as_ref()
+            .expect("the game has started")
+            .unlocked_genomes
+            .key_to_genomes[&self.key(username)]
+    }
+
+    /// A tile of the authoritative map in the first room
+    pub fn tile(&self, qr: AxialCoordinates, layer: i32) -> &MapSync {
+        let game = self.first_game();
+        let map_config = &game.map_config;
+        let auth_map = self
+            .server
+            .world
+            .get::<TileMap>(game.room.map_entity)
+            .unwrap();
+
+        self.server
+            .world
+            .get::<MapSync>(
+                auth_map.children[tile_qrz_to_index(map_config, qr.column_q, qr.row_r, layer)],
+            )
+            .unwrap()
+    }
+
+    /// Sends input from a player's client about their unit on the given hex and layer, if any
+    pub fn send(
+        &mut self,
+        username: &str,
+        unit: Option<(AxialCoordinates, i32)>,
+        input: PlayerInputVariant,
+    ) {
+        let client = self.client_mut(username);
+
+        // Units are told apart by the entity the client was given for them, not the server's
+        let unit = unit.map(|(qr, layer)| {
+            client
+                .view()
+                .unit_at(qr, layer)
+                .unwrap_or_else(|| panic!("{} can't see a unit on {:?}", username, qr))
+                .0
+        });
+
+        client.send_input(unit, input);
+    }
+
+    /// Moves a player's unit and waits for the server to finish moving it
+    pub fn move_unit(&mut self, username: &str, from: AxialCoordinates, to: AxialCoordinates) {
+        self.send(
+            username,
+            Some((from, 0)),
+            PlayerInputVariant::MoveEntity(to),
+        );
+
+        let name = username.to_owned();
+        self.step_until(&format!("{} to reach {:?}", username, to), |harness| {
+            harness
+                .units(&name)
+                .iter()
+                .any(|unit| *unit.position == to && *unit.layer == 0)
+        });
+    }
+
+    /// Ends a player's turn and waits for the server to move on to the next
+    pub fn end_turn(&mut self, username: &str) {
+        let room = self.room_of(username).to_owned();
+        assert_eq!(
+            self.whose_turn_in(&room),
+            username,
+            "{} can only end their own turn",
+            username
+        );
+
+        self.send(username, None, PlayerInputVariant::EndTurn);
+
+        let name = username.to_owned();
+        self.step_until(&format!("{} to end their turn", username), |harness| {
+            harness.whose_turn_in(&room) != name
+        });
+    }
+}
+
+/// A localhost address with a port nothing else is using
+fn free_address() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("a free port on localhost")
+}
@@ -0,0 +1,256 @@
+//! Whole games played between headless clients and a server in the same process. The clients
+//! connect over UDP, so these only run with the `use-udp` feature:
+//!
+//! `cargo test -p rgj_server --no-default-features --features use-udp`
+
+use std::time::Duration;
+
+use rgj_server::GameState;
+use rgj_shared::{
+    behavior::AxialCoordinates,
+    components::genome::{AnimalType, Hybrid, CHICKEN, DEER, ELEPHANT},
+    protocol::{
+        game_sync::map_sync::{ConstructionStatus, TileStructure},
+        notifications::WhoseTurn,
+        player_input::PlayerInputVariant,
+    },
+};
+
+mod common;
+use common::Harness;
+
+const PLAYERS: [&str; 2] = ["alice", "bob"];
+
+/// Where the player's first unit started, and the genome facility next to it with its genome
+fn home(harness: &Harness, username: &str) -> (AxialCoordinates, AxialCoordinates, AnimalType) {
+    let units = harness.units(username);
+    assert_eq!(units.len(), 1, "{} should start with one unit", username);
+
+    let start = *units[0].position;
+    if start == AxialCoordinates::new(1, 1) {
+        (start, AxialCoordinates::new(2, 1), ELEPHANT.clone())
+    } else if start == AxialCoordinates::new(8, 4) {
+        (start, AxialCoordinates::new(7, 4), CHICKEN.clone())
+    } else {
+        panic!(
+            "{} started on {:?}, which is not a spawn point",
+            username, start
+        );
+    }
+}
+
+fn other(username: &str) -> &'static str {
+    PLAYERS.into_iter().find(|name| *name != username).unwrap()
+}
+
+#[test]
+fn game_starts_once_everyone_has_connected() {
+    let mut harness = Harness::start(&PLAYERS, &[]);
+
+    harness.step_until("the countdown", |harness| {
+        harness.state() == Some(GameState::Countdown)
+    });
+    harness.start_game();
+
+    assert_eq!(harness.turn_number(), 1);
+
+    let first = harness.whose_turn();
+    for username in PLAYERS {
+        let view = harness.client(username).view();
+
+        assert!(view.reconnect_token.is_some());
+        assert_eq!(view.is_my_turn(), username == first);
+        assert_eq!(view.turn_number(), Some(1));
+        assert_eq!(view.genomes, vec![DEER.clone()]);
+        assert_eq!(harness.unlocked_genomes(username), vec![DEER.clone()]);
+
+        home(&harness, username);
+    }
+}
+
+#[test]
+fn turns_pass_between_players_in_order() {
+    let mut harness = Harness::start(&PLAYERS, &[]);
+    harness.start_game();
+
+    let first = harness.whose_turn();
+    let second = other(&first);
+
+    // Input from a player whose turn it isn't is ignored
+    harness.send(second, None, PlayerInputVariant::EndTurn);
+    harness.step_for(Duration::from_millis(200));
+    assert_eq!(harness.whose_turn(), first);
+
+    harness.end_turn(&first);
+    assert_eq!(harness.whose_turn(), second);
+    assert_eq!(harness.turn_number(), 1);
+
+    // A new turn number starts once every player has had a go
+    harness.end_turn(second);
+    assert_eq!(harness.whose_turn(), first);
+    assert_eq!(harness.turn_number(), 2);
+
+    harness.step_until("the players to be told", |harness| {
+        harness.client(&first).view().whose_turn == Some(WhoseTurn::Yours { turn_number: 2 })
+            && harness.client(second).view().turn_number() == Some(2)
+    });
+    assert!(!harness.client(second).view().is_my_turn());
+}
+
+#[test]
+fn stepping_onto_a_genome_facility_unlocks_its_genome() {
+    let mut harness = Harness::start(&PLAYERS, &[]);
+    harness.start_game();
+
+    let player = harness.whose_turn();
+    let (start, facility, genome) = home(&harness, &player);
+
+    harness.move_unit(&player, start, facility);
+
+    let unit = &harness.units(&player)[0];
+    assert_eq!(*unit.position, facility);
+    assert!(*unit.stamina_remaining < *harness.units(other(&player))[0].stamina_remaining);
+    assert_eq!(
+        harness.unlocked_genomes(&player),
+        vec![DEER.clone(), genome.clone()]
+    );
+    assert_eq!(harness.unlocked_genomes(other(&player)), vec![DEER.clone()]);
+
+    harness.step_until("the player to be told", |harness| {
+        harness.client(&player).view().genomes.contains(&genome)
+    });
+
+    // And stepping off locks it again
+    harness.move_unit(&player, facility, start);
+    assert_eq!(harness.unlocked_genomes(&player), vec![DEER.clone()]);
+}
+
+#[test]
+fn hybrids_finish_building_on_the_expected_turn() {
+    let mut harness = Harness::start(&PLAYERS, &[]);
+    harness.start_game();
+
+    let player = harness.whose_turn();
+    let (start, facility, genome) = home(&harness, &player);
+
+    harness.move_unit(&player, start, facility);
+
+    // Two of the three genomes the same takes four turns
+    let hybrid = Hybrid::new(DEER.clone(), DEER.clone(), genome);
+    let started_on = harness.turn_number();
+    harness.send(
+        &player,
+        None,
+        PlayerInputVariant::BuildHybrid(facility, hybrid.clone()),
+    );
+
+    harness.step_until("construction to start", |harness| {
+        matches!(
+            &*harness.tile(facility, 0).structure,
+            TileStructure::GenomeFacility {
+                building: Some(_),
+                ..
+            }
+        )
+    });
+    match &*harness.tile(facility, 0).structure {
+        TileStructure::GenomeFacility {
+            building:
+                Some(ConstructionStatus {
+                    building,
+                    finished_on,
+                }),
+            ..
+        } => {
+            assert_eq!(*building, hybrid);
+            assert_eq!(finished_on.turn_number(), started_on + 4);
+        }
+        structure => panic!("expected a hybrid being built, found {:?}", structure),
+    }
+
+    while !(harness.turn_number() == started_on + 4 && harness.whose_turn() == player) {
+        assert_eq!(harness.units(&player).len(), 1, "the hybrid finished early");
+
+        let current = harness.whose_turn();
+        harness.end_turn(&current);
+    }
+
+    let units = harness.units(&player);
+    assert_eq!(units.len(), 2);
+    assert!(units
+        .iter()
+        .any(|unit| *unit.position == facility && *unit.hybrid_type == hybrid));
+    assert!(matches!(
+        &*harness.tile(facility, 0).structure,
+        TileStructure::GenomeFacility { building: None, .. }
+    ));
+    assert_eq!(harness.units(other(&player)).len(), 1);
+}
+
+#[test]
+fn players_in_different_rooms_play_separate_matches() {
+    let others = ["carol", "dave"];
+    let mut harness = Harness::start_in_rooms(&[("first", &PLAYERS), ("second", &others)], &[]);
+    harness.start_game();
+
+    let (first, second) = (harness.game("first"), harness.game("second"));
+    assert!(first.room.key != second.room.key);
+    assert!(first.room.map_entity != second.room.map_entity);
+    for username in PLAYERS {
+        assert!(second.user_key_assoc.get_from_name(username).is_none());
+    }
+
+    let waiting = second.playing.as_ref().unwrap().turn_tracker.player;
+
+    // Ending a turn in one match leaves the other alone
+    let player = harness.whose_turn();
+    harness.end_turn(&player);
+    assert_eq!(
+        harness
+            .game("second")
+            .playing
+            .as_ref()
+            .unwrap()
+            .turn_tracker
+            .player,
+        waiting
+    );
+    assert_eq!(harness.units("carol").len(), 1);
+}
+
+#[test]
+fn attacks_only_hit_units_in_the_same_match() {
+    let others = ["carol", "dave"];
+    let mut harness = Harness::start_in_rooms(&[("first", &PLAYERS), ("second", &others)], &[]);
+    harness.start_game();
+
+    // Both matches are played on the same map, so move a unit in the second onto a hex which is
+    // empty in the first
+    let target = harness.whose_turn_in("second");
+    let (start, facility, _) = home(&harness, &target);
+    harness.move_unit(&target, start, facility);
+    let health = *harness.units(&target)[0].current_health;
+
+    // And attack that hex from the same spawn point in the first
+    let mut attacker = harness.whose_turn();
+    if home(&harness, &attacker).0 != start {
+        harness.end_turn(&attacker);
+        attacker = harness.whose_turn();
+    }
+    harness.send(
+        &attacker,
+        Some((start, 0)),
+        PlayerInputVariant::Attack(facility),
+    );
+    harness.step_for(Duration::from_millis(200));
+
+    let units = harness.units(&target);
+    assert_eq!(units.len(), 1);
+    assert_eq!(*units[0].position, facility);
+    assert_eq!(*units[0].current_health, health);
+    assert_eq!(
+        *harness.units(&attacker)[0].stamina_remaining,
+        *harness.units(other(&attacker))[0].stamina_remaining,
+        "the attack should have been rejected without using the attacker's turn"
+    );
+}
@@ -0,0 +1,209 @@
+//! Compares working out what every player can see from scratch each tick with keeping a [`Sight`]
+//! per player up to date, on maps much larger than the game is usually played on. Run with
+//! `cargo bench -p rgj_shared --bench visibility`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use rgj_shared::{
+    behavior::{
+        visibility::{Sight, Viewpoint},
+        AxialCoordinates,
+    },
+    protocol::game_sync::map_sync::{TileType, MAP_HEIGHT},
+    resources::MapConfig,
+};
+
+const PLAYERS: usize = 6;
+
+struct Scenario {
+    name: &'static str,
+    config: MapConfig,
+    units_per_player: usize,
+    /// How many ticks to simulate. Recomputing everything is slow enough on the larger maps that
+    /// only a few are simulated.
+    ticks: usize,
+}
+
+/// Every player's units, as positions with viewing distances
+type Armies = Vec<Vec<(AxialCoordinates, u32)>>;
+
+fn main() {
+    let scenarios = [
+        Scenario {
+            name: "64x64, 10 units each",
+            config: MapConfig {
+                size_width: 64,
+                size_height: 64,
+            },
+            units_per_player: 10,
+            ticks: 100,
+        },
+        Scenario {
+            name: "200x200, 30 units each",
+            config: MapConfig {
+                size_width: 200,
+                size_height: 200,
+            },
+            units_per_player: 30,
+            ticks: 10,
+        },
+    ];
+
+    for scenario in &scenarios {
+        let moves = moves(scenario);
+        let armies = armies(scenario);
+
+        let full = time(|| full_recompute(scenario, armies.clone(), &moves));
+        let incremental = time(|| incremental(scenario, armies.clone(), &moves));
+
+        println!(
+            "{:<24} full recompute {:>10.2?}/tick   incremental {:>10.2?}/tick   ({:.0}x faster)",
+            scenario.name,
+            full / scenario.ticks as u32,
+            incremental / scenario.ticks as u32,
+            full.as_secs_f64() / incremental.as_secs_f64()
+        );
+    }
+}
+
+/// Where every unit starts
+fn armies(scenario: &Scenario) -> Armies {
+    let mut rng = StdRng::seed_from_u64(0);
+
+    (0..PLAYERS)
+        .map(|_| {
+            (0..scenario.units_per_player)
+                .map(|_| (random_hex(&mut rng, &scenario.config), rng.gen_range(1..=4)))
+                .collect()
+        })
+        .collect()
+}
+
+/// One unit stepping to a neighboring hex each tick, given as its player, which of their units it
+/// is, and which neighbor it steps to
+fn moves(scenario: &Scenario) -> Vec<(usize, usize, usize)> {
+    let mut rng = StdRng::seed_from_u64(1);
+
+    (0..scenario.ticks)
+        .map(|_| {
+            (
+                rng.gen_range(0..PLAYERS),
+                rng.gen_range(0..scenario.units_per_player),
+                rng.gen_range(0..6),
+            )
+        })
+        .collect()
+}
+
+fn random_hex(rng: &mut StdRng, config: &MapConfig) -> AxialCoordinates {
+    AxialCoordinates::new(
+        rng.gen_range(0..config.size_width as i32),
+        rng.gen_range(0..config.size_height as i32),
+    )
+}
+
+fn step(armies: &mut Armies, config: &MapConfig, (player, unit, direction): (usize, usize, usize)) {
+    let (position, _) = &mut armies[player][unit];
+    let next = position.neighbors()[direction];
+
+    if next.is_in_bounds(config) {
+        *position = next;
+    }
+}
+
+/// Runs `f` enough times to get a steady measurement, returning the fastest run
+fn time(mut f: impl FnMut() -> usize) -> Duration {
+    let mut fastest = Duration::MAX;
+
+    for _ in 0..3 {
+        let start = Instant::now();
+        black_box(f());
+        fastest = fastest.min(start.elapsed());
+    }
+
+    fastest
+}
+
+/// Open ground, so that the old radius is what every unit sees
+fn open_terrain(_: AxialCoordinates, _: i32) -> Option<TileType> {
+    Some(TileType::Grass)
+}
+
+/// How visibility used to be found: every tick, list the hexes each player's units can see, then
+/// check every tile on every layer against the list
+fn full_recompute(
+    scenario: &Scenario,
+    mut armies: Armies,
+    moves: &[(usize, usize, usize)],
+) -> usize {
+    let config = &scenario.config;
+    let mut visible_tiles = 0;
+
+    for next in moves {
+        step(&mut armies, config, *next);
+
+        for army in &armies {
+            let mut visible = Vec::new();
+            for (position, viewing_distance) in army {
+                for qr in position.range(*viewing_distance) {
+                    if qr.is_in_bounds(config) && !visible.contains(&qr) {
+                        visible.push(qr);
+                    }
+                }
+            }
+
+            for _layer in 0..MAP_HEIGHT {
+                for r in 0..config.size_height as i32 {
+                    for q in 0..config.size_width as i32 {
+                        if visible.contains(&AxialCoordinates::new(q, r)) {
+                            visible_tiles += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    visible_tiles
+}
+
+/// Keeping a sight per player, and only touching the hexes whose visibility changed
+fn incremental(scenario: &Scenario, mut armies: Armies, moves: &[(usize, usize, usize)]) -> usize {
+    let config = &scenario.config;
+    let mut sights: Vec<Sight<usize>> = armies.iter().map(|_| Sight::new(*config)).collect();
+    let mut newly_visible_tiles = 0;
+
+    for (sight, army) in sights.iter_mut().zip(&armies) {
+        sight.sync_units(unit_sights(army), open_terrain);
+        sight.take_changed();
+    }
+
+    for next in moves {
+        step(&mut armies, config, *next);
+
+        for (sight, army) in sights.iter_mut().zip(&armies) {
+            sight.sync_units(unit_sights(army), open_terrain);
+
+            for qr in sight.take_changed() {
+                if sight.is_visible(qr) {
+                    newly_visible_tiles += MAP_HEIGHT as usize;
+                }
+            }
+        }
+    }
+
+    newly_visible_tiles
+}
+
+fn unit_sights(army: &[(AxialCoordinates, u32)]) -> impl Iterator<Item = (usize, Viewpoint)> + '_ {
+    army.iter()
+        .enumerate()
+        .map(|(unit, (position, viewing_distance))| {
+            (unit, Viewpoint::new(*position, 0, *viewing_distance))
+        })
+}
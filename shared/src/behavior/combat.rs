@@ -0,0 +1,93 @@
+//! Resolution of one unit attacking another. The server decides the outcome of every attack with
+//! [`resolve_attack`], while the client uses [`check_attack`] to avoid sending attacks that would
+//! be rejected anyway.
+
+use thiserror::Error;
+
+use super::AxialCoordinates;
+use crate::components::genome::Hybrid;
+
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum CombatError {
+    #[error("there is no creature on that tile to attack")]
+    NoTarget,
+    #[error("you cannot attack your own creatures")]
+    FriendlyTarget,
+    #[error("creatures can only attack their neighbours")]
+    NotAdjacent,
+    #[error("this creature is too tired to attack -- wait until your next turn")]
+    NoStamina,
+}
+
+/// The result of one unit attacking another
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CombatOutcome {
+    /// The damage the attacker dealt to the defender
+    pub damage_dealt: u16,
+    /// The damage the defender dealt back to the attacker
+    pub damage_taken: u16,
+
+    pub attacker_health: u16,
+    pub defender_health: u16,
+}
+
+impl CombatOutcome {
+    pub fn attacker_died(&self) -> bool {
+        self.attacker_health == 0
+    }
+
+    pub fn defender_died(&self) -> bool {
+        self.defender_health == 0
+    }
+}
+
+/// Whether a unit with the given position and stamina may attack the tile at `target`. Attacking
+/// uses up all of a unit's remaining stamina, so it must have some left.
+pub fn check_attack(
+    attacker_position: AxialCoordinates,
+    attacker_stamina: u16,
+    target: AxialCoordinates,
+) -> Result<(), CombatError> {
+    if !attacker_position.is_adjacent(target) {
+        Err(CombatError::NotAdjacent)
+    } else if attacker_stamina == 0 {
+        Err(CombatError::NoStamina)
+    } else {
+        Ok(())
+    }
+}
+
+/// The damage a defender deals back to its attacker. Defenders hit back with their full
+/// `attack_damage` when unhurt, and proportionally less the more wounded they are.
+pub fn retaliation_damage(defender: &Hybrid, defender_health: u16) -> u16 {
+    let max_health = defender.body().health.max(1) as u32;
+    let attack_damage = defender.head().attack_damage as u32;
+
+    (attack_damage * defender_health.min(max_health as u16) as u32 / max_health) as u16
+}
+
+/// Resolves an attack. The attacker strikes first with its `attack_damage`, and if the defender
+/// survives it retaliates according to [`retaliation_damage`].
+pub fn resolve_attack(
+    attacker: &Hybrid,
+    attacker_health: u16,
+    defender: &Hybrid,
+    defender_health: u16,
+) -> CombatOutcome {
+    let damage_dealt = attacker.head().attack_damage.min(defender_health);
+    let defender_health = defender_health - damage_dealt;
+
+    let damage_taken = if defender_health == 0 {
+        0
+    } else {
+        retaliation_damage(defender, defender_health).min(attacker_health)
+    };
+    let attacker_health = attacker_health - damage_taken;
+
+    CombatOutcome {
+        damage_dealt,
+        damage_taken,
+        attacker_health,
+        defender_health,
+    }
+}
@@ -0,0 +1,246 @@
+//! Hexagon geometry shared between the client and the server. Every tile on the map is addressed
+//! with pointy-topped [`AxialCoordinates`], and anything that needs to reason about distances,
+//! neighbours, lines, or world-space positions should go through the functions here so that both
+//! sides always agree.
+
+use std::ops::{Add, Mul, Neg, Sub};
+
+use bevy::math::Vec2;
+
+use super::{AxialCoordinates, HEXAGON_SIZE};
+use crate::resources::MapConfig;
+
+/// The six unit offsets of neighbouring hexes, in the order used for direction indices
+pub const AXIAL_DIRECTIONS: [AxialCoordinates; 6] = [
+    AxialCoordinates::new(1, 0),
+    AxialCoordinates::new(1, -1),
+    AxialCoordinates::new(0, -1),
+    AxialCoordinates::new(-1, 0),
+    AxialCoordinates::new(-1, 1),
+    AxialCoordinates::new(0, 1),
+];
+
+/// Cube coordinates are axial coordinates with the implied third axis made explicit such that
+/// `q + r + s == 0`. They make rounding and rotation much simpler.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CubeCoordinates {
+    pub q: i32,
+    pub r: i32,
+    pub s: i32,
+}
+
+impl CubeCoordinates {
+    pub const fn new(q: i32, r: i32, s: i32) -> CubeCoordinates {
+        CubeCoordinates { q, r, s }
+    }
+
+    /// Rounds fractional cube coordinates to the hex containing them, resetting whichever
+    /// component had the largest rounding error so that the `q + r + s == 0` constraint holds
+    pub fn round(q: f32, r: f32, s: f32) -> CubeCoordinates {
+        let mut rq = q.round();
+        let mut rr = r.round();
+        let mut rs = s.round();
+
+        let q_diff = (rq - q).abs();
+        let r_diff = (rr - r).abs();
+        let s_diff = (rs - s).abs();
+
+        if q_diff > r_diff && q_diff > s_diff {
+            rq = -rr - rs;
+        } else if r_diff > s_diff {
+            rr = -rq - rs;
+        } else {
+            rs = -rq - rr;
+        }
+
+        CubeCoordinates::new(rq as i32, rr as i32, rs as i32)
+    }
+}
+
+impl From<AxialCoordinates> for CubeCoordinates {
+    fn from(axial: AxialCoordinates) -> Self {
+        CubeCoordinates::new(axial.column_q, axial.row_r, -axial.column_q - axial.row_r)
+    }
+}
+
+impl From<CubeCoordinates> for AxialCoordinates {
+    fn from(cube: CubeCoordinates) -> Self {
+        AxialCoordinates::new(cube.q, cube.r)
+    }
+}
+
+impl Add for AxialCoordinates {
+    type Output = AxialCoordinates;
+    fn add(self, rhs: AxialCoordinates) -> AxialCoordinates {
+        AxialCoordinates::new(self.column_q + rhs.column_q, self.row_r + rhs.row_r)
+    }
+}
+
+impl Sub for AxialCoordinates {
+    type Output = AxialCoordinates;
+    fn sub(self, rhs: AxialCoordinates) -> AxialCoordinates {
+        AxialCoordinates::new(self.column_q - rhs.column_q, self.row_r - rhs.row_r)
+    }
+}
+
+impl Mul<i32> for AxialCoordinates {
+    type Output = AxialCoordinates;
+    fn mul(self, rhs: i32) -> AxialCoordinates {
+        AxialCoordinates::new(self.column_q * rhs, self.row_r * rhs)
+    }
+}
+
+impl Neg for AxialCoordinates {
+    type Output = AxialCoordinates;
+    fn neg(self) -> AxialCoordinates {
+        AxialCoordinates::new(-self.column_q, -self.row_r)
+    }
+}
+
+impl AxialCoordinates {
+    pub fn to_cube(self) -> CubeCoordinates {
+        self.into()
+    }
+
+    /// The number of steps it takes to walk from one hex to the other
+    pub fn distance(self, other: AxialCoordinates) -> u32 {
+        let diff = (self - other).to_cube();
+        ((diff.q.abs() + diff.r.abs() + diff.s.abs()) / 2) as u32
+    }
+
+    /// The adjacent hex in one of the six [`AXIAL_DIRECTIONS`], wrapping the direction index
+    pub fn neighbor(self, direction: usize) -> AxialCoordinates {
+        self + AXIAL_DIRECTIONS[direction % 6]
+    }
+
+    pub fn neighbors(self) -> [AxialCoordinates; 6] {
+        AXIAL_DIRECTIONS.map(|dir| self + dir)
+    }
+
+    pub fn is_adjacent(self, other: AxialCoordinates) -> bool {
+        self.distance(other) == 1
+    }
+
+    /// Every hex exactly `radius` steps away, in walking order around the ring. A radius of zero is
+    /// just the hex itself.
+    pub fn ring(self, radius: u32) -> Vec<AxialCoordinates> {
+        if radius == 0 {
+            return vec![self];
+        }
+
+        let mut ring = Vec::with_capacity(6 * radius as usize);
+        let mut hex = self + AXIAL_DIRECTIONS[4] * radius as i32;
+
+        for direction in 0..6 {
+            for _ in 0..radius {
+                ring.push(hex);
+                hex = hex.neighbor(direction);
+            }
+        }
+
+        ring
+    }
+
+    /// Every hex within `radius` steps, ordered from the center outwards ring by ring
+    pub fn spiral(self, radius: u32) -> Vec<AxialCoordinates> {
+        let mut spiral = Vec::with_capacity(1 + 3 * radius as usize * (radius as usize + 1));
+
+        for ring in 0..=radius {
+            spiral.extend(self.ring(ring));
+        }
+
+        spiral
+    }
+
+    /// Every hex within `radius` steps in row-major order. This contains the same hexes as
+    /// [`AxialCoordinates::spiral`], but is cheaper when the order does not matter.
+    pub fn range(self, radius: u32) -> Vec<AxialCoordinates> {
+        let radius = radius as i32;
+        let mut range = Vec::with_capacity(1 + 3 * radius as usize * (radius as usize + 1));
+
+        for q_offset in -radius..=radius {
+            for r_offset in std::cmp::max(-radius, -q_offset - radius)
+                ..=std::cmp::min(radius, -q_offset + radius)
+            {
+                range.push(self + AxialCoordinates::new(q_offset, r_offset));
+            }
+        }
+
+        range
+    }
+
+    /// The hexes a straight line from `self` to `other` passes through, including both ends
+    pub fn line_to(self, other: AxialCoordinates) -> Vec<AxialCoordinates> {
+        let dist = self.distance(other);
+
+        if dist == 0 {
+            return vec![self];
+        }
+
+        // Nudge the endpoints slightly so that lines running exactly along hex edges always fall
+        // to the same side instead of depending on floating point error
+        let start = self.to_cube();
+        let end = other.to_cube();
+        let (start_q, start_r, start_s) = (
+            start.q as f32 + 1e-3,
+            start.r as f32 + 1e-3,
+            start.s as f32 - 2e-3,
+        );
+        let (end_q, end_r, end_s) = (
+            end.q as f32 + 1e-3,
+            end.r as f32 + 1e-3,
+            end.s as f32 - 2e-3,
+        );
+
+        (0..=dist)
+            .map(|i| {
+                let t = i as f32 / dist as f32;
+                CubeCoordinates::round(
+                    start_q + (end_q - start_q) * t,
+                    start_r + (end_r - start_r) * t,
+                    start_s + (end_s - start_s) * t,
+                )
+                .into()
+            })
+            .collect()
+    }
+
+    /// Rotates this hex about `center` by 60 degrees per step. Negative steps rotate the opposite
+    /// way.
+    pub fn rotate_around(self, center: AxialCoordinates, steps: i32) -> AxialCoordinates {
+        let mut cube = (self - center).to_cube();
+
+        for _ in 0..steps.rem_euclid(6) {
+            cube = CubeCoordinates::new(-cube.r, -cube.s, -cube.q);
+        }
+
+        center + cube.into()
+    }
+
+    /// The world-space position of the center of this hex
+    pub fn to_world(self) -> Vec2 {
+        let q = self.column_q as f32;
+        let r = self.row_r as f32;
+
+        Vec2::new(
+            HEXAGON_SIZE * (f32::sqrt(3.0) * q + f32::sqrt(3.0) / 2.0 * r),
+            HEXAGON_SIZE * (3.0 / 2.0 * r),
+        )
+    }
+
+    /// The hex containing the given world-space position. The result may lie outside of the map
+    pub fn from_world(position: Vec2) -> AxialCoordinates {
+        let q = (f32::sqrt(3.0) / 3.0 * position.x - 1.0 / 3.0 * position.y) / HEXAGON_SIZE;
+        let r = (2.0 / 3.0 * position.y) / HEXAGON_SIZE;
+
+        CubeCoordinates::round(q, r, -q - r).into()
+    }
+
+    /// Whether the hex lies within the `size_width` by `size_height` map
+    pub fn is_in_bounds(self, map_conf: &MapConfig) -> bool {
+        self.column_q >= 0
+            && self.row_r >= 0
+            && self.column_q < map_conf.size_width as i32
+            && self.row_r < map_conf.size_height as i32
+    }
+}
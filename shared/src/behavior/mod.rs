@@ -1,7 +1,12 @@
 use lazy_static::lazy_static;
 use naia_shared::{derive_serde, serde};
 
+pub mod combat;
 pub mod handle_input;
+pub mod hex;
+pub mod movement;
+pub mod pathfinding;
+pub mod visibility;
 
 pub const HEXAGON_SIZE: f32 = 75.0;
 pub const HEXAGON_HEIGHT: f32 = HEXAGON_SIZE * 2.0;
@@ -19,7 +24,7 @@ pub struct AxialCoordinates {
 }
 
 impl AxialCoordinates {
-    pub fn new(q: i32, r: i32) -> AxialCoordinates {
+    pub const fn new(q: i32, r: i32) -> AxialCoordinates {
         AxialCoordinates {
             column_q: q,
             row_r: r,
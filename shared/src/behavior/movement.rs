@@ -0,0 +1,134 @@
+//! The stamina cost of moving a hybrid across the map. Every unit starts its turn with
+//! [`STAMINA_PER_TURN`], and each step spends a share of it that depends on the tile being entered,
+//! how quickly the hybrid's limbs cross that kind of terrain, and how large its body is.
+
+use thiserror::Error;
+
+use crate::{
+    components::genome::Hybrid,
+    protocol::game_sync::map_sync::{TileType, MAP_HEIGHT},
+};
+
+/// The stamina every unit has to spend on movement in a single turn. This is divisible by every
+/// `tiles_per_turn` in use so that a unit crossing plain terrain moves exactly that many tiles.
+pub const STAMINA_PER_TURN: u16 = 60;
+
+/// The cheapest a single step may ever cost. Step costs are clamped to this so that every move
+/// spends at least some stamina.
+pub const MIN_STEP_COST: u16 = 1;
+
+impl TileType {
+    /// How much harder this tile is to cross than plain terrain of the same [`TerrainType`], or
+    /// [`None`] if nothing is known about it
+    pub fn base_movement_cost(&self) -> Option<f32> {
+        match self {
+            TileType::Fog => None,
+
+            TileType::Grass => Some(1.0),
+            // Undergrowth slows everything down
+            TileType::Forest => Some(2.0),
+            // The heat is exhausting to walk through
+            TileType::Desert => Some(1.5),
+
+            TileType::Ocean => Some(1.0),
+            // Swimming with the current is easy
+            TileType::River => Some(0.5),
+            TileType::DesertOasis => Some(1.0),
+
+            TileType::ClearSky => Some(1.0),
+            TileType::WindySky => Some(1.5),
+            TileType::StormySky => Some(2.0),
+        }
+    }
+
+    /// Every tile type that can be crossed, used to find the cheapest step a hybrid can take
+    pub const PASSABLE: [TileType; 9] = [
+        TileType::Grass,
+        TileType::Forest,
+        TileType::Desert,
+        TileType::Ocean,
+        TileType::River,
+        TileType::DesertOasis,
+        TileType::ClearSky,
+        TileType::WindySky,
+        TileType::StormySky,
+    ];
+}
+
+/// The stamina the given hybrid has at the start of each of its turns. How fast its limbs are is
+/// accounted for in [`step_cost`], so every hybrid gets the same [`STAMINA_PER_TURN`] to spend.
+pub fn max_stamina(_hybrid: &Hybrid) -> u16 {
+    STAMINA_PER_TURN
+}
+
+/// The stamina it costs the given hybrid to step onto a tile, or [`None`] if it cannot enter it.
+///
+/// A plain tile costs an even share of [`STAMINA_PER_TURN`] across the `tiles_per_turn` of the
+/// limbs used to cross it. This is then scaled by the tile's
+/// [`base_movement_cost`](TileType::base_movement_cost) and the body's `size_penalty`.
+pub fn step_cost(hybrid: &Hybrid, tile: TileType) -> Option<u16> {
+    let base_cost = tile.base_movement_cost()?;
+    let tiles_per_turn = hybrid.tiles_per_turn(tile.terrain_type()?)?;
+
+    if tiles_per_turn == 0 {
+        return None;
+    }
+
+    let cost =
+        STAMINA_PER_TURN as f32 / tiles_per_turn as f32 * base_cost * hybrid.body().size_penalty;
+
+    Some((cost.ceil() as u16).max(MIN_STEP_COST))
+}
+
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum LayerChangeError {
+    #[error("creatures can only take off or land one layer at a time")]
+    NoSuchLayer,
+    #[error("you must be able to see through the fog to move there -- try getting closer first")]
+    InFog,
+    #[error("this creature cannot move onto that tile")]
+    Impassable,
+    #[error("this creature needs {needed} stamina to do that, but only has {remaining}")]
+    NotEnoughStamina { needed: u16, remaining: u16 },
+}
+
+/// The stamina it costs the given hybrid to take off from or land on the hex it is standing on,
+/// moving from layer `from` to layer `to`. `target` is the [`TileType`] of the hex on the new layer.
+///
+/// Changing layer costs the same as stepping onto the tile on the new layer would.
+pub fn layer_change_cost(
+    hybrid: &Hybrid,
+    stamina: u16,
+    from: i32,
+    to: i32,
+    target: TileType,
+) -> Result<u16, LayerChangeError> {
+    if !(0..MAP_HEIGHT).contains(&to) || (from - to).abs() != 1 {
+        return Err(LayerChangeError::NoSuchLayer);
+    }
+
+    if target == TileType::Fog {
+        return Err(LayerChangeError::InFog);
+    }
+
+    let cost = step_cost(hybrid, target).ok_or(LayerChangeError::Impassable)?;
+
+    if cost > stamina {
+        Err(LayerChangeError::NotEnoughStamina {
+            needed: cost,
+            remaining: stamina,
+        })
+    } else {
+        Ok(cost)
+    }
+}
+
+/// The cheapest step the given hybrid can take onto any tile. This never overestimates the real
+/// cost of a step, so it is safe to use as a pathfinding heuristic.
+pub fn min_step_cost(hybrid: &Hybrid) -> u16 {
+    TileType::PASSABLE
+        .iter()
+        .filter_map(|tile| step_cost(hybrid, *tile))
+        .min()
+        .unwrap_or(MIN_STEP_COST)
+}
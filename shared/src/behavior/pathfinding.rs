@@ -0,0 +1,143 @@
+//! A* pathfinding over the ground layer of the map. Both the client (to preview routes) and the
+//! server (to validate and execute moves) use [`find_path`] so that they always agree on which
+//! tiles a unit walks through and how much stamina it costs.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use thiserror::Error;
+
+use super::{
+    movement::{min_step_cost, step_cost},
+    AxialCoordinates,
+};
+use crate::{components::genome::Hybrid, protocol::game_sync::map_sync::TileType};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Path {
+    /// The tiles walked through in order, not including the tile the unit starts on
+    pub steps: Vec<AxialCoordinates>,
+    /// The total stamina spent walking the whole path
+    pub cost: u16,
+}
+
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum PathfindingError {
+    #[error("the unit is already on that tile")]
+    AlreadyThere,
+    #[error("you must be able to see through the fog to cross it -- try getting closer first")]
+    DestinationInFog,
+    #[error("this creature cannot move onto that tile")]
+    ImpassableDestination,
+    #[error("this creature has no route to that tile")]
+    Unreachable,
+    #[error("this creature needs {needed} stamina to get there, but only has {remaining}")]
+    NotEnoughStamina { needed: u16, remaining: u16 },
+}
+
+/// Finds the cheapest path for `hybrid` from `start` to `goal` that it can afford with `stamina`.
+///
+/// `tile_at` is used to look up the [`TileType`] of a hex on the ground layer as the caller
+/// perceives it, returning [`None`] for hexes outside of the map.
+pub fn find_path<F>(
+    hybrid: &Hybrid,
+    stamina: u16,
+    start: AxialCoordinates,
+    goal: AxialCoordinates,
+    tile_at: F,
+) -> Result<Path, PathfindingError>
+where
+    F: Fn(AxialCoordinates) -> Option<TileType>,
+{
+    if start == goal {
+        return Err(PathfindingError::AlreadyThere);
+    }
+
+    match tile_at(goal) {
+        None => return Err(PathfindingError::Unreachable),
+        Some(TileType::Fog) => return Err(PathfindingError::DestinationInFog),
+        Some(tile) => {
+            if step_cost(hybrid, tile).is_none() {
+                return Err(PathfindingError::ImpassableDestination);
+            }
+        }
+    }
+
+    // Every remaining step costs at least the hybrid's cheapest step, so this never overestimates
+    let cheapest_step = min_step_cost(hybrid) as u32;
+    let heuristic = |hex: AxialCoordinates| hex.distance(goal) * cheapest_step;
+
+    // Costs are tracked as u32 so that long detours can't overflow before being compared against
+    // the unit's stamina
+    let mut cost_so_far: HashMap<AxialCoordinates, u32> = HashMap::new();
+    let mut came_from: HashMap<AxialCoordinates, AxialCoordinates> = HashMap::new();
+    let mut frontier = BinaryHeap::new();
+
+    cost_so_far.insert(start, 0);
+    frontier.push(Reverse((heuristic(start), 0, start.column_q, start.row_r)));
+
+    while let Some(Reverse((_, cost, q, r))) = frontier.pop() {
+        let current = AxialCoordinates::new(q, r);
+
+        if current == goal {
+            break;
+        }
+
+        // Skip stale entries for hexes that have since been reached more cheaply
+        if cost > cost_so_far[&current] {
+            continue;
+        }
+
+        for next in current.neighbors() {
+            let step = match tile_at(next).and_then(|tile| step_cost(hybrid, tile)) {
+                Some(step) => step as u32,
+                None => continue,
+            };
+
+            let new_cost = cost + step;
+            if cost_so_far
+                .get(&next)
+                .map(|&old_cost| new_cost < old_cost)
+                .unwrap_or(true)
+            {
+                cost_so_far.insert(next, new_cost);
+                came_from.insert(next, current);
+                frontier.push(Reverse((
+                    new_cost + heuristic(next),
+                    new_cost,
+                    next.column_q,
+                    next.row_r,
+                )));
+            }
+        }
+    }
+
+    let total_cost = *cost_so_far
+        .get(&goal)
+        .ok_or(PathfindingError::Unreachable)?;
+
+    if total_cost > stamina as u32 {
+        return Err(PathfindingError::NotEnoughStamina {
+            needed: total_cost.min(u16::MAX as u32) as u16,
+            remaining: stamina,
+        });
+    }
+
+    let mut steps = vec![goal];
+    let mut current = goal;
+    while let Some(&previous) = came_from.get(&current) {
+        if previous == start {
+            break;
+        }
+        steps.push(previous);
+        current = previous;
+    }
+    steps.reverse();
+
+    Ok(Path {
+        steps,
+        cost: total_cost as u16,
+    })
+}
@@ -0,0 +1,204 @@
+//! Keeping track of which hexes a player's units can see. Rather than working out everything a
+//! player can see from scratch whenever anything moves, a [`Sight`] counts how many units see each
+//! hex and only revisits the hexes around units that moved, appeared or disappeared. The hexes
+//! whose visibility actually changed are collected so that only they need to be sent again.
+//!
+//! Units see in straight lines from where they stand. On the ground, forests hide whatever is
+//! behind them. Flying units see over forests and further than those on the ground, but storms in
+//! the sky hide what is behind them, and a flying unit inside a storm can barely see at all.
+
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
+
+use super::AxialCoordinates;
+use crate::{protocol::game_sync::map_sync::TileType, resources::MapConfig};
+
+/// How much further than their viewing distance units on the sky layer can see
+pub const SKY_VIEWING_BONUS: u32 = 1;
+
+/// Where a unit looks out from, and how far its head lets it see
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Viewpoint {
+    pub position: AxialCoordinates,
+    pub layer: i32,
+    pub viewing_distance: u32,
+}
+
+impl Viewpoint {
+    pub fn new(position: AxialCoordinates, layer: i32, viewing_distance: u32) -> Viewpoint {
+        Viewpoint {
+            position,
+            layer,
+            viewing_distance,
+        }
+    }
+
+    /// How far the unit can see, before anything in the way is taken into account
+    pub fn range(&self, tile_at: impl Fn(AxialCoordinates, i32) -> Option<TileType>) -> u32 {
+        if self.layer == 0 {
+            self.viewing_distance
+        } else if tile_at(self.position, self.layer) == Some(TileType::StormySky) {
+            1
+        } else {
+            self.viewing_distance + SKY_VIEWING_BONUS
+        }
+    }
+
+    /// Every hex on the map the unit can see, given what is on each tile. A hex is seen when none
+    /// of the hexes on the line to it between it and the unit block the view, so the forest or
+    /// storm doing the blocking is itself seen.
+    pub fn visible_hexes(
+        &self,
+        config: &MapConfig,
+        tile_at: impl Fn(AxialCoordinates, i32) -> Option<TileType>,
+    ) -> Vec<AxialCoordinates> {
+        self.position
+            .range(self.range(&tile_at))
+            .into_iter()
+            .filter(|qr| qr.is_in_bounds(config))
+            .filter(|qr| {
+                let line = self.position.line_to(*qr);
+
+                line.len() <= 2
+                    || !line[1..line.len() - 1].iter().any(|hex| {
+                        tile_at(*hex, self.layer)
+                            .map(|tile| blocks_sight(tile, self.layer))
+                            .unwrap_or(false)
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Whether a tile hides what is behind it from units on the given layer
+pub fn blocks_sight(tile: TileType, layer: i32) -> bool {
+    if layer == 0 {
+        tile == TileType::Forest
+    } else {
+        tile == TileType::StormySky
+    }
+}
+
+/// What a single unit can see
+#[derive(Clone, Debug)]
+struct UnitSight {
+    viewpoint: Viewpoint,
+    hexes: Vec<AxialCoordinates>,
+}
+
+/// Everything one player can see, built up from what each of their units can see. Units are told
+/// apart by `U`, which is whatever identifies them where the sight is kept.
+#[derive(Clone, Debug)]
+pub struct Sight<U> {
+    config: MapConfig,
+    /// How many of the player's units can see each hex, indexed by row then column
+    seen_by: Vec<u16>,
+    units: HashMap<U, UnitSight>,
+    /// Hexes which became visible or hidden since [`Sight::take_changed`] was last called
+    changed: HashSet<AxialCoordinates>,
+}
+
+impl<U: Copy + Eq + Hash> Sight<U> {
+    /// A sight of the map with no units, and so nothing visible
+    pub fn new(config: MapConfig) -> Sight<U> {
+        Sight {
+            config,
+            seen_by: vec![0; config.size_width as usize * config.size_height as usize],
+            units: HashMap::new(),
+            changed: HashSet::new(),
+        }
+    }
+
+    pub fn is_visible(&self, qr: AxialCoordinates) -> bool {
+        qr.is_in_bounds(&self.config) && self.seen_by[self.index(qr)] > 0
+    }
+
+    /// Places a unit, or moves it if it is already known, looking over the terrain given by
+    /// `tile_at`. Only the hexes it could see before and can see now are touched, and nothing at
+    /// all if it hasn't moved.
+    pub fn set_unit(
+        &mut self,
+        unit: U,
+        viewpoint: Viewpoint,
+        tile_at: impl Fn(AxialCoordinates, i32) -> Option<TileType>,
+    ) {
+        if let Some(sight) = self.units.get(&unit) {
+            if sight.viewpoint == viewpoint {
+                return;
+            }
+        }
+
+        self.remove_unit(unit);
+
+        let hexes = viewpoint.visible_hexes(&self.config, tile_at);
+
+        for hex in &hexes {
+            let index = self.index(*hex);
+            self.seen_by[index] += 1;
+
+            if self.seen_by[index] == 1 {
+                self.toggled(*hex);
+            }
+        }
+
+        self.units.insert(unit, UnitSight { viewpoint, hexes });
+    }
+
+    /// Forgets a unit, hiding whatever only it could see
+    pub fn remove_unit(&mut self, unit: U) {
+        if let Some(sight) = self.units.remove(&unit) {
+            for hex in sight.hexes {
+                let index = self.index(hex);
+                self.seen_by[index] -= 1;
+
+                if self.seen_by[index] == 0 {
+                    self.toggled(hex);
+                }
+            }
+        }
+    }
+
+    /// Brings the sight up to date with every unit the player has, given as each unit with where
+    /// it looks from. Units which are no longer given are removed.
+    pub fn sync_units(
+        &mut self,
+        units: impl IntoIterator<Item = (U, Viewpoint)>,
+        tile_at: impl Fn(AxialCoordinates, i32) -> Option<TileType>,
+    ) {
+        let mut current = HashSet::new();
+
+        for (unit, viewpoint) in units {
+            current.insert(unit);
+            self.set_unit(unit, viewpoint, &tile_at);
+        }
+
+        let gone: Vec<U> = self
+            .units
+            .keys()
+            .filter(|unit| !current.contains(*unit))
+            .copied()
+            .collect();
+        for unit in gone {
+            self.remove_unit(unit);
+        }
+    }
+
+    /// The hexes which became visible or hidden since this was last called
+    pub fn take_changed(&mut self) -> HashSet<AxialCoordinates> {
+        std::mem::take(&mut self.changed)
+    }
+
+    fn index(&self, qr: AxialCoordinates) -> usize {
+        qr.row_r as usize * self.config.size_width as usize + qr.column_q as usize
+    }
+
+    /// Notes that a hex went from hidden to visible or back. A hex that flips twice before anybody
+    /// looks has not changed at all.
+    fn toggled(&mut self, qr: AxialCoordinates) {
+        if !self.changed.remove(&qr) {
+            self.changed.insert(qr);
+        }
+    }
+}
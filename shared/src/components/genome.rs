@@ -221,6 +221,28 @@ lazy_static! {
     };
 }
 
+/// The genomes found in genome facilities, one per facility. Every genome except the deer, which
+/// each player starts with.
+pub fn unique_genomes() -> Vec<AnimalType> {
+    vec![
+        VAMPIRE_BAT.clone(),
+        CHICKEN.clone(),
+        ELECTRIC_EEL.clone(),
+        ELEPHANT.clone(),
+        RATTLESNAKE.clone(),
+        SAILFISH.clone(),
+        VULTURE.clone(),
+        WHALE.clone(),
+    ]
+}
+
+/// The unique genome with the given name, such as "Vampire-Bat"
+pub fn unique_genome(name: &str) -> Option<AnimalType> {
+    unique_genomes()
+        .into_iter()
+        .find(|genome| genome.name == name)
+}
+
 #[derive(Debug)]
 #[derive_serde]
 pub struct Hybrid {
@@ -280,6 +302,26 @@ impl Hybrid {
     pub fn limbs_type(&self) -> &AnimalType {
         &self.limbs
     }
+
+    /// Whether this hybrid's limbs allow it to move across the given [`TerrainType`]
+    pub fn can_traverse(&self, terrain: TerrainType) -> bool {
+        self.tiles_per_turn(terrain).is_some()
+    }
+
+    /// How many tiles of plain terrain of the given [`TerrainType`] this hybrid's limbs cover in a
+    /// turn, or [`None`] if they cannot cross it at all
+    pub fn tiles_per_turn(&self, terrain: TerrainType) -> Option<u8> {
+        let limbs = self.limbs();
+
+        if limbs.terrain_a.terrain_type == terrain {
+            Some(limbs.terrain_a.tiles_per_turn)
+        } else {
+            limbs
+                .terrain_b
+                .filter(|terrain_b| terrain_b.terrain_type == terrain)
+                .map(|terrain_b| terrain_b.tiles_per_turn)
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -1,5 +1,6 @@
 pub mod behavior;
 pub mod components;
+pub mod map;
 pub mod protocol;
 pub mod resources;
 
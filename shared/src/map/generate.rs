@@ -0,0 +1,513 @@
+//! Procedural map generation. The same seed and size always produce the same map.
+//!
+//! Generation runs in passes over a [`MapLayout`]: an elevation field decides where the continents
+//! and islands are, rivers are carved downhill from the highlands to the sea, the land is split
+//! into grassland, forest, and desert by a moisture field, oases are dropped into the middle of
+//! deserts, the sky is banded with wind and storms, and finally the genome facilities are spread
+//! out as evenly as the land allows.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    f32::consts::TAU,
+};
+
+use bevy::math::Vec2;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::{Facility, MapLayout, NUM_FACILITIES};
+use crate::{
+    behavior::{AxialCoordinates, HEXAGON_SIZE},
+    protocol::game_sync::map_sync::TileType,
+    resources::MapConfig,
+};
+
+/// The smallest width or height a generated map may have and still fit its facilities
+pub const MIN_GENERATED_SIZE: u16 = 10;
+
+/// The share of the ground layer which ends up as land rather than ocean
+const LAND_FRACTION: f32 = 0.45;
+/// The shares of the land which become desert and forest respectively, the rest being grassland
+const DESERT_FRACTION: f32 = 0.2;
+const FOREST_FRACTION: f32 = 0.3;
+/// Deserts at least this large get an oasis
+const MIN_OASIS_DESERT_SIZE: usize = 5;
+
+/// Generates a map of the given size from `seed`.
+///
+/// Both sides of the map must be at least [`MIN_GENERATED_SIZE`].
+pub fn generate(config: MapConfig, seed: u64) -> MapLayout {
+    assert!(
+        config.size_width >= MIN_GENERATED_SIZE && config.size_height >= MIN_GENERATED_SIZE,
+        "generated maps must be at least {0}x{0}",
+        MIN_GENERATED_SIZE
+    );
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut layout = MapLayout::filled(config, TileType::Ocean);
+
+    let elevation = elevation(&layout, &mut rng);
+    raise_land(&mut layout, &elevation);
+    scatter_islands(&mut layout, &mut rng);
+    carve_rivers(&mut layout, &elevation, &mut rng);
+    assign_biomes(&mut layout, &mut rng);
+    place_oases(&mut layout, &mut rng);
+    band_sky(&mut layout, &mut rng);
+    place_facilities(&mut layout, &mut rng);
+
+    layout
+}
+
+/// Smoothly interpolated random values on a square lattice laid over the world
+struct ValueNoise {
+    cell_size: f32,
+    columns: usize,
+    values: Vec<f32>,
+}
+
+impl ValueNoise {
+    fn new(rng: &mut StdRng, extent: Vec2, cell_size: f32) -> ValueNoise {
+        let columns = (extent.x / cell_size) as usize + 2;
+        let rows = (extent.y / cell_size) as usize + 2;
+
+        ValueNoise {
+            cell_size,
+            columns,
+            values: (0..columns * rows).map(|_| rng.gen()).collect(),
+        }
+    }
+
+    fn sample(&self, point: Vec2) -> f32 {
+        let x = point.x.max(0.0) / self.cell_size;
+        let y = point.y.max(0.0) / self.cell_size;
+
+        let (column, row) = (x as usize, y as usize);
+        let (tx, ty) = (smoothstep(x.fract()), smoothstep(y.fract()));
+
+        let at = |c: usize, r: usize| self.values[r * self.columns + c];
+        let top = lerp(at(column, row), at(column + 1, row), tx);
+        let bottom = lerp(at(column, row + 1), at(column + 1, row + 1), tx);
+
+        lerp(top, bottom, ty)
+    }
+}
+
+/// Several octaves of [`ValueNoise`], each half the size and weight of the last
+struct FractalNoise {
+    octaves: Vec<ValueNoise>,
+}
+
+impl FractalNoise {
+    fn new(rng: &mut StdRng, extent: Vec2, cell_size: f32, octaves: usize) -> FractalNoise {
+        FractalNoise {
+            octaves: (0..octaves)
+                .map(|octave| ValueNoise::new(rng, extent, cell_size / (1 << octave) as f32))
+                .collect(),
+        }
+    }
+
+    /// A value between zero and one
+    fn sample(&self, point: Vec2) -> f32 {
+        let mut total = 0.0;
+        let mut weight = 1.0;
+        let mut total_weight = 0.0;
+
+        for octave in &self.octaves {
+            total += octave.sample(point) * weight;
+            total_weight += weight;
+            weight /= 2.0;
+        }
+
+        total / total_weight
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// The position of a hex in the world measured in hexes rather than pixels
+fn position(hex: AxialCoordinates) -> Vec2 {
+    hex.to_world() / HEXAGON_SIZE
+}
+
+/// The size of the world covered by the map, measured in hexes
+fn extent(config: &MapConfig) -> Vec2 {
+    position(AxialCoordinates::new(
+        config.size_width as i32 - 1,
+        config.size_height as i32 - 1,
+    )) + Vec2::ONE
+}
+
+/// The value below which `fraction` of `values` lie
+fn percentile(values: impl Iterator<Item = f32>, fraction: f32) -> f32 {
+    let mut values: Vec<f32> = values.collect();
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let index = ((values.len() as f32 * fraction) as usize).min(values.len() - 1);
+    values[index]
+}
+
+fn is_land(tile: TileType) -> bool {
+    matches!(tile, TileType::Grass | TileType::Forest | TileType::Desert)
+}
+
+/// How far a hex is from the nearest edge of the map, from zero on the edge to one in the middle
+fn edge_distance(config: &MapConfig, hex: AxialCoordinates) -> f32 {
+    let steps = hex
+        .column_q
+        .min(hex.row_r)
+        .min(config.size_width as i32 - 1 - hex.column_q)
+        .min(config.size_height as i32 - 1 - hex.row_r);
+
+    let half = config.size_width.min(config.size_height) as f32 / 2.0;
+    (steps as f32 / half).min(1.0)
+}
+
+/// The height of every hex on the ground layer. The map falls away towards its edges so that
+/// continents end up surrounded by sea rather than cut off by the border.
+fn elevation(layout: &MapLayout, rng: &mut StdRng) -> HashMap<AxialCoordinates, f32> {
+    let config = layout.config;
+    let continent_size = config.size_width.min(config.size_height) as f32 / 2.5;
+    let noise = FractalNoise::new(rng, extent(&config), continent_size, 4);
+
+    layout
+        .hexes()
+        .map(|hex| {
+            let falloff = 1.0 - smoothstep((edge_distance(&config, hex) / 0.4).min(1.0));
+            (hex, noise.sample(position(hex)) - falloff * 0.5)
+        })
+        .collect()
+}
+
+/// Turns the highest [`LAND_FRACTION`] of the map into land
+fn raise_land(layout: &mut MapLayout, elevation: &HashMap<AxialCoordinates, f32>) {
+    let sea_level = percentile(elevation.values().copied(), 1.0 - LAND_FRACTION);
+
+    for (hex, height) in elevation {
+        if *height > sea_level {
+            layout.set_tile(*hex, 0, TileType::Grass);
+        }
+    }
+}
+
+/// Adds a handful of small islands out in open water, away from any existing land
+fn scatter_islands(layout: &mut MapLayout, rng: &mut StdRng) {
+    let config = layout.config;
+    let attempts = config.size_width as usize * config.size_height as usize / 100 + 1;
+
+    for _ in 0..attempts {
+        let center = AxialCoordinates::new(
+            rng.gen_range(1..config.size_width as i32 - 1),
+            rng.gen_range(1..config.size_height as i32 - 1),
+        );
+
+        let open_water = center
+            .range(3)
+            .into_iter()
+            .filter(|hex| hex.is_in_bounds(&config))
+            .all(|hex| !is_land(layout.tile(hex, 0)));
+
+        if !open_water {
+            continue;
+        }
+
+        layout.set_tile(center, 0, TileType::Grass);
+        for neighbor in center.neighbors() {
+            if neighbor.is_in_bounds(&config)
+                && edge_distance(&config, neighbor) > 0.0
+                && rng.gen_bool(0.4)
+            {
+                layout.set_tile(neighbor, 0, TileType::Grass);
+            }
+        }
+    }
+}
+
+/// Carves rivers from the highlands down to the sea. Each river takes the cheapest route to water,
+/// where climbing is expensive, so they wind along valleys and may join other rivers on the way.
+fn carve_rivers(
+    layout: &mut MapLayout,
+    elevation: &HashMap<AxialCoordinates, f32>,
+    rng: &mut StdRng,
+) {
+    let config = layout.config;
+
+    let land: Vec<AxialCoordinates> = layout
+        .hexes()
+        .filter(|hex| is_land(layout.tile(*hex, 0)))
+        .collect();
+
+    // Rivers start in the highest quarter of the land, inland from the coast
+    let highland = percentile(land.iter().map(|hex| elevation[hex]), 0.75);
+    let mut sources: Vec<AxialCoordinates> = land
+        .iter()
+        .copied()
+        .filter(|hex| elevation[hex] >= highland)
+        .filter(|hex| {
+            hex.range(2)
+                .into_iter()
+                .all(|near| near.is_in_bounds(&config) && is_land(layout.tile(near, 0)))
+        })
+        .collect();
+    sources.shuffle(rng);
+
+    let num_rivers = (land.len() / 60).max(1);
+
+    for source in sources.into_iter().take(num_rivers) {
+        if layout.tile(source, 0) == TileType::River {
+            continue;
+        }
+
+        if let Some(course) = river_course(layout, elevation, source) {
+            for hex in course {
+                layout.set_tile(hex, 0, TileType::River);
+            }
+        }
+    }
+}
+
+/// The hexes a river starting at `source` flows through before reaching the ocean or another river
+fn river_course(
+    layout: &MapLayout,
+    elevation: &HashMap<AxialCoordinates, f32>,
+    source: AxialCoordinates,
+) -> Option<Vec<AxialCoordinates>> {
+    // Heights are compared as integers so that they can be ordered in the heap
+    let cost_of = |hex: &AxialCoordinates| 1 + (elevation[hex].max(0.0) * 1000.0) as u32;
+
+    let mut cost_so_far = HashMap::new();
+    let mut came_from = HashMap::new();
+    let mut frontier = BinaryHeap::new();
+
+    cost_so_far.insert(source, 0);
+    frontier.push(Reverse((0, source.column_q, source.row_r)));
+
+    while let Some(Reverse((cost, q, r))) = frontier.pop() {
+        let current = AxialCoordinates::new(q, r);
+
+        if current != source && matches!(layout.tile(current, 0), TileType::Ocean | TileType::River)
+        {
+            let mut course = Vec::new();
+            let mut hex = came_from[&current];
+            while hex != source {
+                course.push(hex);
+                hex = came_from[&hex];
+            }
+            course.push(source);
+
+            return Some(course);
+        }
+
+        if cost > cost_so_far[&current] {
+            continue;
+        }
+
+        for next in current.neighbors() {
+            if !next.is_in_bounds(&layout.config) {
+                continue;
+            }
+
+            let new_cost = cost + cost_of(&next);
+            if cost_so_far
+                .get(&next)
+                .map(|&old_cost| new_cost < old_cost)
+                .unwrap_or(true)
+            {
+                cost_so_far.insert(next, new_cost);
+                came_from.insert(next, current);
+                frontier.push(Reverse((new_cost, next.column_q, next.row_r)));
+            }
+        }
+    }
+
+    None
+}
+
+/// Splits the land into desert, forest, and grassland according to how wet it is
+fn assign_biomes(layout: &mut MapLayout, rng: &mut StdRng) {
+    let config = layout.config;
+    let noise = FractalNoise::new(rng, extent(&config), 6.0, 3);
+
+    let land: Vec<AxialCoordinates> = layout
+        .hexes()
+        .filter(|hex| is_land(layout.tile(*hex, 0)))
+        .collect();
+
+    if land.is_empty() {
+        return;
+    }
+
+    // Land next to rivers is always a little wetter
+    let moisture: HashMap<AxialCoordinates, f32> = land
+        .iter()
+        .map(|hex| {
+            let near_river = hex.neighbors().iter().any(|neighbor| {
+                neighbor.is_in_bounds(&config) && layout.tile(*neighbor, 0) == TileType::River
+            });
+
+            let bonus = if near_river { 0.15 } else { 0.0 };
+            (*hex, noise.sample(position(*hex)) + bonus)
+        })
+        .collect();
+
+    let dry = percentile(moisture.values().copied(), DESERT_FRACTION);
+    let wet = percentile(moisture.values().copied(), 1.0 - FOREST_FRACTION);
+
+    for hex in land {
+        let tile = if moisture[&hex] < dry {
+            TileType::Desert
+        } else if moisture[&hex] > wet {
+            TileType::Forest
+        } else {
+            TileType::Grass
+        };
+
+        layout.set_tile(hex, 0, tile);
+    }
+}
+
+/// Puts an oasis in the heart of every sizeable desert
+fn place_oases(layout: &mut MapLayout, rng: &mut StdRng) {
+    let config = layout.config;
+    let mut seen = HashSet::new();
+
+    let deserts: Vec<AxialCoordinates> = layout
+        .hexes()
+        .filter(|hex| layout.tile(*hex, 0) == TileType::Desert)
+        .collect();
+
+    for start in deserts {
+        if !seen.insert(start) {
+            continue;
+        }
+
+        // Flood fill the whole desert
+        let mut region = vec![start];
+        let mut index = 0;
+        while index < region.len() {
+            for neighbor in region[index].neighbors() {
+                if neighbor.is_in_bounds(&config)
+                    && layout.tile(neighbor, 0) == TileType::Desert
+                    && seen.insert(neighbor)
+                {
+                    region.push(neighbor);
+                }
+            }
+            index += 1;
+        }
+
+        if region.len() < MIN_OASIS_DESERT_SIZE {
+            continue;
+        }
+
+        let desert_neighbors = |hex: &AxialCoordinates| {
+            hex.neighbors()
+                .iter()
+                .filter(|neighbor| {
+                    neighbor.is_in_bounds(&config) && layout.tile(**neighbor, 0) == TileType::Desert
+                })
+                .count()
+        };
+
+        let deepest = region.iter().map(desert_neighbors).max().unwrap();
+        let candidates: Vec<AxialCoordinates> = region
+            .iter()
+            .copied()
+            .filter(|hex| desert_neighbors(hex) == deepest)
+            .collect();
+
+        if let Some(oasis) = candidates.choose(rng) {
+            layout.set_tile(*oasis, 0, TileType::DesertOasis);
+        }
+    }
+}
+
+/// Fills the sky with bands of wind running across the map, with storms at the heart of the
+/// windiest bands
+fn band_sky(layout: &mut MapLayout, rng: &mut StdRng) {
+    let config = layout.config;
+    let noise = FractalNoise::new(rng, extent(&config), 4.0, 2);
+
+    let bands = rng.gen_range(1.5..3.0);
+    let phase = rng.gen_range(0.0..TAU);
+    let height = extent(&config).y;
+
+    for hex in layout.hexes().collect::<Vec<_>>() {
+        let point = position(hex);
+        let wind =
+            (TAU * bands * point.y / height + phase + (noise.sample(point) - 0.5) * 3.0).sin();
+
+        let tile = if wind > 0.85 {
+            TileType::StormySky
+        } else if wind > 0.4 {
+            TileType::WindySky
+        } else {
+            TileType::ClearSky
+        };
+
+        layout.set_tile(hex, 1, tile);
+    }
+}
+
+/// Spreads [`NUM_FACILITIES`] genome facilities over the land, each placed as far as possible from
+/// all of the others so that no part of the map is favoured
+fn place_facilities(layout: &mut MapLayout, rng: &mut StdRng) {
+    let config = layout.config;
+
+    let mut candidates: Vec<AxialCoordinates> = layout
+        .hexes()
+        .filter(|hex| is_land(layout.tile(*hex, 0)))
+        .collect();
+
+    // On a map with almost no land, fall back to putting facilities on the coast
+    if candidates.len() < NUM_FACILITIES {
+        for hex in layout.hexes().collect::<Vec<_>>() {
+            if !is_land(layout.tile(hex, 0)) && candidates.len() < NUM_FACILITIES {
+                layout.set_tile(hex, 0, TileType::Grass);
+                candidates.push(hex);
+            }
+        }
+    }
+
+    let first = *candidates.choose(rng).unwrap();
+    let mut facilities = vec![first];
+
+    // Distance to the nearest facility so far, kept up to date as each one is placed
+    let mut nearest: Vec<u32> = candidates.iter().map(|hex| hex.distance(first)).collect();
+
+    while facilities.len() < NUM_FACILITIES {
+        // Prefer hexes away from the map's edges when they are equally far from every facility
+        let (index, _) = candidates
+            .iter()
+            .enumerate()
+            .max_by(|(a, hex_a), (b, hex_b)| {
+                nearest[*a].cmp(&nearest[*b]).then_with(|| {
+                    edge_distance(&config, **hex_a)
+                        .partial_cmp(&edge_distance(&config, **hex_b))
+                        .unwrap()
+                })
+            })
+            .unwrap();
+
+        let facility = candidates[index];
+        facilities.push(facility);
+
+        for (distance, hex) in nearest.iter_mut().zip(&candidates) {
+            *distance = (*distance).min(hex.distance(facility));
+        }
+    }
+
+    layout.facilities = facilities
+        .into_iter()
+        .map(|position| Facility {
+            position,
+            layer: 0,
+            genome: None,
+        })
+        .collect();
+}
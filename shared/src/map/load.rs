@@ -0,0 +1,435 @@
+//! Loading maps from files. Two formats are understood, told apart by their first character.
+//!
+//! Versioned map files are [RON](https://github.com/ron-rs/ron) documents starting with a
+//! `version` field, currently always `1`:
+//!
+//! ```ron
+//! (
+//!     version: 1,
+//!     name: "Twin Lakes",
+//!     author: "Somebody",
+//!     recommended_players: 2,
+//!     ground: [
+//!         "OOOOO",
+//!         "OGRGO",
+//!         // ...
+//!     ],
+//!     sky: [
+//!         "CCWCC",
+//!         "CWSWC",
+//!         // ...
+//!     ],
+//!     spawns: [(1, 1), (3, 3)],
+//!     structures: [
+//!         GenomeFacility(position: (2, 1), layer: 0, genome: "Whale"),
+//!         // ...
+//!     ],
+//! )
+//! ```
+//!
+//! Legacy map files are three blocks of lines, all of the same length: the ground layer, then the
+//! sky layer, then the structures standing on the ground, either `_` for nothing or `g` for a
+//! genome facility whose genome is chosen at random.
+//!
+//! Both formats write tiles with the characters understood by [`TileType`]'s `TryFrom<char>`, and
+//! must place exactly [`NUM_FACILITIES`] genome facilities.
+
+use std::{collections::HashSet, path::Path};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{Facility, MapLayout, MapMetadata, NUM_FACILITIES};
+use crate::{
+    behavior::AxialCoordinates,
+    components::genome::unique_genome,
+    protocol::game_sync::map_sync::{TileType, MAP_HEIGHT},
+    resources::MapConfig,
+};
+
+/// The newest version of the map file format
+pub const MAP_FORMAT_VERSION: u32 = 1;
+
+/// Everything that can be wrong with a map file. Lines, rows, and columns count from one.
+#[derive(Debug, Error)]
+pub enum MapLoadError {
+    #[error("could not read the map file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("the map file is empty")]
+    Empty,
+    #[error("could not parse the map file: {0}")]
+    Ron(#[from] ron::Error),
+    #[error("the map file is version {found}, but only version {supported} is supported")]
+    UnsupportedVersion { found: u32, supported: u32 },
+
+    #[error("line {line} is {found} characters long, but the first line is {expected}")]
+    UnevenLines {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("the map has {0} lines, which is not divisible by three")]
+    LineCountNotDivisibleByThree(usize),
+    #[error("line {line}, column {column}: '{character}' is not a tile")]
+    UnrecognizedTile {
+        line: usize,
+        column: usize,
+        character: char,
+    },
+    #[error(
+        "line {line}, column {column}: '{character}' is not a structure -- use '_' for none or \
+         'g' for a genome facility"
+    )]
+    UnrecognizedStructure {
+        line: usize,
+        column: usize,
+        character: char,
+    },
+
+    #[error("the {layer} layer has no rows")]
+    EmptyLayer { layer: &'static str },
+    #[error(
+        "row {row} of the {layer} layer is {found} tiles long, but the first row is {expected}"
+    )]
+    UnevenRows {
+        layer: &'static str,
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error(
+        "the sky layer is {sky_width}x{sky_height} tiles, but the ground layer is \
+         {ground_width}x{ground_height}"
+    )]
+    LayerSizeMismatch {
+        ground_width: usize,
+        ground_height: usize,
+        sky_width: usize,
+        sky_height: usize,
+    },
+    #[error("row {row}, column {column} of the {layer} layer: '{character}' is not a tile")]
+    UnrecognizedLayerTile {
+        layer: &'static str,
+        row: usize,
+        column: usize,
+        character: char,
+    },
+    #[error("({q}, {r}) on layer {layer} is not on the map")]
+    OutOfBounds { q: i32, r: i32, layer: i32 },
+    #[error("more than one structure stands on ({q}, {r}) on layer {layer}")]
+    OverlappingStructures { q: i32, r: i32, layer: i32 },
+    #[error("more than one spawn point is at ({q}, {r})")]
+    DuplicateSpawn { q: i32, r: i32 },
+    #[error("\"{0}\" is not the name of a genome found in genome facilities")]
+    UnknownGenome(String),
+    #[error("the {0} genome is held by more than one genome facility")]
+    DuplicateGenome(String),
+
+    #[error("the map is {width}x{height} tiles, larger than the most supported")]
+    TooLarge { width: usize, height: usize },
+    #[error("the map must have exactly {expected} genome facilities, but has {found}")]
+    FacilityCount { expected: usize, found: usize },
+}
+
+/// Reads and parses the map file at `path`
+pub fn load(path: &Path) -> Result<MapLayout, MapLoadError> {
+    parse(&std::fs::read_to_string(path)?)
+}
+
+/// Parses the contents of a map file in either format
+pub fn parse(source: &str) -> Result<MapLayout, MapLoadError> {
+    if source.trim_start().starts_with('(') {
+        parse_versioned(source)
+    } else {
+        parse_legacy(source)
+    }
+}
+
+/// Just enough of a versioned map file to know how to read the rest of it
+#[derive(Deserialize)]
+struct VersionHeader {
+    version: u32,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(super) struct MapFileV1 {
+    pub version: u32,
+
+    pub name: String,
+    pub author: String,
+    pub recommended_players: u8,
+
+    pub ground: Vec<String>,
+    pub sky: Vec<String>,
+
+    #[serde(default)]
+    pub spawns: Vec<(i32, i32)>,
+    #[serde(default)]
+    pub structures: Vec<StructureV1>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(super) enum StructureV1 {
+    GenomeFacility {
+        position: (i32, i32),
+        layer: i32,
+        genome: String,
+    },
+}
+
+/// Parses a versioned RON map file
+pub fn parse_versioned(source: &str) -> Result<MapLayout, MapLoadError> {
+    let VersionHeader { version } = ron::from_str(source)?;
+    if version != MAP_FORMAT_VERSION {
+        return Err(MapLoadError::UnsupportedVersion {
+            found: version,
+            supported: MAP_FORMAT_VERSION,
+        });
+    }
+
+    let file: MapFileV1 = ron::from_str(source)?;
+
+    let (width, height) = layer_size(&file.ground, "ground")?;
+    let (sky_width, sky_height) = layer_size(&file.sky, "sky")?;
+    if (sky_width, sky_height) != (width, height) {
+        return Err(MapLoadError::LayerSizeMismatch {
+            ground_width: width,
+            ground_height: height,
+            sky_width,
+            sky_height,
+        });
+    }
+
+    let mut layout = MapLayout::filled(map_config(width, height)?, TileType::Fog);
+    layout.metadata = Some(MapMetadata {
+        name: file.name,
+        author: file.author,
+        recommended_players: file.recommended_players,
+    });
+
+    for (layer, (name, rows)) in [("ground", &file.ground), ("sky", &file.sky)]
+        .into_iter()
+        .enumerate()
+    {
+        for (r, row) in rows.iter().enumerate() {
+            for (q, character) in row.chars().enumerate() {
+                let tile = TileType::try_from(character).map_err(|_| {
+                    MapLoadError::UnrecognizedLayerTile {
+                        layer: name,
+                        row: r + 1,
+                        column: q + 1,
+                        character,
+                    }
+                })?;
+
+                layout.set_tile(
+                    AxialCoordinates::new(q as i32, r as i32),
+                    layer as i32,
+                    tile,
+                );
+            }
+        }
+    }
+
+    for structure in file.structures {
+        match structure {
+            StructureV1::GenomeFacility {
+                position: (q, r),
+                layer,
+                genome,
+            } => {
+                let genome =
+                    unique_genome(&genome).ok_or(MapLoadError::UnknownGenome(genome.clone()))?;
+
+                layout.facilities.push(Facility {
+                    position: AxialCoordinates::new(q, r),
+                    layer,
+                    genome: Some(genome),
+                });
+            }
+        }
+    }
+
+    layout.spawns = file
+        .spawns
+        .into_iter()
+        .map(|(q, r)| AxialCoordinates::new(q, r))
+        .collect();
+
+    validate(&layout)?;
+
+    Ok(layout)
+}
+
+/// The width and height of a layer given as rows of tile characters
+fn layer_size(rows: &[String], layer: &'static str) -> Result<(usize, usize), MapLoadError> {
+    let width = rows
+        .first()
+        .map(|row| row.chars().count())
+        .ok_or(MapLoadError::EmptyLayer { layer })?;
+
+    for (index, row) in rows.iter().enumerate() {
+        let found = row.chars().count();
+        if found != width {
+            return Err(MapLoadError::UnevenRows {
+                layer,
+                row: index + 1,
+                expected: width,
+                found,
+            });
+        }
+    }
+
+    Ok((width, rows.len()))
+}
+
+fn map_config(width: usize, height: usize) -> Result<MapConfig, MapLoadError> {
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(MapLoadError::TooLarge { width, height });
+    }
+
+    Ok(MapConfig {
+        size_width: width as u16,
+        size_height: height as u16,
+    })
+}
+
+fn check_bounds(
+    config: &MapConfig,
+    position: AxialCoordinates,
+    layer: i32,
+) -> Result<(), MapLoadError> {
+    if position.is_in_bounds(config) && (0..MAP_HEIGHT).contains(&layer) {
+        Ok(())
+    } else {
+        Err(MapLoadError::OutOfBounds {
+            q: position.column_q,
+            r: position.row_r,
+            layer,
+        })
+    }
+}
+
+/// Checks everything placed on a map against the rules every map must follow, whichever format it
+/// came from. Genome facilities and spawn points must be on the map without overlapping, no genome
+/// may be held by two facilities, and there must be exactly [`NUM_FACILITIES`] facilities.
+pub fn validate(layout: &MapLayout) -> Result<(), MapLoadError> {
+    let mut placed = HashSet::new();
+    let mut genomes = HashSet::new();
+
+    for facility in &layout.facilities {
+        let AxialCoordinates {
+            column_q: q,
+            row_r: r,
+        } = facility.position;
+        let layer = facility.layer;
+
+        check_bounds(&layout.config, facility.position, layer)?;
+
+        if !placed.insert((facility.position, layer)) {
+            return Err(MapLoadError::OverlappingStructures { q, r, layer });
+        }
+
+        if let Some(genome) = &facility.genome {
+            if !genomes.insert(&genome.name) {
+                return Err(MapLoadError::DuplicateGenome(genome.name.clone()));
+            }
+        }
+    }
+
+    let mut spawns = HashSet::new();
+    for spawn in &layout.spawns {
+        check_bounds(&layout.config, *spawn, 0)?;
+
+        if !spawns.insert(*spawn) {
+            return Err(MapLoadError::DuplicateSpawn {
+                q: spawn.column_q,
+                r: spawn.row_r,
+            });
+        }
+    }
+
+    if layout.facilities.len() != NUM_FACILITIES {
+        return Err(MapLoadError::FacilityCount {
+            expected: NUM_FACILITIES,
+            found: layout.facilities.len(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Parses a legacy map file made of three blocks of characters
+pub fn parse_legacy(source: &str) -> Result<MapLayout, MapLoadError> {
+    let mut lines: Vec<&str> = source.lines().collect();
+
+    // Blank lines at the very end are only trailing newlines, not a row of the map
+    while lines.last().map(|line| line.is_empty()).unwrap_or(false) {
+        lines.pop();
+    }
+
+    if lines.is_empty() {
+        return Err(MapLoadError::Empty);
+    }
+
+    let width = lines[0].chars().count();
+    for (index, line) in lines.iter().enumerate() {
+        let found = line.chars().count();
+        if found != width {
+            return Err(MapLoadError::UnevenLines {
+                line: index + 1,
+                expected: width,
+                found,
+            });
+        }
+    }
+
+    if lines.len() % 3 != 0 {
+        return Err(MapLoadError::LineCountNotDivisibleByThree(lines.len()));
+    }
+
+    let height = lines.len() / 3;
+    let mut layout = MapLayout::filled(map_config(width, height)?, TileType::Fog);
+
+    // The first two blocks of lines are the ground and sky layers, in that order
+    for (index, line) in lines[..height * 2].iter().enumerate() {
+        let layer = (index / height) as i32;
+        let r = (index % height) as i32;
+
+        for (q, character) in line.chars().enumerate() {
+            let tile =
+                TileType::try_from(character).map_err(|_| MapLoadError::UnrecognizedTile {
+                    line: index + 1,
+                    column: q + 1,
+                    character,
+                })?;
+
+            layout.set_tile(AxialCoordinates::new(q as i32, r), layer, tile);
+        }
+    }
+
+    // Then the structures on the ground layer
+    for (index, line) in lines[height * 2..].iter().enumerate() {
+        for (q, character) in line.chars().enumerate() {
+            match character {
+                '_' => {}
+                'g' => layout.facilities.push(Facility {
+                    position: AxialCoordinates::new(q as i32, index as i32),
+                    layer: 0,
+                    genome: None,
+                }),
+                _ => {
+                    return Err(MapLoadError::UnrecognizedStructure {
+                        line: height * 2 + index + 1,
+                        column: q + 1,
+                        character,
+                    })
+                }
+            }
+        }
+    }
+
+    validate(&layout)?;
+
+    Ok(layout)
+}
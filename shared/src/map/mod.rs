@@ -0,0 +1,132 @@
+//! Maps as plain data, before any of their tiles have been spawned as entities. Whether a map was
+//! generated or loaded, the server builds the game from a [`MapLayout`].
+
+use crate::{
+    behavior::AxialCoordinates,
+    components::genome::AnimalType,
+    protocol::game_sync::map_sync::{tile_qrz_to_index, TileType, MAP_HEIGHT},
+    resources::MapConfig,
+};
+
+pub mod generate;
+pub mod load;
+pub mod save;
+
+/// The number of genome facilities on every map
+pub const NUM_FACILITIES: usize = 8;
+
+/// Information about a hand-made map that isn't needed to play on it
+#[derive(Clone, Debug)]
+pub struct MapMetadata {
+    pub name: String,
+    pub author: String,
+    /// The number of players the map was designed for
+    pub recommended_players: u8,
+}
+
+impl Default for MapMetadata {
+    fn default() -> Self {
+        MapMetadata {
+            name: "Untitled".to_owned(),
+            author: String::new(),
+            recommended_players: 2,
+        }
+    }
+}
+
+/// A genome facility placed on the map
+#[derive(Clone, Debug)]
+pub struct Facility {
+    pub position: AxialCoordinates,
+    pub layer: i32,
+    /// The genome the facility holds, or [`None`] to give it one of the genomes not already fixed
+    /// to another facility at random
+    pub genome: Option<AnimalType>,
+}
+
+/// The terrain of every tile on a map and what stands on it
+#[derive(Clone, Debug)]
+pub struct MapLayout {
+    pub config: MapConfig,
+    /// Only maps loaded from versioned map files have metadata
+    pub metadata: Option<MapMetadata>,
+    /// Every tile on every layer, in the order given by [`tile_qrz_to_index`]
+    pub tiles: Vec<TileType>,
+    pub facilities: Vec<Facility>,
+    /// Hexes on the ground layer where players' first units may start. When empty, the server
+    /// chooses starting positions itself.
+    pub spawns: Vec<AxialCoordinates>,
+}
+
+impl MapLayout {
+    /// A map of the given size with every tile on every layer set to `tile`
+    pub fn filled(config: MapConfig, tile: TileType) -> MapLayout {
+        MapLayout {
+            config,
+            metadata: None,
+            tiles: vec![
+                tile;
+                config.size_width as usize
+                    * config.size_height as usize
+                    * MAP_HEIGHT as usize
+            ],
+            facilities: Vec::new(),
+            spawns: Vec::new(),
+        }
+    }
+
+    pub fn tile(&self, qr: AxialCoordinates, layer: i32) -> TileType {
+        self.tiles[tile_qrz_to_index(&self.config, qr.column_q, qr.row_r, layer)]
+    }
+
+    pub fn set_tile(&mut self, qr: AxialCoordinates, layer: i32, tile: TileType) {
+        self.tiles[tile_qrz_to_index(&self.config, qr.column_q, qr.row_r, layer)] = tile;
+    }
+
+    /// The genome facility at the given position, if there is one
+    pub fn facility(&self, qr: AxialCoordinates, layer: i32) -> Option<&Facility> {
+        self.facilities
+            .iter()
+            .find(|facility| facility.position == qr && facility.layer == layer)
+    }
+
+    /// A copy of the map at a new size. Tiles and structures beyond the new size are dropped, and
+    /// tiles that are new to the map are filled with `ground` on the ground layer and `sky` above it.
+    pub fn resized(&self, config: MapConfig, ground: TileType, sky: TileType) -> MapLayout {
+        let mut resized = MapLayout::filled(config, ground);
+        resized.metadata = self.metadata.clone();
+
+        for hex in resized.hexes().collect::<Vec<_>>() {
+            resized.set_tile(hex, 1, sky);
+
+            if hex.is_in_bounds(&self.config) {
+                for layer in 0..MAP_HEIGHT {
+                    resized.set_tile(hex, layer, self.tile(hex, layer));
+                }
+            }
+        }
+
+        resized.facilities = self
+            .facilities
+            .iter()
+            .filter(|facility| facility.position.is_in_bounds(&config))
+            .cloned()
+            .collect();
+        resized.spawns = self
+            .spawns
+            .iter()
+            .copied()
+            .filter(|spawn| spawn.is_in_bounds(&config))
+            .collect();
+
+        resized
+    }
+
+    /// Every hex on a single layer of the map in row-major order
+    pub fn hexes(&self) -> impl Iterator<Item = AxialCoordinates> {
+        let width = self.config.size_width as i32;
+        let height = self.config.size_height as i32;
+
+        (0..height).flat_map(move |r| (0..width).map(move |q| AxialCoordinates::new(q, r)))
+    }
+}
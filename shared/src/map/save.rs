@@ -0,0 +1,97 @@
+//! Writing maps out as versioned map files, the format described in [`load`](super::load).
+
+use std::path::Path;
+
+use ron::ser::PrettyConfig;
+use thiserror::Error;
+
+use super::{
+    load::{validate, MapFileV1, MapLoadError, StructureV1, MAP_FORMAT_VERSION},
+    MapLayout,
+};
+use crate::behavior::AxialCoordinates;
+
+#[derive(Debug, Error)]
+pub enum MapSaveError {
+    #[error("the map is not valid: {0}")]
+    Invalid(#[from] MapLoadError),
+    #[error("({q}, {r}) on layer {layer} is fog, which can't be saved")]
+    FogTile { q: i32, r: i32, layer: i32 },
+    #[error("the genome facility at ({q}, {r}) on layer {layer} has no genome")]
+    UnassignedGenome { q: i32, r: i32, layer: i32 },
+    #[error("could not write the map: {0}")]
+    Ron(#[from] ron::Error),
+    #[error("could not write the map file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Writes `layout` to the file at `path`
+pub fn save(layout: &MapLayout, path: &Path) -> Result<(), MapSaveError> {
+    std::fs::write(path, to_string(layout)?)?;
+
+    Ok(())
+}
+
+/// The contents of a versioned map file holding `layout`. Maps must pass the same checks they
+/// would when loaded, and every genome facility must have a genome.
+pub fn to_string(layout: &MapLayout) -> Result<String, MapSaveError> {
+    validate(layout)?;
+
+    let metadata = layout.metadata.clone().unwrap_or_default();
+
+    let rows = |layer: i32| -> Result<Vec<String>, MapSaveError> {
+        (0..layout.config.size_height as i32)
+            .map(|r| {
+                (0..layout.config.size_width as i32)
+                    .map(|q| {
+                        layout
+                            .tile(AxialCoordinates::new(q, r), layer)
+                            .to_char()
+                            .ok_or(MapSaveError::FogTile { q, r, layer })
+                    })
+                    .collect()
+            })
+            .collect()
+    };
+
+    let structures = layout
+        .facilities
+        .iter()
+        .map(|facility| {
+            let (q, r) = (facility.position.column_q, facility.position.row_r);
+
+            match &facility.genome {
+                Some(genome) => Ok(StructureV1::GenomeFacility {
+                    position: (q, r),
+                    layer: facility.layer,
+                    genome: genome.name.clone(),
+                }),
+                None => Err(MapSaveError::UnassignedGenome {
+                    q,
+                    r,
+                    layer: facility.layer,
+                }),
+            }
+        })
+        .collect::<Result<Vec<StructureV1>, MapSaveError>>()?;
+
+    let file = MapFileV1 {
+        version: MAP_FORMAT_VERSION,
+
+        name: metadata.name,
+        author: metadata.author,
+        recommended_players: metadata.recommended_players,
+
+        ground: rows(0)?,
+        sky: rows(1)?,
+
+        spawns: layout
+            .spawns
+            .iter()
+            .map(|spawn| (spawn.column_q, spawn.row_r))
+            .collect(),
+        structures,
+    };
+
+    Ok(ron::ser::to_string_pretty(&file, PrettyConfig::new())?)
+}
@@ -0,0 +1,302 @@
+//! Replicating the map a chunk at a time. Each player's perception of the map is split into square
+//! chunks of [`CHUNK_SIZE`] hexes covering both layers, so a map needs a few hundred replicated
+//! entities per player rather than one for every tile. Tile types are packed two to a byte and
+//! only the tiles with a structure on them carry one, so a chunk stays small enough to resend
+//! whenever anything in it changes.
+//!
+//! A tile is in one of three states for the player. Tiles they have never seen are
+//! [`TileType::Fog`]. Tiles they can see show what is really there. Tiles they have seen before but
+//! can't see now are remembered: they keep the terrain and facilities the player last saw, but
+//! nothing being built on them.
+
+use bevy::prelude::Component;
+use naia_shared::{derive_serde, serde, Property, Replicate};
+
+use super::map_sync::{TileStructure, TileType, MAP_HEIGHT};
+use crate::{behavior::AxialCoordinates, resources::MapConfig};
+
+/// The width and height of a chunk in hexes. Chunks on the far edges of a map may be smaller.
+pub const CHUNK_SIZE: u16 = 8;
+
+/// The hexes a chunk covers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkBounds {
+    /// The hex in the chunk with the lowest `q` and `r`
+    pub origin: AxialCoordinates,
+    pub columns: u8,
+    pub rows: u8,
+}
+
+impl ChunkBounds {
+    /// Every chunk of a map in row-major order, the order given by [`ChunkBounds::index_of`]
+    pub fn all(config: &MapConfig) -> Vec<ChunkBounds> {
+        let mut chunks = Vec::new();
+
+        for r in (0..config.size_height).step_by(CHUNK_SIZE as usize) {
+            for q in (0..config.size_width).step_by(CHUNK_SIZE as usize) {
+                chunks.push(ChunkBounds {
+                    origin: AxialCoordinates::new(q as i32, r as i32),
+                    columns: CHUNK_SIZE.min(config.size_width - q) as u8,
+                    rows: CHUNK_SIZE.min(config.size_height - r) as u8,
+                });
+            }
+        }
+
+        chunks
+    }
+
+    /// The index into [`ChunkBounds::all`] of the chunk holding the given hex
+    pub fn index_of(config: &MapConfig, qr: AxialCoordinates) -> usize {
+        let chunks_wide = (config.size_width + CHUNK_SIZE - 1) / CHUNK_SIZE;
+
+        (qr.row_r as usize / CHUNK_SIZE as usize) * chunks_wide as usize
+            + qr.column_q as usize / CHUNK_SIZE as usize
+    }
+
+    /// The number of tiles in the chunk over every layer
+    pub fn tile_count(&self) -> usize {
+        self.columns as usize * self.rows as usize * MAP_HEIGHT as usize
+    }
+
+    pub fn contains(&self, qr: AxialCoordinates) -> bool {
+        let q = qr.column_q - self.origin.column_q;
+        let r = qr.row_r - self.origin.row_r;
+
+        (0..self.columns as i32).contains(&q) && (0..self.rows as i32).contains(&r)
+    }
+
+    /// Every hex in the chunk in row-major order
+    pub fn hexes(&self) -> impl Iterator<Item = AxialCoordinates> {
+        let origin = self.origin;
+        let columns = self.columns as i32;
+        let rows = self.rows as i32;
+
+        (0..rows).flat_map(move |r| {
+            (0..columns).map(move |q| AxialCoordinates::new(origin.column_q + q, origin.row_r + r))
+        })
+    }
+
+    /// Where a tile of the chunk is kept in its list of tiles, ordered by layer, then row, then
+    /// column like the whole map is
+    pub fn local_index(&self, qr: AxialCoordinates, layer: i32) -> usize {
+        let q = (qr.column_q - self.origin.column_q) as usize;
+        let r = (qr.row_r - self.origin.row_r) as usize;
+        let columns = self.columns as usize;
+
+        layer as usize * columns * self.rows as usize + r * columns + q
+    }
+
+    /// Collects the contents of every tile in the chunk from `tile_at`, ready to be put into a
+    /// [`MapChunkSync`]
+    pub fn gather(
+        &self,
+        mut tile_at: impl FnMut(AxialCoordinates, i32) -> (TileType, TileStructure),
+    ) -> (Vec<TileType>, Vec<ChunkStructure>) {
+        let mut tiles = vec![TileType::Fog; self.tile_count()];
+        let mut structures = Vec::new();
+
+        for layer in 0..MAP_HEIGHT {
+            for hex in self.hexes() {
+                let index = self.local_index(hex, layer);
+                let (tile, structure) = tile_at(hex, layer);
+
+                tiles[index] = tile;
+                if structure != TileStructure::None {
+                    structures.push(ChunkStructure {
+                        index: index as u16,
+                        structure,
+                    });
+                }
+            }
+        }
+
+        (tiles, structures)
+    }
+}
+
+/// A structure standing on one of a chunk's tiles
+#[derive(Debug)]
+#[derive_serde]
+pub struct ChunkStructure {
+    /// The tile's [`ChunkBounds::local_index`]
+    pub index: u16,
+    pub structure: TileStructure,
+}
+
+#[derive(Component, Replicate)]
+#[protocol_path = "crate::protocol::Protocol"]
+/// The synchronization of a chunk of the map as a player perceives it
+pub struct MapChunkSync {
+    pub origin: Property<AxialCoordinates>,
+    pub columns: Property<u8>,
+    pub rows: Property<u8>,
+    /// The chunk's tile types in [`ChunkBounds::local_index`] order, packed two to a byte
+    pub tiles: Property<Vec<u8>>,
+    /// Only the tiles that have a structure, in [`ChunkBounds::local_index`] order
+    pub structures: Property<Vec<ChunkStructure>>,
+    /// Which tiles are remembered rather than currently visible, a bit for each tile in
+    /// [`ChunkBounds::local_index`] order
+    pub remembered: Property<Vec<u8>>,
+}
+
+impl MapChunkSync {
+    pub fn new_chunk(
+        bounds: ChunkBounds,
+        tiles: &[TileType],
+        structures: Vec<ChunkStructure>,
+    ) -> MapChunkSync {
+        MapChunkSync::new_complete(
+            bounds.origin,
+            bounds.columns,
+            bounds.rows,
+            pack(tiles),
+            structures,
+            vec![0; (bounds.tile_count() + 7) / 8],
+        )
+    }
+
+    pub fn bounds(&self) -> ChunkBounds {
+        ChunkBounds {
+            origin: *self.origin,
+            columns: *self.columns,
+            rows: *self.rows,
+        }
+    }
+
+    /// Shows what is on a tile the player can see. Properties are only written when they change,
+    /// so that chunks nothing happened in aren't sent again.
+    pub fn set_tile(
+        &mut self,
+        qr: AxialCoordinates,
+        layer: i32,
+        tile: TileType,
+        structure: TileStructure,
+    ) {
+        let index = self.bounds().local_index(qr, layer);
+        self.set_remembered(index, false);
+
+        if unpack(&self.tiles, index) != tile {
+            let byte = &mut self.tiles[index / 2];
+            *byte = if index % 2 == 0 {
+                (*byte & 0xf0) | u8::from(tile)
+            } else {
+                (*byte & 0x0f) | (u8::from(tile) << 4)
+            };
+        }
+
+        let index = index as u16;
+        let existing = self
+            .structures
+            .iter()
+            .position(|structure| structure.index == index);
+
+        match (existing, structure) {
+            (Some(position), TileStructure::None) => {
+                self.structures.remove(position);
+            }
+            (Some(position), structure) => {
+                if self.structures[position].structure != structure {
+                    self.structures[position].structure = structure;
+                }
+            }
+            (None, TileStructure::None) => {}
+            (None, structure) => {
+                // Keep the structures in the same order as the tiles
+                let position = self
+                    .structures
+                    .iter()
+                    .position(|structure| structure.index > index)
+                    .unwrap_or(self.structures.len());
+
+                self.structures
+                    .insert(position, ChunkStructure { index, structure });
+            }
+        }
+    }
+
+    /// Keeps what the player last saw on a tile that just went out of view, hiding anything being
+    /// built there. Tiles the player never saw stay fog.
+    pub fn remember_tile(&mut self, qr: AxialCoordinates, layer: i32) {
+        let tile = self.tile(qr, layer);
+        if tile == TileType::Fog {
+            return;
+        }
+
+        let structure = match self.structure(qr, layer) {
+            TileStructure::GenomeFacility { unique_genome, .. } => TileStructure::GenomeFacility {
+                unique_genome,
+                building: None,
+            },
+            TileStructure::None => TileStructure::None,
+        };
+        self.set_tile(qr, layer, tile, structure);
+
+        let index = self.bounds().local_index(qr, layer);
+        self.set_remembered(index, true);
+    }
+
+    /// Whether the player has seen the tile on the given hex and layer before but can't see it now
+    pub fn is_remembered(&self, qr: AxialCoordinates, layer: i32) -> bool {
+        let index = self.bounds().local_index(qr, layer);
+
+        self.remembered
+            .get(index / 8)
+            .map(|byte| byte & (1 << (index % 8)) != 0)
+            .unwrap_or(false)
+    }
+
+    /// Whether the player can currently see the tile on the given hex and layer
+    pub fn is_visible(&self, qr: AxialCoordinates, layer: i32) -> bool {
+        self.tile(qr, layer) != TileType::Fog && !self.is_remembered(qr, layer)
+    }
+
+    fn set_remembered(&mut self, index: usize, remembered: bool) {
+        let bit = 1 << (index % 8);
+        let byte = self.remembered[index / 8];
+        let changed = if remembered { byte | bit } else { byte & !bit };
+
+        if changed != byte {
+            self.remembered[index / 8] = changed;
+        }
+    }
+
+    /// The type of the tile on the given hex and layer, which must be in the chunk
+    pub fn tile(&self, qr: AxialCoordinates, layer: i32) -> TileType {
+        unpack(&self.tiles, self.bounds().local_index(qr, layer))
+    }
+
+    /// The structure on the tile on the given hex and layer, which must be in the chunk
+    pub fn structure(&self, qr: AxialCoordinates, layer: i32) -> TileStructure {
+        let index = self.bounds().local_index(qr, layer) as u16;
+
+        self.structures
+            .iter()
+            .find(|structure| structure.index == index)
+            .map(|structure| structure.structure.clone())
+            .unwrap_or(TileStructure::None)
+    }
+}
+
+/// Packs tile types into the low and high halves of each byte
+fn pack(tiles: &[TileType]) -> Vec<u8> {
+    tiles
+        .chunks(2)
+        .map(|pair| {
+            let low = u8::from(pair[0]);
+            let high = pair.get(1).map(|tile| u8::from(*tile)).unwrap_or(0);
+
+            low | (high << 4)
+        })
+        .collect()
+}
+
+fn unpack(packed: &[u8], index: usize) -> TileType {
+    let byte = packed.get(index / 2).copied().unwrap_or(0);
+    let nibble = if index % 2 == 0 {
+        byte & 0x0f
+    } else {
+        byte >> 4
+    };
+
+    TileType::try_from(nibble).unwrap_or(TileType::Fog)
+}
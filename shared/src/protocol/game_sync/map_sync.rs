@@ -6,7 +6,7 @@ use thiserror::Error;
 
 use crate::{
     behavior::AxialCoordinates,
-    components::genome::{AnimalType, Hybrid},
+    components::genome::{AnimalType, Hybrid, TerrainType},
     protocol::notifications::WhoseTurn,
     resources::MapConfig,
 };
@@ -53,6 +53,18 @@ impl TileType {
             TileType::StormySky => format!("Stormy Skies"),
         }
     }
+
+    /// The [`TerrainType`] a creature needs to be able to traverse to cross this tile, or [`None`]
+    /// if nothing is known about the tile
+    pub fn terrain_type(&self) -> Option<TerrainType> {
+        match self {
+            TileType::Fog => None,
+
+            TileType::Grass | TileType::Forest | TileType::Desert => Some(TerrainType::Ground),
+            TileType::Ocean | TileType::River | TileType::DesertOasis => Some(TerrainType::Water),
+            TileType::ClearSky | TileType::WindySky | TileType::StormySky => Some(TerrainType::Air),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -127,6 +139,28 @@ impl TryFrom<char> for TileType {
     }
 }
 
+impl TileType {
+    /// The character standing for this tile in map files, the reverse of `TryFrom<char>`. Fog has
+    /// no character since maps can't be saved with any.
+    pub fn to_char(&self) -> Option<char> {
+        match self {
+            TileType::Fog => None,
+
+            TileType::Grass => Some('G'),
+            TileType::Forest => Some('F'),
+            TileType::Desert => Some('D'),
+
+            TileType::Ocean => Some('O'),
+            TileType::River => Some('R'),
+            TileType::DesertOasis => Some('o'),
+
+            TileType::ClearSky => Some('C'),
+            TileType::WindySky => Some('W'),
+            TileType::StormySky => Some('S'),
+        }
+    }
+}
+
 impl From<TileType> for Color {
     fn from(ty: TileType) -> Self {
         match ty {
@@ -194,7 +228,9 @@ pub fn index_to_tile_qrz(map_conf: &MapConfig, index: usize) -> (i32, i32, i32)
 
 #[derive(Component, Replicate)]
 #[protocol_path = "crate::protocol::Protocol"]
-/// The synchronization of a tile on the map
+/// A single tile of the map. The server's authoritative map and the client's map are made of these,
+/// while players' perceptions of the map are sent in chunks by
+/// [`MapChunkSync`](super::map_chunk_sync::MapChunkSync)
 pub struct MapSync {
     pub position: Property<AxialCoordinates>,
     pub layer: Property<i32>,
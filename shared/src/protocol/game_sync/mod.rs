@@ -1,2 +1,3 @@
+pub mod map_chunk_sync;
 pub mod map_sync;
 pub mod unit_sync;
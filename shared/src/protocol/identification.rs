@@ -5,5 +5,10 @@ use naia_shared::{Property, Replicate};
 #[protocol_path = "crate::protocol::Protocol"]
 pub struct Identification {
     pub username: Property<String>,
+    /// The match to join, which is started if nobody is playing in a room of that name yet
+    pub room_name: Property<String>,
     pub room_password: Property<String>,
+    /// The [`super::ReconnectToken`] handed out when the game started, when taking back a seat in
+    /// a game already underway
+    pub reconnect_token: Property<Option<u64>>,
 }
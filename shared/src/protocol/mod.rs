@@ -15,14 +15,19 @@ pub use waiting_on_players::WaitingOnPlayers;
 pub mod countdown;
 pub use countdown::Countdown;
 
+pub mod reconnect_token;
+pub use reconnect_token::ReconnectToken;
+
 pub mod notifications;
 pub use notifications::{
-    client_connected::ClientConnected, game_start::GameStartNotification,
-    genome_status_change::GenomeStatusChange, turn_change::TurnChangeNotification,
+    client_connected::ClientConnected, combat_result::CombatResultNotification,
+    game_over::GameOverNotification, game_start::GameStartNotification,
+    genome_status_change::GenomeStatusChange, rejoin::RejoinNotification,
+    turn_change::TurnChangeNotification, turn_clock::TurnClockNotification,
 };
 
 pub mod game_sync;
-pub use game_sync::{map_sync::MapSync, unit_sync::UnitSync};
+pub use game_sync::{map_chunk_sync::MapChunkSync, map_sync::MapSync, unit_sync::UnitSync};
 
 pub mod chat;
 pub use chat::{receive_chat::ReceiveChat, send_chat::SendChat};
@@ -37,13 +42,19 @@ pub enum Protocol {
     WaitingOnPlayers(WaitingOnPlayers),
     ClientConnected(ClientConnected),
     Countdown(Countdown),
+    ReconnectToken(ReconnectToken),
 
     ReceiveChat(ReceiveChat),
 
     GameStartNotification(GameStartNotification),
+    RejoinNotification(RejoinNotification),
     GenomeStatusChange(GenomeStatusChange),
     TurnChangeNotification(TurnChangeNotification),
+    TurnClockNotification(TurnClockNotification),
+    CombatResultNotification(CombatResultNotification),
+    GameOverNotification(GameOverNotification),
 
     MapSync(MapSync),
+    MapChunkSync(MapChunkSync),
     UnitSync(UnitSync),
 }
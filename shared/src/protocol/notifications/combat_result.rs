@@ -0,0 +1,36 @@
+use bevy::prelude::Component;
+use naia_shared::{derive_serde, serde, Property, Replicate};
+
+use crate::{behavior::AxialCoordinates, components::players::PlayerId};
+
+/// One side of a fight as it stood once the fight was over
+#[derive(Debug)]
+#[derive_serde]
+pub struct Combatant {
+    pub owner: PlayerId,
+    pub hybrid_name: String,
+    pub position: AxialCoordinates,
+
+    pub damage_taken: u16,
+    pub health_remaining: u16,
+}
+
+impl Combatant {
+    pub fn died(&self) -> bool {
+        self.health_remaining == 0
+    }
+}
+
+#[derive(Component, Replicate)]
+#[protocol_path = "crate::protocol::Protocol"]
+/// Sent to both players involved whenever one unit attacks another
+pub struct CombatResultNotification {
+    pub attacker: Property<Combatant>,
+    pub defender: Property<Combatant>,
+}
+
+impl CombatResultNotification {
+    pub fn new(attacker: Combatant, defender: Combatant) -> CombatResultNotification {
+        CombatResultNotification::new_complete(attacker, defender)
+    }
+}
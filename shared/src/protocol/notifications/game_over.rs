@@ -0,0 +1,39 @@
+use bevy::prelude::Component;
+use naia_shared::{derive_serde, serde, Property, Replicate};
+
+use crate::components::players::PlayerId;
+
+/// How the game was won
+#[derive(Debug)]
+#[derive_serde]
+pub enum Victory {
+    /// Every other player was eliminated
+    LastPlayerStanding,
+    /// The winner held at least `facilities` genome facilities for `turns` turns in a row
+    HeldFacilities { facilities: u8, turns: u16 },
+}
+
+/// Where a player finished
+#[derive(Debug)]
+#[derive_serde]
+pub struct Standing {
+    pub username: String,
+    pub id: PlayerId,
+    /// The turn the player lost all of their units on, if they did
+    pub eliminated_on_turn: Option<u16>,
+}
+
+#[derive(Component, Replicate)]
+#[protocol_path = "crate::protocol::Protocol"]
+/// Sent to every player once somebody has won
+pub struct GameOverNotification {
+    pub victory: Property<Victory>,
+    /// Every player in the order they placed, starting with the winner
+    pub standings: Property<Vec<Standing>>,
+}
+
+impl GameOverNotification {
+    pub fn new(victory: Victory, standings: Vec<Standing>) -> GameOverNotification {
+        GameOverNotification::new_complete(victory, standings)
+    }
+}
@@ -7,4 +7,6 @@ use super::WhoseTurn;
 #[protocol_path = "crate::protocol::Protocol"]
 pub struct GameStartNotification {
     pub whose_turn: Property<WhoseTurn>,
+    /// The seed the server's randomness was drawn from, so that the game can be replayed
+    pub seed: Property<u64>,
 }
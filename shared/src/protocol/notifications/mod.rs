@@ -3,9 +3,13 @@ use naia_shared::{derive_serde, serde};
 use crate::components::players::PlayerId;
 
 pub mod client_connected;
+pub mod combat_result;
+pub mod game_over;
 pub mod game_start;
 pub mod genome_status_change;
+pub mod rejoin;
 pub mod turn_change;
+pub mod turn_clock;
 
 // TODO: move to components mod (and probably rename components)
 #[derive(Debug)]
@@ -0,0 +1,15 @@
+use bevy::prelude::Component;
+use naia_shared::{Property, Replicate};
+
+use super::WhoseTurn;
+use crate::components::genome::AnimalType;
+
+#[derive(Component, Replicate)]
+#[protocol_path = "crate::protocol::Protocol"]
+/// Sent in place of a [`super::game_start::GameStartNotification`] to a player who reconnected to a
+/// game already being played, with everything they need to carry on that isn't replicated
+pub struct RejoinNotification {
+    pub whose_turn: Property<WhoseTurn>,
+    pub seed: Property<u64>,
+    pub unlocked_genomes: Property<Vec<AnimalType>>,
+}
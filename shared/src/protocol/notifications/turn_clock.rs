@@ -0,0 +1,14 @@
+use bevy::prelude::Component;
+use naia_shared::{Property, Replicate};
+
+#[derive(Component, Replicate)]
+#[protocol_path = "crate::protocol::Protocol"]
+/// How long the player whose turn it is has left before their turn is ended for them. Only sent
+/// when the server has a turn time limit, and again whenever a second passes.
+pub struct TurnClockNotification {
+    /// Whole seconds left of the turn's own time
+    pub turn_secs_left: Property<u32>,
+    /// Whole seconds left in the player's time bank, which is drawn from once the turn's own time
+    /// has run out
+    pub bank_secs_left: Property<u32>,
+}
@@ -14,5 +14,9 @@ pub struct PlayerInput {
 pub enum PlayerInputVariant {
     MoveEntity(AxialCoordinates),
     BuildHybrid(AxialCoordinates, Hybrid),
+    /// Attack the enemy unit on the given adjacent tile with the relevant entity
+    Attack(AxialCoordinates),
+    /// Take off or land the relevant entity, moving it to the given layer of the hex it is on
+    ChangeLayer(i32),
     EndTurn,
 }
@@ -0,0 +1,10 @@
+use bevy::prelude::Component;
+use naia_shared::{Property, Replicate};
+
+#[derive(Component, Replicate)]
+#[protocol_path = "crate::protocol::Protocol"]
+/// Sent to each player when the game starts. Giving it back in [`super::Identification`] along
+/// with the same username lets a player who lost their connection take their seat back.
+pub struct ReconnectToken {
+    pub token: Property<u64>,
+}